@@ -31,7 +31,7 @@ use ethcore::snapshot::SnapshotService;
 use ethcore::header::BlockNumber;
 use sync_io::NetSyncIo;
 use chain::{ChainSync, SyncStatus as EthSyncStatus};
-use std::net::{SocketAddr, AddrParseError};
+use std::net::{SocketAddr, AddrParseError, TcpListener};
 use std::str::FromStr;
 use parking_lot::RwLock;
 use chain::{ETH_PROTOCOL_VERSION_63, ETH_PROTOCOL_VERSION_62,
@@ -522,6 +522,10 @@ pub trait ManageNetwork : Send + Sync {
 	fn stop_network(&self);
 	/// Query the current configuration of the network
 	fn network_config(&self) -> NetworkConfiguration;
+	/// Node URL with the address the network listens on, once it's started
+	fn local_url(&self) -> Option<String>;
+	/// Duplicate of the socket the network listens on, once it's started
+	fn listener(&self) -> Option<TcpListener>;
 	/// Get network context for protocol.
 	fn with_proto_context(&self, proto: ProtocolId, f: &mut FnMut(&NetworkContext));
 }
@@ -565,6 +569,14 @@ impl ManageNetwork for EthSync {
 		NetworkConfiguration::from(self.network.config().clone())
 	}
 
+	fn local_url(&self) -> Option<String> {
+		self.network.local_url()
+	}
+
+	fn listener(&self) -> Option<TcpListener> {
+		self.network.listener()
+	}
+
 	fn with_proto_context(&self, proto: ProtocolId, f: &mut FnMut(&NetworkContext)) {
 		self.network.with_context_eval(proto, f);
 	}
@@ -840,6 +852,14 @@ impl ManageNetwork for LightSync {
 		NetworkConfiguration::from(self.network.config().clone())
 	}
 
+	fn local_url(&self) -> Option<String> {
+		self.network.local_url()
+	}
+
+	fn listener(&self) -> Option<TcpListener> {
+		self.network.listener()
+	}
+
 	fn with_proto_context(&self, proto: ProtocolId, f: &mut FnMut(&NetworkContext)) {
 		self.network.with_context_eval(proto, f);
 	}
@@ -33,10 +33,10 @@ mod rotating;
 use std::{env, thread, fs};
 use std::sync::{Weak, Arc};
 use std::io::Write;
-use env_logger::LogBuilder;
+use env_logger::{LogBuilder, Logger};
 use regex::Regex;
 use ansi_term::Colour;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 
 pub use rotating::{RotatingLogger, init_log};
 
@@ -59,10 +59,87 @@ impl Default for Config {
 
 lazy_static! {
 	static ref ROTATING_LOGGER : Mutex<Weak<RotatingLogger>> = Mutex::new(Default::default());
+	static ref LOG_RELOADER : Mutex<Option<LogReloader>> = Mutex::new(None);
 }
 
 /// Sets up the logger
 pub fn setup_log(config: &Config) -> Result<Arc<RotatingLogger>, String> {
+	let (mut builder, levels) = log_builder(config, None);
+	let logs = Arc::new(RotatingLogger::new(levels));
+	set_format(&mut builder, config, logs.clone())?;
+	let logger = builder.build();
+
+	let mut reloader = None;
+	let result = rlog::set_logger(|max_level| {
+		max_level.set(logger.filter());
+		let inner = Arc::new(Reloadable {
+			config: config.clone(),
+			logs: logs.clone(),
+			logger: RwLock::new(logger),
+			max_level: max_level,
+		});
+		reloader = Some(LogReloader { inner: inner.clone() });
+		Box::new(ReloadableLogger(inner))
+	});
+	result
+		.and_then(|_| {
+			*ROTATING_LOGGER.lock() = Arc::downgrade(&logs);
+			*LOG_RELOADER.lock() = reloader;
+			Ok(logs)
+		})
+		// couldn't create new logger - try to fall back on previous logger.
+		.or_else(|err| match ROTATING_LOGGER.lock().upgrade() {
+			Some(l) => Ok(l),
+			// no previous logger. fatal.
+			None => Err(format!("{:?}", err)),
+		})
+}
+
+/// Returns the handle changing the levels of the logger set up by `setup_log`, if it was.
+pub fn log_reloader() -> Option<LogReloader> {
+	LOG_RELOADER.lock().clone()
+}
+
+/// Handle changing the log levels of a running logger, without touching its output.
+#[derive(Clone)]
+pub struct LogReloader {
+	inner: Arc<Reloadable>,
+}
+
+impl LogReloader {
+	/// Rebuilds the log levels from `RUST_LOG` as it is now and the logger configuration, with
+	/// `levels` like `sync=debug,rpc=trace` applied on top.
+	pub fn reload(&self, levels: Option<&str>) -> Result<(), String> {
+		let (mut builder, _) = log_builder(&self.inner.config, levels);
+		set_format(&mut builder, &self.inner.config, self.inner.logs.clone())?;
+		let logger = builder.build();
+		self.inner.max_level.set(logger.filter());
+		*self.inner.logger.write() = logger;
+		Ok(())
+	}
+}
+
+struct Reloadable {
+	config: Config,
+	logs: Arc<RotatingLogger>,
+	logger: RwLock<Logger>,
+	max_level: rlog::MaxLogLevelFilter,
+}
+
+struct ReloadableLogger(Arc<Reloadable>);
+
+impl rlog::Log for ReloadableLogger {
+	fn enabled(&self, metadata: &rlog::LogMetadata) -> bool {
+		self.0.logger.read().enabled(metadata)
+	}
+
+	fn log(&self, record: &rlog::LogRecord) {
+		self.0.logger.read().log(record)
+	}
+}
+
+/// Builder with the log levels of `config`, and `extra` ones on top, returning the levels it was given too.
+fn log_builder(config: &Config, extra: Option<&str>) -> (LogBuilder, String) {
 	use rlog::*;
 
 	let mut levels = String::new();
@@ -86,10 +163,20 @@ pub fn setup_log(config: &Config) -> Result<Arc<RotatingLogger>, String> {
 		builder.parse(s);
 	}
 
+	if let Some(s) = extra {
+		levels.push_str(",");
+		levels.push_str(s);
+		builder.parse(s);
+	}
+
+	(builder, levels)
+}
+
+fn set_format(builder: &mut LogBuilder, config: &Config, logger: Arc<RotatingLogger>) -> Result<(), String> {
+	use rlog::*;
+
 	let isatty = atty::is(atty::Stream::Stderr);
 	let enable_color = config.color && isatty;
-	let logs = Arc::new(RotatingLogger::new(levels));
-	let logger = logs.clone();
 	let mut open_options = fs::OpenOptions::new();
 
 	let maybe_file = match config.file.as_ref() {
@@ -131,17 +218,7 @@ pub fn setup_log(config: &Config) -> Result<Arc<RotatingLogger>, String> {
     };
 
 	builder.format(format);
-	builder.init()
-		.and_then(|_| {
-			*ROTATING_LOGGER.lock() = Arc::downgrade(&logs);
-			Ok(logs)
-		})
-		// couldn't create new logger - try to fall back on previous logger.
-		.or_else(|err| match ROTATING_LOGGER.lock().upgrade() {
-			Some(l) => Ok(l),
-			// no previous logger. fatal.
-			None => Err(format!("{:?}", err)),
-		})
+	Ok(())
 }
 
 fn kill_color(s: &str) -> String {
@@ -114,6 +114,7 @@ pub extern fn parity_start(cfg: *const ParityParams, output: *mut *mut c_void) -
 			match action {
 				parity::ExecutionAction::Instant(Some(s)) => { println!("{}", s); 0 },
 				parity::ExecutionAction::Instant(None) => 0,
+				parity::ExecutionAction::InstantStructured(s, _) => { println!("{}", s); 0 },
 				parity::ExecutionAction::Running(client) => {
 					*output = Box::into_raw(Box::<parity::RunningClient>::new(client)) as *mut c_void;
 					0
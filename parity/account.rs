@@ -64,7 +64,7 @@ pub struct ImportFromGethAccounts {
 pub fn execute(cmd: AccountCmd) -> Result<String, String> {
 	match cmd {
 		AccountCmd::New(new_cmd) => new(new_cmd),
-		AccountCmd::List(list_cmd) => list(list_cmd),
+		AccountCmd::List(list_cmd) => list(list_cmd).map(|accounts| accounts.join("\n")),
 		AccountCmd::Import(import_cmd) => import(import_cmd),
 		AccountCmd::ImportFromGeth(import_geth_cmd) => import_geth(import_geth_cmd)
 	}
@@ -97,15 +97,15 @@ fn new(n: NewAccount) -> Result<String, String> {
 	Ok(format!("0x{:x}", new_account))
 }
 
-fn list(list_cmd: ListAccounts) -> Result<String, String> {
+/// Lists the addresses of the accounts in the keys directory.
+pub fn list(list_cmd: ListAccounts) -> Result<Vec<String>, String> {
 	let dir = Box::new(keys_dir(list_cmd.path, list_cmd.spec)?);
 	let secret_store = Box::new(secret_store(dir, None)?);
 	let acc_provider = AccountProvider::new(secret_store, AccountProviderSettings::default());
 	let accounts = acc_provider.accounts().map_err(|e| format!("{}", e))?;
 	let result = accounts.into_iter()
 		.map(|a| format!("0x{:x}", a))
-		.collect::<Vec<String>>()
-		.join("\n");
+		.collect();
 
 	Ok(result)
 }
@@ -0,0 +1,171 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Assembly of the node's command line.
+
+use std::fs::File;
+use std::io::Read;
+
+/// Deepest nesting of response files, so one naming itself is caught instead of read forever.
+const MAX_RESPONSE_FILE_DEPTH: usize = 8;
+
+/// Replaces every `@file` argument after the program name with the arguments read from `file`.
+///
+/// Arguments in a response file are separated by whitespace, including newlines, and may name
+/// further response files. Relative paths are taken from the working directory. A missing or
+/// unreadable file is an error, as the node would otherwise run without part of its arguments.
+pub fn expand_response_files(args: Vec<String>) -> Result<Vec<String>, String> {
+	let mut args = args.into_iter();
+	let mut expanded: Vec<_> = args.next().into_iter().collect();
+	expand_into(args, 0, &mut expanded)?;
+	Ok(expanded)
+}
+
+fn expand_into<I>(args: I, depth: usize, expanded: &mut Vec<String>) -> Result<(), String> where I: IntoIterator<Item = String> {
+	for arg in args {
+		if arg.len() < 2 || !arg.starts_with('@') {
+			expanded.push(arg);
+			continue;
+		}
+
+		let path = &arg[1..];
+		if depth == MAX_RESPONSE_FILE_DEPTH {
+			return Err(format!("Response file {} is nested more than {} deep", path, MAX_RESPONSE_FILE_DEPTH));
+		}
+		let mut content = String::new();
+		File::open(path).and_then(|mut f| f.read_to_string(&mut content))
+			.map_err(|e| format!("Couldn't read response file {}: {}", path, e))?;
+		expand_into(content.split_whitespace().map(String::from), depth + 1, expanded)?;
+	}
+	Ok(())
+}
+
+/// Merges `sticky` arguments, kept by the node across a restart, into the user's `args`.
+///
+/// Sticky arguments are given as `--flag=value` or `--flag` and take precedence over the user's:
+/// they reflect the node's state at the time it asked to be restarted. Any occurrence of the same
+/// flag in `args` is dropped, along with its value when it's passed as a separate argument.
+pub fn merge_sticky_args(args: &[String], sticky: &[String]) -> Vec<String> {
+	let sticky_flags: Vec<&str> = sticky.iter().map(|arg| flag_name(arg)).collect();
+
+	let mut merged = Vec::with_capacity(args.len() + sticky.len());
+	let mut args = args.iter().peekable();
+	while let Some(arg) = args.next() {
+		if !sticky_flags.contains(&flag_name(arg)) {
+			merged.push(arg.clone());
+			continue;
+		}
+
+		// `--flag value`: the value goes with the overridden flag
+		if !arg.contains('=') && args.peek().map_or(false, |next| !next.starts_with('-')) {
+			args.next();
+		}
+	}
+
+	merged.extend(sticky.iter().cloned());
+	merged
+}
+
+/// Name of the flag in `--flag=value`.
+fn flag_name(arg: &str) -> &str {
+	arg.splitn(2, '=').next().unwrap_or(arg)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+	use std::path::Path;
+	use tempdir::TempDir;
+	use super::{expand_response_files, merge_sticky_args};
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|s| s.to_string()).collect()
+	}
+
+	fn response_file(dir: &Path, name: &str, content: &str) -> String {
+		let path = dir.join(name);
+		File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+		format!("@{}", path.display())
+	}
+
+	#[test]
+	fn should_expand_response_files_in_place() {
+		let tempdir = TempDir::new("").unwrap();
+		let file = response_file(tempdir.path(), "args", "--chain kovan\n  --port=30304\n\n--no-ui\n");
+
+		assert_eq!(
+			expand_response_files(args(&["parity", "--force-direct", &file, "--jsonrpc-port", "8545"])).unwrap(),
+			args(&["parity", "--force-direct", "--chain", "kovan", "--port=30304", "--no-ui", "--jsonrpc-port", "8545"])
+		);
+		assert_eq!(expand_response_files(args(&[&file])).unwrap(), args(&[&file]));
+		assert_eq!(expand_response_files(args(&["parity", "@", "--no-ui"])).unwrap(), args(&["parity", "@", "--no-ui"]));
+	}
+
+	#[test]
+	fn should_expand_nested_response_files() {
+		let tempdir = TempDir::new("").unwrap();
+		let inner = response_file(tempdir.path(), "inner", "--port=30304");
+		let outer = response_file(tempdir.path(), "outer", &format!("--chain kovan {} --no-ui", inner));
+
+		assert_eq!(
+			expand_response_files(args(&["parity", &outer])).unwrap(),
+			args(&["parity", "--chain", "kovan", "--port=30304", "--no-ui"])
+		);
+	}
+
+	#[test]
+	fn should_fail_on_missing_or_looping_response_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let missing = tempdir.path().join("missing");
+		let error = expand_response_files(args(&["parity", &format!("@{}", missing.display())])).unwrap_err();
+		assert!(error.starts_with(&format!("Couldn't read response file {}: ", missing.display())), "{}", error);
+
+		let looping = tempdir.path().join("looping");
+		let looping = response_file(tempdir.path(), "looping", &format!("--no-ui @{}", looping.display()));
+		let error = expand_response_files(args(&["parity", &looping])).unwrap_err();
+		assert!(error.ends_with("is nested more than 8 deep"), "{}", error);
+	}
+
+	#[test]
+	fn should_append_sticky_args() {
+		assert_eq!(
+			merge_sticky_args(&args(&["--chain", "kovan"]), &args(&["--port=30304"])),
+			args(&["--chain", "kovan", "--port=30304"])
+		);
+	}
+
+	#[test]
+	fn should_keep_args_without_sticky_ones() {
+		assert_eq!(merge_sticky_args(&args(&["--chain", "kovan"]), &[]), args(&["--chain", "kovan"]));
+	}
+
+	#[test]
+	fn should_override_user_args_with_sticky_ones() {
+		assert_eq!(
+			merge_sticky_args(&args(&["--port", "30303", "--chain=kovan", "--no-ui"]), &args(&["--port=30304", "--chain=ropsten"])),
+			args(&["--no-ui", "--port=30304", "--chain=ropsten"])
+		);
+	}
+
+	#[test]
+	fn should_not_take_next_flag_as_value_of_overridden_flag() {
+		assert_eq!(
+			merge_sticky_args(&args(&["--no-warp", "--light"]), &args(&["--no-warp"])),
+			args(&["--light", "--no-warp"])
+		);
+	}
+}
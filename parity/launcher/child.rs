@@ -0,0 +1,682 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Handling of the re-executed child process.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use super::{latest_exe_path, Error, LauncherConfig};
+use super::compressed::unpack_update;
+use super::crash_output::capture_stderr;
+use super::error::signal_name;
+use super::handoff::{collect_handoff, handoff_spawned, offer_handoff};
+use super::health::{supervise, HEALTH_WINDOW, PROBE_INTERVAL};
+use super::launch_mode::{LaunchMode, LAUNCH_MODE_ENV};
+use super::pidfile::{remove_pid_file, write_pid_file};
+use super::supervisor::{track_child, untrack_child, wait_supervised};
+use super::system::retry_on_interrupt;
+use super::updates::mark_bad_update;
+use super::verify::check_executable;
+
+/// Flags the installed update is always run with, telling it it's hypervised.
+const CHILD_FLAGS: &[&str] = &["--can-restart", "--force-direct"];
+
+/// How a child run by a `ChildRunner` ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChildStatus {
+	/// The child terminated with the given status.
+	Exited(ExitStatus),
+	/// The child was stopped for not becoming healthy in time.
+	Unhealthy,
+}
+
+/// Runs a child process until it ends, so how its end is handled can be tested without spawning
+/// anything.
+pub trait ChildRunner {
+	/// Runs `exe` with `args` until it ends. Fails if it couldn't be started, or waited for.
+	fn run(&self, exe: &Path, args: &[OsString]) -> io::Result<ChildStatus>;
+}
+
+/// Runs children the way the launcher is configured to, health checking them with
+/// `--update-health-url`.
+struct UpdateRunner<'a>(&'a LauncherConfig);
+
+impl<'a> ChildRunner for UpdateRunner<'a> {
+	fn run(&self, exe: &Path, args: &[OsString]) -> io::Result<ChildStatus> {
+		let child = child_command(self.0, exe, args, LaunchMode::Updated).spawn()?;
+		wait_child(self.0, child, self.0.update_health_url.as_ref())
+	}
+}
+
+/// Runs the installed update until it exits, returning its exit code.
+///
+/// A compressed update is unpacked first. An update stopped for not becoming healthy is recorded
+/// as bad, and isn't run anymore.
+pub fn run_latest(launcher: &LauncherConfig) -> Result<i32, Error> {
+	run_latest_with(launcher, &UpdateRunner(launcher))
+}
+
+/// Runs the installed update with `runner`, as `run_latest` does.
+fn run_latest_with<R: ChildRunner>(launcher: &LauncherConfig, runner: &R) -> Result<i32, Error> {
+	let exe = latest_exe_path(launcher)?;
+	let binary = unpack_update(launcher, &exe).map_err(|e| {
+		warn_main!("Couldn't unpack the latest update {}: {}", exe.display(), e);
+		Error::UnpackFailed(e)
+	})?;
+	let result = run_child(launcher, runner, &binary);
+	if result == Err(Error::Unhealthy) {
+		mark_bad_update(launcher, &exe);
+	}
+	result
+}
+
+/// Runs the first `--fallback-binary` that can be started, in the order given, until it exits.
+///
+/// A candidate that's missing, can't be executed or fails to spawn is skipped. Returns the
+/// candidate run along with how it ended, or `None` when none could be started.
+pub fn run_fallback_binary(launcher: &LauncherConfig) -> Option<(PathBuf, Result<i32, Error>)> {
+	for candidate in &launcher.fallback_binaries {
+		if let Err(e) = check_executable(candidate) {
+			warn_main!("Skipping fallback binary {}: {}", candidate.display(), e);
+			continue;
+		}
+		match latest_command(launcher, candidate).spawn() {
+			Ok(child) => {
+				trace_main!("Running fallback binary {}...", candidate.display());
+				return Some((candidate.clone(), child_result(wait_child(launcher, child, launcher.update_health_url.as_ref()))));
+			},
+			Err(e) => warn_main!("Skipping fallback binary {}: {}", candidate.display(), e),
+		}
+	}
+	None
+}
+
+/// Runs the current binary `exe` as a supervised child until it exits.
+///
+/// Unlike an update, it isn't health checked, as there's nothing to go back to. Returns `None`
+/// when it couldn't be started.
+pub fn run_current(launcher: &LauncherConfig, exe: &Path) -> Option<Result<i32, Error>> {
+	match child_command(launcher, exe, &child_os_args(launcher), LaunchMode::Supervised).spawn() {
+		Ok(child) => Some(child_result(wait_child(launcher, child, None))),
+		Err(e) => {
+			warn_main!("Couldn't start the current binary {}: {}", exe.display(), e);
+			None
+		},
+	}
+}
+
+/// Runs `exe` as the child with `runner` until it exits, returning its exit code.
+fn run_child<R: ChildRunner>(launcher: &LauncherConfig, runner: &R, exe: &Path) -> Result<i32, Error> {
+	child_result(runner.run(exe, &child_os_args(launcher)))
+}
+
+/// Exit code of a child which ended as `status`, or why it has none to go by.
+///
+/// A child which couldn't be started or waited for is reported as `Error::UnknownStatusCode`.
+pub fn child_result(status: io::Result<ChildStatus>) -> Result<i32, Error> {
+	match status {
+		Ok(ChildStatus::Exited(status)) => exit_result(&status),
+		Ok(ChildStatus::Unhealthy) => Err(Error::Unhealthy),
+		Err(_) => Err(Error::UnknownStatusCode),
+	}
+}
+
+/// Waits for the started `child` to exit, keeping the child PID file up to date meanwhile.
+///
+/// The child is stopped if `health_url` doesn't answer in time. With `--supervise`, termination
+/// signals arriving at the launcher are forwarded to it, and then escalated if it doesn't stop.
+/// With `--capture-crash-output`, the end of its stderr is logged if it ends abnormally. With
+/// `--warm-restart`, the listeners it hands over before exiting to be restarted are held on to for
+/// the next child.
+fn wait_child(launcher: &LauncherConfig, mut child: Child, health_url: Option<&String>) -> io::Result<ChildStatus> {
+	let crash_output = capture_stderr(&mut child);
+	if launcher.warm_restart {
+		handoff_spawned();
+	}
+	if let Some(ref path) = launcher.child_pidfile {
+		write_pid_file(path, child.id());
+	}
+	if launcher.supervise {
+		track_child(child.id());
+	}
+	let (status, unhealthy) = match health_url {
+		Some(url) => {
+			let supervised = supervise(&mut child, url, HEALTH_WINDOW, PROBE_INTERVAL);
+			(supervised.status, supervised.unhealthy)
+		},
+		None if launcher.supervise => (wait_supervised(&mut child, launcher.stop_timeout), false),
+		None => (retry_on_interrupt(|| child.wait()), false),
+	};
+	if launcher.supervise {
+		untrack_child();
+	}
+	if launcher.warm_restart {
+		let restarted = !unhealthy && status.as_ref().ok().and_then(ExitStatus::code) == Some(launcher.restart_exit_code);
+		collect_handoff(restarted);
+	}
+	if let Some(ref path) = launcher.child_pidfile {
+		remove_pid_file(path);
+	}
+	if let Some(output) = crash_output {
+		let ended = match status {
+			Ok(_) if unhealthy => Some("was stopped for not becoming healthy".to_owned()),
+			Ok(ref status) => describe_abnormal_end(launcher, status),
+			Err(ref e) => Some(format!("couldn't be waited for ({})", e)),
+		};
+		if let Some(ended) = ended {
+			output.report(&ended);
+		}
+	}
+	let status = status?;
+	Ok(if unhealthy { ChildStatus::Unhealthy } else { ChildStatus::Exited(status) })
+}
+
+/// Arguments the installed update is run with, without the executable name.
+pub fn child_args(launcher: &LauncherConfig) -> Vec<String> {
+	build_child_args(launcher.node_args(), CHILD_FLAGS)
+}
+
+fn child_os_args(launcher: &LauncherConfig) -> Vec<OsString> {
+	child_args(launcher).into_iter().map(OsString::from).collect()
+}
+
+/// Appends the boolean `injected` flags to `user_args`, dropping the user's own copies of them.
+///
+/// Each injected flag is then given exactly once, as the node may reject repeated flags.
+pub fn build_child_args(user_args: &[String], injected: &[&str]) -> Vec<String> {
+	user_args.iter()
+		.filter(|arg| !injected.contains(&arg.as_str()))
+		.cloned()
+		.chain(injected.iter().map(|flag| flag.to_string()))
+		.collect()
+}
+
+/// Changes to an environment variable passed down to the child: the value it's set to, or `None` to remove it.
+pub type EnvOverride = (String, Option<String>);
+
+/// Builds the command running the installed update `exe` under the launcher.
+fn latest_command(launcher: &LauncherConfig, exe: &Path) -> Command {
+	child_command(launcher, exe, &child_os_args(launcher), LaunchMode::Updated)
+}
+
+/// Builds the command running `exe` with `args` under the launcher, telling it it's launched in `mode`.
+///
+/// Everything the child is run with is traced, as it's about to be spawned.
+fn child_command(launcher: &LauncherConfig, exe: &Path, args: &[OsString], mode: LaunchMode) -> Command {
+	let mut command = Command::new(exe);
+	let mut overrides = env_overrides(launcher, mode);
+	if launcher.warm_restart {
+		overrides.extend(offer_handoff(&mut command));
+	}
+	command.args(args);
+	command.env_clear().envs(build_child_env(env::vars_os(), &overrides));
+	if let Some(ref dir) = launcher.working_dir {
+		command.current_dir(dir);
+	}
+	limit_resources(&mut command, launcher);
+	if launcher.capture_crash_output {
+		command.stderr(Stdio::piped());
+	}
+
+	let cwd = env::current_dir().unwrap_or_default();
+	let dir = launcher.working_dir.as_ref().map_or(cwd.clone(), |dir| cwd.join(dir));
+	trace_main!("{}", describe_command(&cwd.join(exe), args, &dir, &overrides));
+	command
+}
+
+/// Describes running `exe` with `args` in `dir`, with `overrides` applied to its environment.
+///
+/// Only the names of the variables are given, as their values may be secrets.
+fn describe_command(exe: &Path, args: &[OsString], dir: &Path, overrides: &[EnvOverride]) -> String {
+	let argv: Vec<_> = Some(exe.display().to_string()).into_iter()
+		.chain(args.iter().map(|arg| arg.to_string_lossy().into_owned()))
+		.collect();
+	let keys: Vec<_> = overrides.iter()
+		.map(|&(ref key, ref value)| if value.is_some() { key.clone() } else { format!("{} (removed)", key) })
+		.collect();
+	format!(
+		"Spawning {} with argv {:?} in {}, overriding {} in its environment",
+		exe.display(), argv, dir.display(), if keys.is_empty() { "nothing".into() } else { keys.join(", ") },
+	)
+}
+
+/// Every change the launcher makes to the environment it passes down to the child.
+///
+/// Those are the settings needed by the node process, and the
+/// variables given with `--child-env`, which come next and so take precedence. The launch `mode`
+/// comes last, so the child is always told how it's run.
+fn env_overrides(launcher: &LauncherConfig, mode: LaunchMode) -> Vec<EnvOverride> {
+	let mut overrides: Vec<_> = launcher.child_env().into_iter().map(|(key, value)| (key.to_owned(), Some(value))).collect();
+	overrides.extend(launcher.extra_child_env.iter().map(|&(ref key, ref value)| (key.clone(), Some(value.clone()))));
+	overrides.push((LAUNCH_MODE_ENV.to_owned(), Some(mode.as_str().to_owned())));
+	overrides
+}
+
+/// Whole environment of the child: the `inherited` one, with `overrides` applied in order.
+pub fn build_child_env<I>(inherited: I, overrides: &[EnvOverride]) -> BTreeMap<OsString, OsString>
+	where I: IntoIterator<Item = (OsString, OsString)>
+{
+	let mut vars: BTreeMap<_, _> = inherited.into_iter().collect();
+	for &(ref key, ref value) in overrides {
+		match *value {
+			Some(ref value) => { vars.insert(key.into(), value.into()); },
+			None => { vars.remove(OsStr::new(key)); },
+		}
+	}
+	vars
+}
+
+/// Applies the resource limits configured for the child to the one spawned by `command`.
+///
+/// A limit above the launcher's own hard limit is lowered to it, as it couldn't be raised anyway.
+#[cfg(unix)]
+fn limit_resources(command: &mut Command, launcher: &LauncherConfig) {
+	use std::os::unix::process::CommandExt;
+	use libc;
+
+	let mut limits = Vec::new();
+	if let Some(bytes) = launcher.child_rlimit_as {
+		trace_main!("Limiting the child's address space to {} bytes", bytes);
+		limits.push((libc::RLIMIT_AS, bytes as libc::rlim_t));
+	}
+	if let Some(secs) = launcher.child_rlimit_cpu {
+		trace_main!("Limiting the child's CPU time to {} seconds", secs);
+		limits.push((libc::RLIMIT_CPU, secs as libc::rlim_t));
+	}
+	if limits.is_empty() {
+		return;
+	}
+
+	let set_limits = move || {
+		for &(resource, value) in &limits {
+			let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+			if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+				return Err(io::Error::last_os_error());
+			}
+			let value = if value < limit.rlim_max { value } else { limit.rlim_max };
+			limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+			if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+		Ok(())
+	};
+	// only async-signal-safe calls are made between fork and exec, without allocating
+	unsafe { command.pre_exec(set_limits); }
+}
+
+#[cfg(not(unix))]
+fn limit_resources(_command: &mut Command, _launcher: &LauncherConfig) {}
+
+/// Returns the exit code of a child which terminated with `status`.
+///
+/// A child killed by a signal has no exit code, which is reported as `Error::KilledBySignal`.
+pub fn exit_result(status: &ExitStatus) -> Result<i32, Error> {
+	if let Some(code) = status.code() {
+		return Ok(code);
+	}
+
+	match termination_signal(status) {
+		Some(signal) => {
+			trace_main!("Child process was terminated by signal {} ({})", signal, signal_name(signal));
+			Err(Error::KilledBySignal(signal))
+		},
+		None => Err(Error::UnknownStatusCode),
+	}
+}
+
+/// Describes how a child which terminated with `status` ended, unless it exited normally or asked
+/// for a restart.
+fn describe_abnormal_end(launcher: &LauncherConfig, status: &ExitStatus) -> Option<String> {
+	match (status.code(), termination_signal(status)) {
+		(Some(0), _) => None,
+		(Some(code), _) if code == launcher.restart_exit_code => None,
+		(Some(code), _) => Some(format!("exited with {}", code)),
+		(None, Some(signal)) => Some(format!("was terminated by signal {} ({})", signal, signal_name(signal))),
+		(None, None) => Some("ended without an exit code".to_owned()),
+	}
+}
+
+#[cfg(unix)]
+fn termination_signal(status: &ExitStatus) -> Option<i32> {
+	use std::os::unix::process::ExitStatusExt;
+	status.signal()
+}
+
+#[cfg(not(unix))]
+fn termination_signal(_status: &ExitStatus) -> Option<i32> {
+	None
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::cell::RefCell;
+	use std::ffi::OsString;
+	use std::fs::File;
+	use std::io::{self, Read};
+	use std::os::unix::process::ExitStatusExt;
+	use std::path::{Path, PathBuf};
+	use std::process::ExitStatus;
+	use launcher::{Error, LauncherConfig};
+	use launcher::updates::{set_sticky_args, take_sticky_args, BAD_UPDATE_FILE};
+	use tempdir::TempDir;
+	use super::{
+		build_child_args, build_child_env, child_args, child_result, describe_abnormal_end, describe_command, exit_result,
+		latest_command, run_child, run_current, run_fallback_binary, run_latest_with, ChildRunner, ChildStatus,
+		UpdateRunner,
+	};
+
+	/// Stands in for the child, ending as `status` or failing to start without one.
+	struct MockRunner {
+		status: Option<ChildStatus>,
+		runs: RefCell<Vec<(PathBuf, Vec<OsString>)>>,
+	}
+
+	impl MockRunner {
+		fn new(status: Option<ChildStatus>) -> Self {
+			MockRunner { status, runs: RefCell::new(Vec::new()) }
+		}
+	}
+
+	impl ChildRunner for MockRunner {
+		fn run(&self, exe: &Path, args: &[OsString]) -> io::Result<ChildStatus> {
+			self.runs.borrow_mut().push((exe.to_owned(), args.to_vec()));
+			self.status.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such binary"))
+		}
+	}
+
+	fn config(args: &[&str]) -> LauncherConfig {
+		LauncherConfig::new(args.iter().map(|s| s.to_string()).collect(), None, PathBuf::from("/updates")).unwrap()
+	}
+
+	#[test]
+	fn should_describe_spawned_command() {
+		let overrides = vec![("PARITY_LAUNCH_MODE".to_owned(), Some("updated".to_owned())), ("RUST_BACKTRACE".to_owned(), None)];
+		let args: Vec<OsString> = vec!["--chain".into(), "kovan".into(), "--can-restart".into()];
+
+		assert_eq!(
+			describe_command(Path::new("/updates/parity-1.12.0"), &args, Path::new("/var/lib/parity"), &overrides),
+			"Spawning /updates/parity-1.12.0 with argv [\"/updates/parity-1.12.0\", \"--chain\", \"kovan\", \"--can-restart\"] \
+				in /var/lib/parity, overriding PARITY_LAUNCH_MODE, RUST_BACKTRACE (removed) in its environment",
+		);
+		assert!(describe_command(Path::new("/bin/parity"), &[], Path::new("/"), &[]).ends_with("overriding nothing in its environment"));
+	}
+
+	#[test]
+	fn should_set_extra_env_for_child() {
+		// the shell stands in for the update, exiting with 0 only if the variable is set
+		let args = ["parity", "-c", "test \"$PARITY_TEST_VAR\" = expected", "--child-env", "PARITY_TEST_VAR=expected"];
+		let launcher = config(&args);
+
+		let status = latest_command(&launcher, Path::new("/bin/sh")).status().unwrap();
+		assert!(status.success());
+	}
+
+	#[test]
+	fn should_pass_launcher_settings_on_top_of_inherited_env() {
+		// the shell stands in for the update, exiting with 0 only if it got both
+		let args = ["parity", "-c", "test \"$PARITY_RESTART_GRACE_MS\" = 250 -a -n \"$PATH\"", "--restart-grace-ms", "250"];
+		let launcher = config(&args);
+
+		let status = latest_command(&launcher, Path::new("/bin/sh")).status().unwrap();
+		assert!(status.success());
+	}
+
+	#[test]
+	fn should_tell_current_binary_it_is_supervised() {
+		// the shell stands in for the current binary, exiting with 0 only if it's told it's supervised
+		let args = ["parity", "-c", "test \"$PARITY_LAUNCH_MODE\" = supervised"];
+		let launcher = config(&args);
+
+		assert_eq!(run_current(&launcher, Path::new("/bin/sh")), Some(Ok(0)));
+		assert_eq!(run_current(&launcher, Path::new("/nonexistent/parity")), None);
+	}
+
+	#[test]
+	fn should_tell_child_it_is_an_update() {
+		// the shell stands in for the update, exiting with 0 only if it's told it's one
+		let args = ["parity", "-c", "test \"$PARITY_LAUNCH_MODE\" = updated", "--child-env", "PARITY_LAUNCH_MODE=direct"];
+		let launcher = config(&args);
+
+		let status = latest_command(&launcher, Path::new("/bin/sh")).status().unwrap();
+		assert!(status.success());
+	}
+
+	#[test]
+	fn should_pass_node_output_through_when_quiet() {
+		// the shell stands in for the update, printing only if it's told to keep launcher messages quiet
+		let args = ["parity", "-c", "test \"$PARITY_QUIET_LAUNCHER\" = 1 && echo node output", "--quiet-launcher"];
+		let launcher = config(&args);
+
+		let output = latest_command(&launcher, Path::new("/bin/sh")).output().unwrap();
+		assert_eq!(String::from_utf8(output.stdout).unwrap(), "node output\n");
+		assert!(output.stderr.is_empty());
+	}
+
+	#[test]
+	fn should_apply_env_overrides_in_order() {
+		let inherited = vec![("KEPT".into(), "1".into()), ("DROPPED".into(), "42".into()), ("RUST_LOG".into(), "info".into())];
+		let overrides = vec![
+			("RUST_LOG".to_owned(), Some("debug".to_owned())),
+			("DROPPED".to_owned(), None),
+			("RUST_LOG".to_owned(), Some("trace".to_owned())),
+			("ADDED".to_owned(), Some("2".to_owned())),
+		];
+
+		let vars = build_child_env(inherited, &overrides);
+		let vars: Vec<_> = vars.iter().map(|(key, value)| (key.to_str().unwrap(), value.to_str().unwrap())).collect();
+		assert_eq!(vars, [("ADDED", "2"), ("KEPT", "1"), ("RUST_LOG", "trace")]);
+	}
+
+	#[test]
+	fn should_run_child_in_launcher_working_dir() {
+		let tempdir = TempDir::new("").unwrap();
+		// the shell stands in for the update, printing its working directory
+		let args = ["parity", "-c", "pwd -P"];
+		let mut launcher = config(&args);
+		launcher.working_dir = Some(tempdir.path().to_owned());
+
+		let output = latest_command(&launcher, Path::new("/bin/sh")).output().unwrap();
+		let printed = String::from_utf8(output.stdout).unwrap();
+		assert_eq!(Path::new(printed.trim_right()), tempdir.path().canonicalize().unwrap());
+	}
+
+	#[test]
+	fn should_run_first_startable_fallback_binary() {
+		use std::fs::{set_permissions, File, Permissions};
+		use std::os::unix::fs::PermissionsExt;
+
+		let tempdir = TempDir::new("").unwrap();
+		let missing = tempdir.path().join("parity-missing");
+		let not_executable = tempdir.path().join("parity-1.11.0");
+		File::create(&not_executable).unwrap();
+		set_permissions(&not_executable, Permissions::from_mode(0o644)).unwrap();
+		// the shell stands in for the known-good binary, exiting with 3
+		let args = [
+			"parity", "-c", "exit 3",
+			"--fallback-binary", missing.to_str().unwrap(),
+			"--fallback-binary", not_executable.to_str().unwrap(),
+			"--fallback-binary", "/bin/sh",
+			"--fallback-binary", missing.to_str().unwrap(),
+		];
+		let launcher = config(&args);
+
+		assert_eq!(run_fallback_binary(&launcher), Some((PathBuf::from("/bin/sh"), Ok(3))));
+	}
+
+	#[test]
+	fn should_not_run_fallback_binary_when_none_is_usable() {
+		let tempdir = TempDir::new("").unwrap();
+		let missing = tempdir.path().join("parity-missing");
+		let args = ["parity", "--fallback-binary", missing.to_str().unwrap()];
+		let launcher = config(&args);
+		assert_eq!(run_fallback_binary(&launcher), None);
+
+		let launcher = config(&["parity"]);
+		assert_eq!(run_fallback_binary(&launcher), None);
+	}
+
+	#[test]
+	fn should_limit_child_resources() {
+		// the shell stands in for the update, exiting with 0 only if its CPU time is limited
+		let args = ["parity", "-c", "test \"$(ulimit -t)\" = 7", "--child-rlimit-cpu", "7"];
+		let launcher = config(&args);
+
+		let status = latest_command(&launcher, Path::new("/bin/sh")).status().unwrap();
+		assert!(status.success());
+	}
+
+	#[test]
+	fn should_keep_child_pid_file_while_running() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("child.pid");
+		let path = path.to_str().unwrap();
+		// the shell stands in for the update, exiting with 0 only if the file holds its own PID
+		let args = ["parity", "-c", "test \"$(cat \"$0\")\" = $$", path, "--child-pidfile", path];
+		let launcher = config(&args);
+
+		assert_eq!(run_child(&launcher, &UpdateRunner(&launcher), Path::new("/bin/sh")), Ok(0));
+		assert!(!Path::new(path).exists());
+	}
+
+	#[test]
+	fn should_spawn_child_with_printed_args() {
+		// the shell stands in for the update, printing the arguments after its script
+		let args = ["parity", "-c", "printf '%s\\n' \"$0\" \"$@\"", "--chain", "kovan", "--capture-crash-output"];
+		let launcher = config(&args);
+
+		let output = latest_command(&launcher, Path::new("/bin/sh")).output().unwrap();
+		let printed: Vec<_> = String::from_utf8(output.stdout).unwrap().lines().map(String::from).collect();
+		assert_eq!(printed, &child_args(&launcher)[2..]);
+		assert_eq!(printed, ["--chain", "kovan", "--can-restart", "--force-direct"]);
+	}
+
+	#[test]
+	fn should_not_repeat_injected_flags() {
+		let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+		let injected = &["--can-restart", "--force-direct"];
+
+		assert_eq!(build_child_args(&args(&["--chain", "kovan"]), injected), args(&["--chain", "kovan", "--can-restart", "--force-direct"]));
+		assert_eq!(
+			build_child_args(&args(&["--force-direct", "--chain", "kovan", "--can-restart", "--force-direct"]), injected),
+			args(&["--chain", "kovan", "--can-restart", "--force-direct"])
+		);
+		assert_eq!(build_child_args(&args(&["--force-direct-chain"]), injected), args(&["--force-direct-chain", "--can-restart", "--force-direct"]));
+	}
+
+	#[test]
+	fn should_pass_user_force_direct_once() {
+		let args = ["parity", "--force-direct", "--can-restart", "--chain", "kovan"];
+		let launcher = config(&args);
+		assert_eq!(child_args(&launcher), ["--chain", "kovan", "--can-restart", "--force-direct"]);
+	}
+
+	#[test]
+	fn should_restart_child_with_port_it_kept() {
+		let tempdir = TempDir::new("").unwrap();
+		let args = ["parity", "--port", "0", "--chain", "kovan"];
+		let mut launcher = LauncherConfig { updates_dir: tempdir.path().to_owned(), ..config(&args) };
+
+		// as reported by the client for the port it took instead of any free one, when asking for a restart
+		set_sticky_args(&launcher, &["--port=30310".to_owned()]);
+		// as taken by the launcher before running the node again
+		let sticky_args = take_sticky_args(&launcher);
+		launcher.keep_sticky_args(&sticky_args);
+
+		assert_eq!(child_args(&launcher), ["--chain", "kovan", "--port=30310", "--can-restart", "--force-direct"]);
+	}
+
+	#[test]
+	fn should_report_exit_code_of_normally_exited_child() {
+		// wait status layout: exit code in the second byte
+		assert_eq!(exit_result(&ExitStatus::from_raw(0)), Ok(0));
+		assert_eq!(exit_result(&ExitStatus::from_raw(69 << 8)), Ok(69));
+	}
+
+	#[test]
+	fn should_report_signal_of_killed_child() {
+		// wait status layout: terminating signal in the low 7 bits
+		assert_eq!(exit_result(&ExitStatus::from_raw(9)), Err(Error::KilledBySignal(9)));
+		assert_eq!(exit_result(&ExitStatus::from_raw(11)), Err(Error::KilledBySignal(11)));
+	}
+
+	#[test]
+	fn should_run_child_with_captured_stderr() {
+		// the shell stands in for the current binary, crashing with a last word on stderr
+		let args = ["parity", "-c", "echo 'thread main panicked' >&2; exit 3", "--capture-crash-output"];
+		let launcher = config(&args);
+
+		assert!(launcher.capture_crash_output);
+		assert_eq!(run_current(&launcher, Path::new("/bin/sh")), Some(Ok(3)));
+	}
+
+	#[test]
+	fn should_describe_only_abnormal_ends() {
+		let launcher = config(&["parity"]);
+		let ended = |raw| describe_abnormal_end(&launcher, &ExitStatus::from_raw(raw));
+
+		assert_eq!(ended(0), None);
+		assert_eq!(ended(launcher.restart_exit_code << 8), None);
+		assert_eq!(ended(3 << 8), Some("exited with 3".into()));
+		assert_eq!(ended(11), Some("was terminated by signal 11 (SIGSEGV)".into()));
+	}
+
+	#[test]
+	fn should_map_every_child_end_to_its_result() {
+		let exited = |raw| Ok(ChildStatus::Exited(ExitStatus::from_raw(raw)));
+
+		assert_eq!(child_result(exited(0)), Ok(0));
+		assert_eq!(child_result(exited(69 << 8)), Ok(69));
+		assert_eq!(child_result(exited(9)), Err(Error::KilledBySignal(9)));
+		// stopped rather than terminated, so it has neither an exit code nor a terminating signal
+		assert_eq!(child_result(exited(0x137f)), Err(Error::UnknownStatusCode));
+		assert_eq!(child_result(Ok(ChildStatus::Unhealthy)), Err(Error::Unhealthy));
+		assert_eq!(child_result(Err(io::ErrorKind::Interrupted.into())), Err(Error::UnknownStatusCode));
+	}
+
+	#[test]
+	fn should_run_installed_update_with_child_args() {
+		let tempdir = TempDir::new("").unwrap();
+		let args = ["parity", "--chain", "kovan", "--use-binary", "/bin/sh"];
+		let launcher = LauncherConfig { updates_dir: tempdir.path().to_owned(), ..config(&args) };
+		let runner = MockRunner::new(Some(ChildStatus::Exited(ExitStatus::from_raw(69 << 8))));
+
+		assert_eq!(run_latest_with(&launcher, &runner), Ok(69));
+		let expected: Vec<OsString> = child_args(&launcher).into_iter().map(OsString::from).collect();
+		assert_eq!(*runner.runs.borrow(), [(PathBuf::from("/bin/sh"), expected)]);
+		assert!(!tempdir.path().join(BAD_UPDATE_FILE).exists());
+	}
+
+	#[test]
+	fn should_mark_only_unhealthy_update_bad() {
+		let tempdir = TempDir::new("").unwrap();
+		let args = ["parity", "--use-binary", "/bin/sh"];
+		let launcher = LauncherConfig { updates_dir: tempdir.path().to_owned(), ..config(&args) };
+
+		assert_eq!(run_latest_with(&launcher, &MockRunner::new(None)), Err(Error::UnknownStatusCode));
+		assert!(!tempdir.path().join(BAD_UPDATE_FILE).exists());
+
+		assert_eq!(run_latest_with(&launcher, &MockRunner::new(Some(ChildStatus::Unhealthy))), Err(Error::Unhealthy));
+		let mut marked = String::new();
+		File::open(tempdir.path().join(BAD_UPDATE_FILE)).unwrap().read_to_string(&mut marked).unwrap();
+		assert_eq!(marked, "sh");
+	}
+}
@@ -0,0 +1,461 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Commands handled by the launcher itself.
+
+use std::fs::{self, read_dir, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use super::{is_same_binary, LauncherCommand, LauncherConfig};
+use super::child::child_args;
+use super::compressed::LATEST_CHECKSUM_FILE;
+use super::detect::explain_launch_detection;
+use super::notify::NOTIFIED_VERSION_FILE;
+use super::panics::PANIC_MARKER_FILE;
+use super::patch::{LATEST_PATCH_FILE, PATCH_BASE_VERSION_FILE};
+use super::plan::emit_launch_plan;
+use super::selftest::self_test;
+use super::slots::{Slot, ACTIVE_SLOT_FILE};
+use super::updates::{
+	pending_sticky_args, request_update_apply, APPLY_UPDATE_FILE, BAD_UPDATE_FILE, CANDIDATE_FILE, LATEST_FILE,
+	RUNNING_SPEC_FILE, SPEC_NAME_OVERRIDE_FILE, STICKY_ARGS_FILE,
+};
+use super::verify::{verify, verify_bundle};
+use super::version::{BINARY_PREFIX, LATEST_CHANNEL_FILE, LATEST_VERSION_FILE};
+
+/// Launcher state files, safe to remove while no node is being restarted.
+const STATE_FILES: &[&str] = &[
+	SPEC_NAME_OVERRIDE_FILE, STICKY_ARGS_FILE, APPLY_UPDATE_FILE, LATEST_PATCH_FILE, PATCH_BASE_VERSION_FILE, PANIC_MARKER_FILE,
+	NOTIFIED_VERSION_FILE, RUNNING_SPEC_FILE, BAD_UPDATE_FILE, "launcher.log",
+];
+
+/// File naming the binary to roll back to, never pruned.
+const ROLLBACK_FILE: &str = "rollback";
+
+/// File removed from the updates directory.
+#[derive(Debug, PartialEq)]
+pub struct Removed {
+	/// Path of the file.
+	pub path: PathBuf,
+	/// Size of the file, in bytes.
+	pub size: u64,
+}
+
+/// Runs a launcher command, returning the exit code.
+pub fn execute(launcher: &LauncherConfig, command: &LauncherCommand) -> i32 {
+	let writes_state = match *command {
+		LauncherCommand::CleanState { .. } | LauncherCommand::PruneUpdates { .. } | LauncherCommand::ApplyUpdateNow => true,
+		_ => false,
+	};
+	if writes_state && launcher.readonly {
+		warn_main!("Not changing {} with --launcher-readonly.", launcher.updates_dir.display());
+		return 1;
+	}
+
+	match *command {
+		LauncherCommand::CleanState { keep_latest } => {
+			match clean_state(&launcher.updates_dir, keep_latest, launcher.current_exe.as_ref().map(PathBuf::as_path)) {
+				Ok(removed) => report(&removed),
+				Err(e) => {
+					warn_main!("Couldn't clean launcher state in {}: {}", launcher.updates_dir.display(), e);
+					1
+				},
+			}
+		},
+		LauncherCommand::PruneUpdates { keep } => {
+			match prune_updates(&launcher.updates_dir, keep, launcher.current_exe.as_ref().map(PathBuf::as_path)) {
+				Ok(removed) => report(&removed),
+				Err(e) => {
+					warn_main!("Couldn't prune updates in {}: {}", launcher.updates_dir.display(), e);
+					1
+				},
+			}
+		},
+		LauncherCommand::SelfTest => self_test(launcher),
+		LauncherCommand::VerifyOnly => verify(launcher),
+		LauncherCommand::VerifyUpdateBundle(ref dir) => verify_bundle(launcher, dir),
+		LauncherCommand::PrintChildArgs => {
+			// arguments kept across a pending restart are merged in just like before the next run
+			let mut launcher = launcher.clone();
+			let sticky_args = pending_sticky_args(&launcher);
+			launcher.keep_sticky_args(&sticky_args);
+			for arg in child_args(&launcher) {
+				println!("{}", arg);
+			}
+			0
+		},
+		LauncherCommand::EmitLaunchPlan => emit_launch_plan(launcher),
+		LauncherCommand::ExplainLaunchDetection => {
+			for line in explain_launch_detection(launcher) {
+				println!("{}", line);
+			}
+			0
+		},
+		LauncherCommand::ApplyUpdateNow => {
+			match request_update_apply(&launcher.updates_dir) {
+				Ok(()) => {
+					println!("The staged update will be run on the node's next restart");
+					0
+				},
+				Err(e) => {
+					warn_main!("Couldn't ask for the update to be applied in {}: {}", launcher.updates_dir.display(), e);
+					1
+				},
+			}
+		},
+		LauncherCommand::SelfTestExit(code) => code,
+		LauncherCommand::EmitRestartCode => {
+			println!(
+				"Exiting with restart code {} to test the supervisor; it should start the node again.",
+				launcher.restart_exit_code
+			);
+			launcher.restart_exit_code
+		},
+	}
+}
+
+fn report(removed: &[Removed]) -> i32 {
+	for file in removed {
+		println!("Removed {} ({} bytes)", file.path.display(), file.size);
+	}
+	println!("Freed {} bytes", removed.iter().map(|file| file.size).sum::<u64>());
+	0
+}
+
+/// Removes installed binaries from `updates_dir`, except for the `keep` most recent ones.
+///
+/// The `running` executable and the binaries `latest`, `rollback` and the slots point to are
+/// always kept on top of the `keep` most recent others.
+pub fn prune_updates(updates_dir: &Path, keep: usize, running: Option<&Path>) -> io::Result<Vec<Removed>> {
+	let entries = match read_dir(updates_dir) {
+		Ok(entries) => entries,
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+	};
+
+	let mut binaries = Vec::new();
+	for entry in entries {
+		let entry = entry?;
+		let metadata = entry.metadata()?;
+		if metadata.is_file() && entry.file_name().to_string_lossy().starts_with(BINARY_PREFIX) {
+			binaries.push((entry.path(), metadata.modified()?));
+		}
+	}
+
+	let mut pinned: Vec<PathBuf> = running.into_iter().map(Path::to_owned).collect();
+	pinned.extend(read_pointer(updates_dir, LATEST_FILE).map(|name| updates_dir.join(name)));
+	pinned.extend(read_pointer(updates_dir, CANDIDATE_FILE).map(|name| updates_dir.join(name)));
+	pinned.extend(read_pointer(updates_dir, ROLLBACK_FILE).map(|name| updates_dir.join(name)));
+	for slot in &[Slot::A, Slot::B] {
+		pinned.extend(read_pointer(updates_dir, &slot.pointer_file()).map(|name| updates_dir.join(name)));
+	}
+
+	binaries.sort_by(|a, b| b.1.cmp(&a.1));
+	let to_remove: Vec<_> = binaries.into_iter()
+		.map(|(path, _)| path)
+		.filter(|path| !pinned.iter().any(|kept| is_same_binary(path, kept)))
+		.skip(keep)
+		.collect();
+
+	let mut removed = Vec::with_capacity(to_remove.len());
+	for path in to_remove {
+		let size = fs::metadata(&path)?.len();
+		fs::remove_file(&path)?;
+		removed.push(Removed { path, size });
+	}
+
+	Ok(removed)
+}
+
+/// Removes launcher state files, lock files and installed binaries from `updates_dir`.
+///
+/// With `keep_latest`, the binary `latest` points to and the most recent one before it are kept,
+/// along with the binaries `candidate` and the slots point to. The `running` executable is never removed. A pointer and
+/// the files describing it are kept while its binary is, and removed otherwise.
+pub fn clean_state(updates_dir: &Path, keep_latest: bool, running: Option<&Path>) -> io::Result<Vec<Removed>> {
+	let entries = match read_dir(updates_dir) {
+		Ok(entries) => entries,
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+	};
+
+	let mut state = Vec::new();
+	let mut binaries = Vec::new();
+	for entry in entries {
+		let entry = entry?;
+		let metadata = entry.metadata()?;
+		let name = entry.file_name().to_string_lossy().into_owned();
+		if !metadata.is_file() {
+			continue;
+		}
+
+		if STATE_FILES.contains(&name.as_str()) || name.ends_with(".lock") {
+			state.push(entry.path());
+		} else if name.starts_with(BINARY_PREFIX) {
+			binaries.push((entry.path(), metadata.modified()?));
+		}
+	}
+
+	let latest = read_pointer(updates_dir, LATEST_FILE).map(|name| updates_dir.join(name));
+	let mut keep: Vec<PathBuf> = running.into_iter().map(Path::to_owned).collect();
+	if keep_latest {
+		if let Some(ref latest) = latest {
+			keep.push(latest.clone());
+			let previous = binaries.iter()
+				.filter(|&&(ref path, _)| !is_same_binary(path, latest))
+				.max_by_key(|&&(_, modified)| modified)
+				.map(|&(ref path, _)| path.clone());
+			keep.extend(previous);
+		}
+		keep.extend(read_pointer(updates_dir, CANDIDATE_FILE).map(|name| updates_dir.join(name)));
+		for slot in &[Slot::A, Slot::B] {
+			keep.extend(read_pointer(updates_dir, &slot.pointer_file()).map(|name| updates_dir.join(name)));
+		}
+	}
+
+	let mut to_remove = state;
+	to_remove.extend(binaries.into_iter()
+		.map(|(path, _)| path)
+		.filter(|path| !keep.iter().any(|kept| is_same_binary(path, kept))));
+
+	for pointer in &[LATEST_FILE, CANDIDATE_FILE] {
+		let files: Vec<_> = [LATEST_VERSION_FILE, LATEST_CHANNEL_FILE, LATEST_CHECKSUM_FILE].iter()
+			.map(|file| format!("{}{}", pointer, &file[LATEST_FILE.len()..]))
+			.collect();
+		to_remove.extend(unkept_pointer(updates_dir, pointer, &files, &keep));
+	}
+	let mut slots_left = false;
+	for slot in &[Slot::A, Slot::B] {
+		let pointer = slot.pointer_file();
+		let removed = unkept_pointer(updates_dir, &pointer, &slot.trial_files(), &keep);
+		slots_left |= updates_dir.join(&pointer).is_file() && removed.is_empty();
+		to_remove.extend(removed);
+	}
+	let active_slot = updates_dir.join(ACTIVE_SLOT_FILE);
+	if !slots_left && active_slot.is_file() {
+		to_remove.push(active_slot);
+	}
+
+	let mut removed = Vec::with_capacity(to_remove.len());
+	for path in to_remove {
+		let size = fs::metadata(&path)?.len();
+		fs::remove_file(&path)?;
+		removed.push(Removed { path, size });
+	}
+
+	Ok(removed)
+}
+
+/// The `pointer` and the `files` describing it, if it points to a binary which isn't one to `keep`.
+fn unkept_pointer<P: AsRef<Path>>(updates_dir: &Path, pointer: &str, files: &[P], keep: &[PathBuf]) -> Vec<PathBuf> {
+	let binary = match read_pointer(updates_dir, pointer) {
+		Some(name) => updates_dir.join(name),
+		None => return Vec::new(),
+	};
+	if keep.iter().any(|kept| is_same_binary(&binary, kept)) {
+		return Vec::new();
+	}
+	Some(updates_dir.join(pointer)).into_iter()
+		.chain(files.iter().map(|name| updates_dir.join(name)))
+		.filter(|path| path.is_file())
+		.collect()
+}
+
+fn read_pointer(updates_dir: &Path, pointer: &str) -> Option<String> {
+	let mut name = String::new();
+	File::open(updates_dir.join(pointer)).and_then(|mut f| f.read_to_string(&mut name)).ok().map(|_| name)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+	use std::path::Path;
+	use std::thread;
+	use std::time::Duration;
+	use tempdir::TempDir;
+	use launcher::{LauncherCommand, LauncherConfig};
+	use super::{clean_state, execute, prune_updates};
+
+	fn write(dir: &Path, name: &str, content: &str) {
+		File::create(dir.join(name)).unwrap().write_all(content.as_bytes()).unwrap();
+		// make sure modification times differ
+		thread::sleep(Duration::from_millis(20));
+	}
+
+	fn populate(dir: &Path) {
+		write(dir, "parity-1.10.0-aa", "old");
+		write(dir, "parity-1.11.0-bb", "previous");
+		write(dir, "parity-1.12.0-cc", "latest");
+		write(dir, "latest", "parity-1.12.0-cc");
+		write(dir, "spec_name_override", "kovan");
+		write(dir, "updater.lock", "");
+		write(dir, "notes.txt", "unrelated");
+	}
+
+	fn remaining(dir: &Path) -> Vec<String> {
+		let mut names: Vec<_> = dir.read_dir().unwrap()
+			.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+			.collect();
+		names.sort();
+		names
+	}
+
+	#[test]
+	fn should_remove_everything_launcher_related() {
+		let tempdir = TempDir::new("").unwrap();
+		populate(tempdir.path());
+
+		let removed = clean_state(tempdir.path(), false, None).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec!["notes.txt"]);
+		assert_eq!(removed.len(), 6);
+		assert_eq!(removed.iter().map(|r| r.size).sum::<u64>(), 3 + 8 + 6 + 16 + 5);
+	}
+
+	#[test]
+	fn should_keep_latest_and_previous_binaries() {
+		let tempdir = TempDir::new("").unwrap();
+		populate(tempdir.path());
+
+		clean_state(tempdir.path(), true, None).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec!["latest", "notes.txt", "parity-1.11.0-bb", "parity-1.12.0-cc"]);
+	}
+
+	#[test]
+	fn should_not_remove_running_binary() {
+		let tempdir = TempDir::new("").unwrap();
+		populate(tempdir.path());
+
+		clean_state(tempdir.path(), false, Some(&tempdir.path().join("parity-1.12.0-cc"))).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec!["latest", "notes.txt", "parity-1.12.0-cc"]);
+	}
+
+	#[test]
+	fn should_keep_candidate_binary_with_latest() {
+		let tempdir = TempDir::new("").unwrap();
+		write(tempdir.path(), "parity-1.13.0-dd", "candidate");
+		populate(tempdir.path());
+		write(tempdir.path(), "candidate", "parity-1.13.0-dd");
+		write(tempdir.path(), "candidate.version", "1.13.0");
+
+		clean_state(tempdir.path(), true, None).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec![
+			"candidate", "candidate.version", "latest", "notes.txt", "parity-1.11.0-bb", "parity-1.12.0-cc", "parity-1.13.0-dd",
+		]);
+	}
+
+	#[test]
+	fn should_remove_channel_files_along_with_binaries() {
+		let tempdir = TempDir::new("").unwrap();
+		populate(tempdir.path());
+		write(tempdir.path(), "latest.version", "1.12.0");
+		write(tempdir.path(), "latest.keccak", "0x00");
+		write(tempdir.path(), "candidate", "parity-1.10.0-aa");
+		write(tempdir.path(), "candidate.version", "1.10.0");
+		write(tempdir.path(), "candidate.channel", "beta");
+		write(tempdir.path(), "candidate.keccak", "0x00");
+
+		clean_state(tempdir.path(), false, None).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec!["notes.txt"]);
+	}
+
+	#[test]
+	fn should_keep_slot_binaries_and_their_state_with_latest() {
+		let tempdir = TempDir::new("").unwrap();
+		write(tempdir.path(), "parity-1.9.0-zz", "slot");
+		populate(tempdir.path());
+		write(tempdir.path(), "slot-a", "parity-1.9.0-zz");
+		write(tempdir.path(), "slot-a.settled", "parity-1.9.0-zz");
+		write(tempdir.path(), "active-slot", "a");
+
+		clean_state(tempdir.path(), true, None).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec![
+			"active-slot", "latest", "notes.txt", "parity-1.11.0-bb", "parity-1.12.0-cc", "parity-1.9.0-zz", "slot-a",
+			"slot-a.settled",
+		]);
+	}
+
+	#[test]
+	fn should_remove_slot_state_along_with_slot_binaries() {
+		let tempdir = TempDir::new("").unwrap();
+		populate(tempdir.path());
+		write(tempdir.path(), "slot-a", "parity-1.11.0-bb");
+		write(tempdir.path(), "slot-b", "parity-1.10.0-aa");
+		write(tempdir.path(), "slot-b.settled", "parity-1.10.0-aa");
+		write(tempdir.path(), "slot-b.demoted", "parity-1.10.0-aa");
+		write(tempdir.path(), "active-slot", "a");
+
+		clean_state(tempdir.path(), false, Some(&tempdir.path().join("parity-1.11.0-bb"))).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec!["active-slot", "notes.txt", "parity-1.11.0-bb", "slot-a"]);
+
+		clean_state(tempdir.path(), false, None).unwrap();
+
+		assert_eq!(remaining(tempdir.path()), vec!["notes.txt"]);
+	}
+
+	#[test]
+	fn should_accept_missing_updates_dir() {
+		let tempdir = TempDir::new("").unwrap();
+
+		assert_eq!(clean_state(&tempdir.path().join("missing"), false, None).unwrap(), vec![]);
+	}
+
+	#[test]
+	fn should_prune_all_but_most_recent_binaries() {
+		let tempdir = TempDir::new("").unwrap();
+		write(tempdir.path(), "parity-1.9.0-zz", "oldest");
+		populate(tempdir.path());
+
+		let removed = prune_updates(tempdir.path(), 1, None).unwrap();
+
+		assert_eq!(removed.len(), 2);
+		assert_eq!(remaining(tempdir.path()), vec![
+			"latest", "notes.txt", "parity-1.11.0-bb", "parity-1.12.0-cc", "spec_name_override", "updater.lock",
+		]);
+	}
+
+	#[test]
+	fn should_never_prune_pinned_binaries() {
+		let tempdir = TempDir::new("").unwrap();
+		populate(tempdir.path());
+		write(tempdir.path(), "rollback", "parity-1.10.0-aa");
+
+		prune_updates(tempdir.path(), 0, Some(&tempdir.path().join("parity-1.11.0-bb"))).unwrap();
+
+		let remaining = remaining(tempdir.path());
+		assert!(remaining.contains(&"parity-1.10.0-aa".to_owned()));
+		assert!(remaining.contains(&"parity-1.11.0-bb".to_owned()));
+		assert!(remaining.contains(&"parity-1.12.0-cc".to_owned()));
+	}
+
+	#[test]
+	fn should_exit_with_configured_restart_code() {
+		let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect();
+		let launcher = LauncherConfig::new(args(&["parity", "emit-restart-code"]), None, "/updates".into()).unwrap();
+		assert_eq!(execute(&launcher, &LauncherCommand::EmitRestartCode), 69);
+
+		let launcher = LauncherConfig::new(args(&["parity", "emit-restart-code", "--restart-exit-code", "75"]), None, "/updates".into()).unwrap();
+		assert_eq!(execute(&launcher, &LauncherCommand::EmitRestartCode), 75);
+	}
+}
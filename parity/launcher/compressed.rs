@@ -0,0 +1,276 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Compressed updates, unpacked by the launcher before being run.
+//!
+//! The updater may stage a gzip or, with the `zstd` feature, zstd-compressed binary, recognized by
+//! its extension or magic bytes. The launcher unpacks it next to the compressed one and runs the
+//! result once its keccak hash matches the one given in `latest.keccak`, or `candidate.keccak` on
+//! the candidate channel.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use hash::{keccak_buffer, keccak_pipe};
+use super::LauncherConfig;
+
+/// File holding the hex keccak hash of the unpacked binary of a compressed update.
+pub const LATEST_CHECKSUM_FILE: &str = "latest.keccak";
+
+/// Compression formats an update may be staged in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+	/// gzip, supported by default.
+	Gzip,
+	/// zstd, supported with the `zstd` feature.
+	Zstd,
+}
+
+impl Compression {
+	/// Detects the compression of the file `name` starting with `magic`, if it's compressed.
+	pub fn detect(name: &str, magic: &[u8]) -> Option<Self> {
+		if name.ends_with(".gz") || magic.starts_with(&[0x1f, 0x8b]) {
+			Some(Compression::Gzip)
+		} else if name.ends_with(".zst") || magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+			Some(Compression::Zstd)
+		} else {
+			None
+		}
+	}
+
+	fn extension(&self) -> &'static str {
+		match *self {
+			Compression::Gzip => ".gz",
+			Compression::Zstd => ".zst",
+		}
+	}
+
+	fn decoder(&self, file: File) -> io::Result<Box<Read>> {
+		match *self {
+			Compression::Gzip => Ok(Box::new(GzDecoder::new(file))),
+			#[cfg(feature = "zstd")]
+			Compression::Zstd => Ok(Box::new(::zstd::stream::read::Decoder::new(file)?)),
+			#[cfg(not(feature = "zstd"))]
+			Compression::Zstd => Err(io::Error::new(io::ErrorKind::Other, "zstd support isn't built in")),
+		}
+	}
+}
+
+/// Returns the binary to run for the installed update `exe`, unpacking it first if it's compressed.
+///
+/// A binary unpacked before is reused as long as it matches the expected hash. A read-only
+/// launcher doesn't unpack anything.
+pub fn unpack_update(launcher: &LauncherConfig, exe: &Path) -> Result<PathBuf, String> {
+	let (name, compression) = match compression_of(exe)? {
+		(name, Some(compression)) => (name, compression),
+		(_, None) => return Ok(exe.to_owned()),
+	};
+
+	let checksum_path = launcher.channel_path(LATEST_CHECKSUM_FILE);
+	let expected = read_checksum(&checksum_path)?;
+	let unpacked = unpacked_name(&name, compression);
+	let target = launcher.updates_path(&unpacked);
+	if checksum(&target).ok() == Some(expected) {
+		trace_main!("Running {} unpacked before from {}", target.display(), exe.display());
+		return Ok(target);
+	}
+	if !launcher.can_write_state() {
+		return Err(format!("can't unpack {} without writing to {}", exe.display(), launcher.updates_dir.display()));
+	}
+
+	trace_main!("Unpacking {:?}-compressed update {} to {}", compression, exe.display(), target.display());
+	let partial = launcher.updates_path(&format!("{}.partial", unpacked));
+	let result = unpack(compression, exe, &partial).and_then(|actual| if actual == expected {
+		make_executable(&partial).and_then(|_| fs::rename(&partial, &target)).map_err(|e| e.to_string())
+	} else {
+		Err(format!("unpacked binary doesn't match the hash in {}", checksum_path.display()))
+	});
+	if result.is_err() {
+		let _ = fs::remove_file(&partial);
+	}
+	result.map(|_| target)
+}
+
+/// Whether the binary run for the installed update `exe` matches the hash in `latest.keccak`,
+/// changing nothing on disk.
+///
+/// A compressed update is checked by the binary unpacked from it, so it's only verified once it
+/// was unpacked. Without a hash to check against, nothing is verified.
+pub fn is_checksum_verified(launcher: &LauncherConfig, exe: &Path) -> bool {
+	let target = match compression_of(exe) {
+		Ok((name, Some(compression))) => launcher.updates_path(&unpacked_name(&name, compression)),
+		Ok((_, None)) => exe.to_owned(),
+		Err(_) => return false,
+	};
+	match read_checksum(&launcher.channel_path(LATEST_CHECKSUM_FILE)) {
+		Ok(expected) => checksum(&target).ok() == Some(expected),
+		Err(_) => false,
+	}
+}
+
+/// File name of `exe` and its compression, if it's compressed.
+fn compression_of(exe: &Path) -> Result<(String, Option<Compression>), String> {
+	let name = exe.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+	let mut magic = [0u8; 4];
+	let read = File::open(exe).and_then(|mut f| f.read(&mut magic)).map_err(|e| format!("couldn't read {}: {}", exe.display(), e))?;
+	let compression = Compression::detect(&name, &magic[..read]);
+	Ok((name, compression))
+}
+
+/// Name of the binary unpacked from the compressed update `name`.
+fn unpacked_name(name: &str, compression: Compression) -> String {
+	let extension = compression.extension();
+	if name.ends_with(extension) && name.len() > extension.len() {
+		name[..name.len() - extension.len()].to_owned()
+	} else {
+		format!("{}.unpacked", name)
+	}
+}
+
+/// Streams the unpacked `exe` to `to`, returning its hash.
+fn unpack(compression: Compression, exe: &Path, to: &Path) -> Result<[u8; 32], String> {
+	let mut decoder = BufReader::new(File::open(exe).and_then(|f| compression.decoder(f)).map_err(|e| e.to_string())?);
+	let mut output = File::create(to).map_err(|e| format!("couldn't write {}: {}", to.display(), e))?;
+	let hash = keccak_pipe(&mut decoder, &mut output).map_err(|e| format!("couldn't unpack {}: {}", exe.display(), e))?;
+	output.sync_all().map_err(|e| format!("couldn't write {}: {}", to.display(), e))?;
+	Ok(hash_bytes(&hash))
+}
+
+fn checksum(path: &Path) -> io::Result<[u8; 32]> {
+	keccak_buffer(&mut BufReader::new(File::open(path)?)).map(|hash| hash_bytes(&hash))
+}
+
+fn hash_bytes(hash: &[u8]) -> [u8; 32] {
+	let mut bytes = [0u8; 32];
+	bytes.copy_from_slice(&hash[..32]);
+	bytes
+}
+
+/// Reads the hex hash in `path`, with or without a `0x` prefix.
+fn read_checksum(path: &Path) -> Result<[u8; 32], String> {
+	let mut content = String::new();
+	File::open(path)
+		.and_then(|mut f| f.read_to_string(&mut content))
+		.map_err(|e| format!("couldn't read the expected hash from {}: {}", path.display(), e))?;
+	parse_checksum(content.trim()).ok_or_else(|| format!("invalid hash in {}", path.display()))
+}
+
+fn parse_checksum(hex: &str) -> Option<[u8; 32]> {
+	let hex = hex.trim_left_matches("0x");
+	if hex.len() != 64 {
+		return None;
+	}
+	let mut bytes = [0u8; 32];
+	for (i, byte) in bytes.iter_mut().enumerate() {
+		*byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+	}
+	Some(bytes)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+	fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::{Read, Write};
+	use std::path::Path;
+	use flate2::Compression as GzLevel;
+	use flate2::write::GzEncoder;
+	use hash::keccak;
+	use tempdir::TempDir;
+	use launcher::LauncherConfig;
+	use super::{parse_checksum, unpack_update, Compression, LATEST_CHECKSUM_FILE};
+
+	const BINARY: &[u8] = b"#!/bin/sh\nexit 0\n";
+
+	fn config(dir: &Path) -> LauncherConfig {
+		LauncherConfig::new(vec!["parity".into()], None, dir.to_owned()).unwrap()
+	}
+
+	fn stage_gzip(dir: &Path, name: &str, content: &[u8], hashed: &[u8]) {
+		let mut encoder = GzEncoder::new(File::create(dir.join(name)).unwrap(), GzLevel::default());
+		encoder.write_all(content).unwrap();
+		encoder.finish().unwrap();
+		let hex: String = keccak(hashed).iter().map(|b| format!("{:02x}", b)).collect();
+		File::create(dir.join(LATEST_CHECKSUM_FILE)).unwrap().write_all(hex.as_bytes()).unwrap();
+	}
+
+	fn read(path: &Path) -> Vec<u8> {
+		let mut content = Vec::new();
+		File::open(path).unwrap().read_to_end(&mut content).unwrap();
+		content
+	}
+
+	#[test]
+	fn should_detect_compression_by_name_or_magic() {
+		assert_eq!(Compression::detect("parity-1.12.0.gz", b"\x7fELF"), Some(Compression::Gzip));
+		assert_eq!(Compression::detect("parity-1.12.0", &[0x1f, 0x8b, 8, 0]), Some(Compression::Gzip));
+		assert_eq!(Compression::detect("parity-1.12.0.zst", b""), Some(Compression::Zstd));
+		assert_eq!(Compression::detect("parity-1.12.0", &[0x28, 0xb5, 0x2f, 0xfd]), Some(Compression::Zstd));
+		assert_eq!(Compression::detect("parity-1.12.0", b"\x7fELF"), None);
+	}
+
+	#[test]
+	fn should_unpack_gzip_update_matching_its_hash() {
+		let tempdir = TempDir::new("").unwrap();
+		stage_gzip(tempdir.path(), "parity-1.12.0.gz", BINARY, BINARY);
+		let launcher = config(tempdir.path());
+
+		let binary = unpack_update(&launcher, &tempdir.path().join("parity-1.12.0.gz")).unwrap();
+		assert_eq!(binary, tempdir.path().join("parity-1.12.0"));
+		assert_eq!(read(&binary), BINARY);
+		assert!(!tempdir.path().join("parity-1.12.0.partial").exists());
+		// unpacked once, then reused
+		assert_eq!(unpack_update(&launcher, &tempdir.path().join("parity-1.12.0.gz")), Ok(binary));
+	}
+
+	#[test]
+	fn should_refuse_unpacked_update_not_matching_its_hash() {
+		let tempdir = TempDir::new("").unwrap();
+		stage_gzip(tempdir.path(), "parity-1.12.0.gz", BINARY, b"another binary");
+
+		assert!(unpack_update(&config(tempdir.path()), &tempdir.path().join("parity-1.12.0.gz")).is_err());
+		assert!(!tempdir.path().join("parity-1.12.0").exists());
+		assert!(!tempdir.path().join("parity-1.12.0.partial").exists());
+	}
+
+	#[test]
+	fn should_run_uncompressed_update_as_is() {
+		let tempdir = TempDir::new("").unwrap();
+		let exe = tempdir.path().join("parity-1.12.0");
+		File::create(&exe).unwrap().write_all(BINARY).unwrap();
+		assert_eq!(unpack_update(&config(tempdir.path()), &exe), Ok(exe));
+	}
+
+	#[test]
+	fn should_parse_hex_checksums() {
+		let hex = "0x".to_owned() + &"ab".repeat(32);
+		assert_eq!(parse_checksum(&hex), Some([0xab; 32]));
+		assert_eq!(parse_checksum(&hex[2..]), Some([0xab; 32]));
+		assert_eq!(parse_checksum("abcd"), None);
+		assert_eq!(parse_checksum(&"zz".repeat(32)), None);
+	}
+}
@@ -0,0 +1,1291 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Launcher configuration.
+
+use std::{env, mem};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use dir::{default_hypervisor_path, try_default_hypervisor_path};
+use rand::{self, Rng};
+use semver::Version;
+use super::merge_sticky_args;
+use super::args::expand_response_files;
+use super::config_file::{read_config_file, LAUNCHER_CONFIG_FILE, LAUNCHER_CONFIG_FLAG};
+use super::fd_limit::FdLimit;
+use super::http::parse_http_url;
+use super::output::OutputFormat;
+use super::selftest::SELFTEST_EXIT_FLAG;
+use super::updates::{UpdateChannel, CANDIDATE_FILE, LATEST_FILE};
+use super::verify::check_executable;
+
+/// Default number of installed binaries kept by `--prune-updates`, besides the pinned ones.
+const DEFAULT_PRUNE_KEEP: usize = 2;
+
+/// Default time an update on trial in a slot has to run for before being promoted.
+const DEFAULT_SLOT_PROMOTE_AFTER_SECS: u64 = 10 * 60;
+
+/// Default time a restarted node waits for the previous one's ports to be released, in seconds.
+const DEFAULT_RESTART_PORT_TIMEOUT_SECS: u64 = 10;
+
+/// Chain the node runs without `--chain`.
+const DEFAULT_CHAIN: &str = "foundation";
+
+/// Chain selected by `--testnet`.
+const TESTNET_CHAIN: &str = "testnet";
+
+/// Default time since it was last modified an installed update has to wait before it's run, in seconds.
+const DEFAULT_UPDATE_SETTLE_TIME_SECS: u64 = 5;
+
+/// Default number of identical panics in a row after which the node isn't relaunched anymore.
+const DEFAULT_MAX_IDENTICAL_PANICS: u32 = 3;
+
+/// Default time a supervised child has to exit after each termination signal it's sent, in seconds.
+const DEFAULT_STOP_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of consecutive failed liveness probes before the watchdog restarts the node.
+const DEFAULT_WATCHDOG_MISSES: u32 = 3;
+
+/// Environment variable handing the watchdog interval (in seconds) down to the node process.
+const WATCHDOG_INTERVAL_ENV: &str = "PARITY_WATCHDOG_INTERVAL";
+
+/// Environment variable handing the watchdog miss threshold down to the node process.
+const WATCHDOG_MISSES_ENV: &str = "PARITY_WATCHDOG_MISSES";
+
+/// Environment variable handing the restart grace delay (in milliseconds) down to the node process.
+const RESTART_GRACE_ENV: &str = "PARITY_RESTART_GRACE_MS";
+
+/// Environment variable handing the spec switch cooldown (in seconds) down to the node process.
+const SPEC_SWITCH_COOLDOWN_ENV: &str = "PARITY_SPEC_SWITCH_COOLDOWN";
+
+/// Environment variable handing the exit wait timeout (in seconds) down to the node process.
+const EXIT_WAIT_TIMEOUT_ENV: &str = "PARITY_EXIT_WAIT_TIMEOUT";
+
+/// Environment variable handing the launcher log file down to the node process.
+const LAUNCHER_LOG_ENV: &str = "PARITY_LAUNCHER_LOG";
+
+/// Environment variable handing the restart exit code down to a child.
+const RESTART_EXIT_CODE_ENV: &str = "PARITY_RESTART_EXIT_CODE";
+
+/// Exit code the node uses to ask the launcher for a restart, unless configured otherwise.
+const DEFAULT_RESTART_EXIT_CODE: i32 = 69;
+
+/// Range of exit codes allowed for restarts: `0` means success, and shells reserve the codes above.
+const RESTART_EXIT_CODES: (i32, i32) = (1, 125);
+
+/// Environment variable handing the launch ID down to a child, so its logs can be told apart.
+const LAUNCH_ID_ENV: &str = "PARITY_LAUNCH_ID";
+
+/// Smallest address space limit the node can start within, in bytes.
+const MIN_CHILD_RLIMIT_AS: u64 = 64 * 1024 * 1024;
+
+/// Environment variable handing the output format of commands down to a child.
+const OUTPUT_FORMAT_ENV: &str = "PARITY_OUTPUT_FORMAT";
+
+/// Environment variable handing the open files limit setting down to a child.
+const FD_LIMIT_ENV: &str = "PARITY_FD_LIMIT";
+
+/// Environment variable handing the file log levels are reloaded from on `SIGHUP` down to a child.
+const LOG_RELOAD_FILE_ENV: &str = "PARITY_LOG_RELOAD_FILE";
+
+/// Environment variable handing the file the node records its effective chain in down to a child.
+const EFFECTIVE_CHAIN_FILE_ENV: &str = "PARITY_EFFECTIVE_CHAIN_FILE";
+
+/// Environment variable telling a child to restart the node when its config file changes.
+const RESTART_ON_CONFIG_CHANGE_ENV: &str = "PARITY_RESTART_ON_CONFIG_CHANGE";
+
+/// Environment variable telling a child to keep launcher messages off stdout and stderr.
+const QUIET_ENV: &str = "PARITY_QUIET_LAUNCHER";
+
+/// Environment variable telling a child the launcher can't write to the updates directory.
+const READONLY_ENV: &str = "PARITY_LAUNCHER_READONLY";
+
+/// Flags handled by the launcher and never forwarded to the node, with whether each takes a value.
+const LAUNCHER_FLAGS: &[(&str, bool)] = &[
+	("--warm-restart", false),
+	("--watchdog-interval", true),
+	("--watchdog-misses", true),
+	("--restart-grace-ms", true),
+	("--max-lifetime", true),
+	("--exit-wait-timeout", true),
+	("--log-launcher-to", true),
+	("--log-reload-file", true),
+	("--write-effective-chain", true),
+	("--post-exit-cmd", true),
+	("--child-env", true),
+	("--fallback-binary", true),
+	("--max-update-version", true),
+	("--allow-downgrade", false),
+	("--slot-promote-after", true),
+	("--update-check-only", false),
+	("--apply-update-now", false),
+	("--print-child-args", false),
+	("--emit-launch-plan", false),
+	("--explain-launch-detection", false),
+	("--verify-only", false),
+	("--verify-update-bundle", true),
+	("--windows-service", false),
+	("--launcher-pidfile", true),
+	("--child-pidfile", true),
+	("--decision-socket", true),
+	("--restart-exit-code", true),
+	("--max-identical-panics", true),
+	("--crash-grace", true),
+	("--no-indirect", false),
+	("--update-notify-url", true),
+	("--update-health-url", true),
+	("--use-binary", true),
+	("--output-format", true),
+	("--child-rlimit-as", true),
+	("--child-rlimit-cpu", true),
+	("--fd-limit", true),
+	("--launcher-config", true),
+	("--restart-port-timeout", true),
+	("--spec-switch-cooldown", true),
+	("--restart-jitter", true),
+	("--launcher-status-addr", true),
+	("--launcher-readonly", false),
+	("--quiet-launcher", false),
+	("--supervise", false),
+	("--capture-crash-output", false),
+	("--update-channel", true),
+	("--update-settle-time", true),
+	("--expected-channel", true),
+	("--stop-timeout", true),
+	("--restart-on-config-change", false),
+	("--prune-updates", false),
+	("--launcher-selftest", false),
+];
+
+/// Commands handled by the launcher itself, without starting the node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LauncherCommand {
+	/// Remove stale launcher state and old binaries from the updates directory.
+	CleanState {
+		/// Keep the binary `latest` points to and the one installed before it.
+		keep_latest: bool,
+	},
+	/// Remove old installed binaries from the updates directory.
+	PruneUpdates {
+		/// Number of most recent binaries to keep, besides the running one and those pointed to.
+		keep: usize,
+	},
+	/// Check the update plumbing against a throwaway updates directory.
+	SelfTest,
+	/// Let a launcher running with `--update-check-only` switch to the staged update on the node's
+	/// next restart.
+	ApplyUpdateNow,
+	/// Print the arguments an installed update would be run with.
+	PrintChildArgs,
+	/// Print what the launcher determined before running the node as JSON, without running it.
+	EmitLaunchPlan,
+	/// Print how the launcher was detected to be started, and whether it looks for updates as a
+	/// result.
+	ExplainLaunchDetection,
+	/// Check the installed update without running or changing anything.
+	VerifyOnly,
+	/// Check an update bundle in the given directory the way an installed update is, without
+	/// installing it.
+	VerifyUpdateBundle(PathBuf),
+	/// Exit right away with the given code, standing in for the node during the self-test.
+	SelfTestExit(i32),
+	/// Exit right away with the restart exit code, for testing that a supervisor relaunches the node.
+	///
+	/// A test aid only: no chain switch happens and no launcher state is touched.
+	EmitRestartCode,
+}
+
+/// Launcher settings, gathered once at startup from the command line and the environment.
+///
+/// Launcher-only flags are taken out of the command line; the remaining node arguments are
+/// parsed separately by `parity::Configuration::parse_cli`. Settings needed by the node process
+/// itself are handed down to a re-executed child through the environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LauncherConfig {
+	/// Node command line, including the executable name.
+	pub args: Vec<String>,
+	/// Path of the running executable, if it could be determined.
+	pub current_exe: Option<PathBuf>,
+	/// Directory holding installed updates and launcher state.
+	pub updates_dir: PathBuf,
+	/// Why the default updates directory couldn't be determined, leaving `updates_dir` unusable.
+	pub updates_dir_error: Option<String>,
+	/// Working directory the launcher was started in, which every child is run in.
+	pub working_dir: Option<PathBuf>,
+	/// Run the current executable, ignoring any installed updates.
+	pub force_direct: bool,
+	/// Run the node in this very process, for profilers and debuggers to attach to.
+	///
+	/// Unlike `--force-direct`, launcher state such as chain overrides is ignored and restarts
+	/// requested by the node are refused, so the process never hands over to another one.
+	pub no_indirect: bool,
+	/// The updates directory is unusable, so updates and launcher state are ignored.
+	pub updates_disabled: bool,
+	/// The updates directory can't be written to, e.g. on a read-only root filesystem.
+	///
+	/// Installed updates are still run, but no launcher state is written and no directory is
+	/// created. Restarts rerun the node with the settings the launcher was started with, handed
+	/// down to each child through the environment, so a chain switch or arguments the node wants
+	/// to keep don't survive a restart.
+	pub readonly: bool,
+	/// Keep the launcher's own messages off stdout and stderr, so only the node's output appears there.
+	pub quiet: bool,
+	/// Always run the node as a child the launcher stays around for, relaunching it after a crash
+	/// and forwarding termination signals to it.
+	pub supervise: bool,
+	/// Pipe the node's stderr through the launcher, logging its last few kilobytes when the node
+	/// ends abnormally.
+	pub capture_crash_output: bool,
+	/// Time the supervised child has to exit after a forwarded signal, and then after `SIGTERM`,
+	/// before it's sent `SIGTERM`, and then killed.
+	pub stop_timeout: Duration,
+	/// Have the node hand its listening sockets over to the restarted child instead of letting it
+	/// bind anew.
+	pub warm_restart: bool,
+	/// How often the watchdog probes the running node, if at all.
+	pub watchdog_interval: Option<Duration>,
+	/// Number of consecutive failed probes after which the node is restarted.
+	pub watchdog_misses: u32,
+	/// Exit code the node uses to ask for a restart.
+	pub restart_exit_code: i32,
+	/// Number of identical panics in a row after which the node isn't relaunched anymore.
+	pub max_identical_panics: u32,
+	/// A node exiting with a nonzero code sooner than this after being started is taken for a crash.
+	pub crash_grace: Duration,
+	/// Delay between the node shutdown and asking for a restart, letting resources be released.
+	pub restart_grace: Duration,
+	/// Restart the node once its config file was edited, to run it with the new settings.
+	pub restart_on_config_change: bool,
+	/// Time a restarted node waits for the previous one's listening ports to be released.
+	pub restart_port_timeout: Duration,
+	/// Longest random delay before restarting the node into an update.
+	pub restart_jitter: Duration,
+	/// Minimum time between chain switches honoured by the node, ignoring requests made sooner.
+	pub spec_switch_cooldown: Duration,
+	/// Time after which a node that never asked to exit is shut down anyway, as a safety net.
+	pub exit_wait_timeout: Option<Duration>,
+	/// Time after which the launcher stops restarting the node and exits.
+	pub max_lifetime: Option<Duration>,
+	/// File the launcher's own messages are appended to.
+	pub log_launcher_to: Option<PathBuf>,
+	/// File the node reads its log levels from on `SIGHUP`, instead of the startup `RUST_LOG`.
+	pub log_reload_file: Option<PathBuf>,
+	/// File the node records the chain it ends up running in as it starts, overrides included.
+	pub effective_chain_file: Option<PathBuf>,
+	/// Command run after the node exits, given the exit reason and code.
+	pub post_exit_cmd: Option<String>,
+	/// Extra environment variables set for the re-executed child only.
+	pub extra_child_env: Vec<(String, String)>,
+	/// Binaries run in the order given when the installed update fails, before the current binary.
+	pub fallback_binaries: Vec<PathBuf>,
+	/// Random ID of the current launch, shared by the launcher and its child for log stitching.
+	pub launch_id: Option<String>,
+	/// Time an update on trial in a slot has to run for before being promoted.
+	pub slot_promote_after: Duration,
+	/// File the launcher writes its own PID to.
+	pub launcher_pidfile: Option<PathBuf>,
+	/// File holding the PID of the running child, while there is one.
+	pub child_pidfile: Option<PathBuf>,
+	/// Unix socket each update decision is reported to, as a JSON line.
+	pub decision_socket: Option<PathBuf>,
+	/// Run as a Windows service, stopped through the service control manager.
+	pub windows_service: bool,
+	/// Binary run instead of the one `latest` points to.
+	pub use_binary: Option<PathBuf>,
+	/// Local address the launcher serves its state on as JSON, over HTTP.
+	pub launcher_status_addr: Option<String>,
+	/// `http://` URL notified of installed updates which aren't run.
+	pub update_notify_url: Option<String>,
+	/// Address space limit of the re-executed child, in bytes.
+	///
+	/// Resource limits only apply to a child, and so not to a node run directly.
+	pub child_rlimit_as: Option<u64>,
+	/// CPU time limit of the re-executed child, in seconds.
+	pub child_rlimit_cpu: Option<u64>,
+	/// How the node raises its limit on open files.
+	pub fd_limit: FdLimit,
+	/// How the output of commands which don't start a node is printed.
+	pub output_format: OutputFormat,
+	/// `http://` URL of the node a freshly started update must answer successfully within a few minutes.
+	pub update_health_url: Option<String>,
+	/// Only report staged updates, switching to them only once asked with `--apply-update-now`.
+	pub update_check_only: bool,
+	/// Highest update version the launcher is allowed to run.
+	pub max_update_version: Option<Version>,
+	/// Channel of updates to run, `stable` behind `latest` or `candidate` behind `candidate`.
+	pub update_channel: UpdateChannel,
+	/// Time since it was last modified an installed update has to wait before it's run, so a
+	/// binary still being written isn't started.
+	pub update_settle_time: Duration,
+	/// Release channel, e.g. `stable`, updates have to be built for to be run.
+	pub expected_channel: Option<String>,
+	/// Run an update even if its version is below the current one.
+	pub allow_downgrade: bool,
+	/// Command to run instead of starting the node.
+	pub command: Option<LauncherCommand>,
+}
+
+impl LauncherConfig {
+	/// Creates launcher configuration from the given command line.
+	pub fn new(args: Vec<String>, current_exe: Option<PathBuf>, updates_dir: PathBuf) -> Result<Self, String> {
+		let force_direct = args.iter().skip(1).any(|arg| arg == "--force-direct");
+		let mut warm_restart = false;
+		let mut watchdog_interval = None;
+		let mut watchdog_misses = None;
+		let mut restart_grace = Duration::from_millis(0);
+		let mut restart_port_timeout = Duration::from_secs(DEFAULT_RESTART_PORT_TIMEOUT_SECS);
+		let mut spec_switch_cooldown = Duration::from_secs(0);
+		let mut restart_jitter = Duration::from_secs(0);
+		let mut max_update_version = None;
+		let mut update_channel = UpdateChannel::default();
+		let mut update_settle_time = Duration::from_secs(DEFAULT_UPDATE_SETTLE_TIME_SECS);
+		let mut expected_channel = None;
+		let mut allow_downgrade = false;
+		let mut slot_promote_after = Duration::from_secs(DEFAULT_SLOT_PROMOTE_AFTER_SECS);
+		let mut update_check_only = false;
+		let mut windows_service = false;
+		let mut launcher_pidfile = None;
+		let mut child_pidfile = None;
+		let mut decision_socket = None;
+		let mut restart_exit_code = None;
+		let mut max_identical_panics = DEFAULT_MAX_IDENTICAL_PANICS;
+		let mut crash_grace = Duration::from_millis(0);
+		let mut no_indirect = false;
+		let mut readonly = false;
+		let mut quiet = false;
+		let mut supervise = false;
+		let mut capture_crash_output = false;
+		let mut stop_timeout = Duration::from_secs(DEFAULT_STOP_TIMEOUT_SECS);
+		let mut restart_on_config_change = false;
+		let mut update_notify_url = None;
+		let mut launcher_status_addr = None;
+		let mut update_health_url = None;
+		let mut use_binary = None;
+		let mut output_format = OutputFormat::default();
+		let mut child_rlimit_as = None;
+		let mut child_rlimit_cpu = None;
+		let mut fd_limit = FdLimit::default();
+		let mut max_lifetime = None;
+		let mut exit_wait_timeout = None;
+		let mut log_launcher_to = None;
+		let mut log_reload_file = None;
+		let mut effective_chain_file = None;
+		let mut post_exit_cmd = None;
+		let mut extra_child_env = Vec::new();
+		let mut fallback_binaries = Vec::new();
+		let mut command = None;
+		let mut prune = false;
+		let mut prune_keep = DEFAULT_PRUNE_KEEP;
+
+		let mut node_args = Vec::with_capacity(args.len());
+		let mut launcher_flags: Vec<(&'static str, String)> = Vec::new();
+		let mut args = args.into_iter();
+		node_args.extend(args.next());
+		while let Some(arg) = args.next() {
+			let (flag, value) = split_flag(&arg);
+			if flag == SELFTEST_EXIT_FLAG {
+				// kept on the command line, so that it reaches the fake update run by the self-test
+				let code = parse_value(flag, &flag_value(flag, value, &mut args)?)?;
+				node_args.push(format!("{}={}", SELFTEST_EXIT_FLAG, code));
+				command = Some(LauncherCommand::SelfTestExit(code));
+				continue;
+			}
+
+			let prune_seen = launcher_flags.iter().any(|&(f, _)| f == "--prune-updates");
+			match launcher_flag(flag, prune_seen) {
+				Some((name, true)) => launcher_flags.push((name, flag_value(flag, value, &mut args)?)),
+				Some((name, false)) => launcher_flags.push((name, String::new())),
+				None => node_args.push(arg.clone()),
+			}
+		}
+
+		for (flag, value) in launcher_flags {
+			match flag {
+				"--warm-restart" => warm_restart = true,
+				"--watchdog-interval" => watchdog_interval = Some(Duration::from_secs(parse_value(flag, &value)?)),
+				"--watchdog-misses" => watchdog_misses = Some(parse_value(flag, &value)?),
+				"--restart-grace-ms" => restart_grace = Duration::from_millis(parse_value(flag, &value)?),
+				"--restart-port-timeout" => restart_port_timeout = parse_duration(flag, &value)?,
+				"--spec-switch-cooldown" => spec_switch_cooldown = parse_duration(flag, &value)?,
+				"--restart-jitter" => restart_jitter = parse_duration(flag, &value)?,
+				"--max-lifetime" => max_lifetime = Some(parse_duration(flag, &value)?),
+				"--exit-wait-timeout" => exit_wait_timeout = Some(parse_duration(flag, &value)?),
+				"--log-launcher-to" => log_launcher_to = Some(value.into()),
+				"--log-reload-file" => log_reload_file = Some(value.into()),
+				"--write-effective-chain" => effective_chain_file = Some(value.into()),
+				"--post-exit-cmd" => post_exit_cmd = Some(value),
+				"--child-env" => extra_child_env.push(parse_env_var(flag, &value)?),
+				"--fallback-binary" => fallback_binaries.push(PathBuf::from(value)),
+				"--max-update-version" => max_update_version = Some(parse_value(flag, &value)?),
+				"--update-channel" => update_channel = parse_value(flag, &value)?,
+				"--update-settle-time" => update_settle_time = parse_duration(flag, &value)?,
+				"--expected-channel" => expected_channel = Some(parse_channel_name(flag, &value)?),
+				"--allow-downgrade" => allow_downgrade = true,
+				"--slot-promote-after" => slot_promote_after = parse_duration(flag, &value)?,
+				"--update-check-only" => update_check_only = true,
+				"--windows-service" => windows_service = true,
+				"--launcher-pidfile" => launcher_pidfile = Some(value.into()),
+				"--child-pidfile" => child_pidfile = Some(value.into()),
+				"--decision-socket" => decision_socket = Some(value.into()),
+				"--restart-exit-code" => restart_exit_code = Some(parse_restart_exit_code(flag, &value)?),
+				"--max-identical-panics" => max_identical_panics = parse_value(flag, &value)?,
+				"--crash-grace" => crash_grace = Duration::from_millis(parse_value(flag, &value)?),
+				"--no-indirect" => no_indirect = true,
+				"--launcher-readonly" => readonly = true,
+				"--quiet-launcher" => quiet = true,
+				"--supervise" => supervise = true,
+				"--capture-crash-output" => capture_crash_output = true,
+				"--stop-timeout" => stop_timeout = parse_duration(flag, &value)?,
+				"--restart-on-config-change" => restart_on_config_change = true,
+				"--use-binary" => use_binary = Some(PathBuf::from(value)),
+				"--output-format" => output_format = parse_value(flag, &value)?,
+				"--child-rlimit-as" => child_rlimit_as = Some(parse_rlimit(flag, &value, MIN_CHILD_RLIMIT_AS)?),
+				"--child-rlimit-cpu" => child_rlimit_cpu = Some(parse_rlimit(flag, &value, 1)?),
+				"--fd-limit" => fd_limit = parse_value(flag, &value)?,
+				"--launcher-status-addr" => launcher_status_addr = Some(value),
+				"--update-notify-url" => {
+					parse_http_url(&value)?;
+					update_notify_url = Some(value);
+				},
+				"--update-health-url" => {
+					parse_http_url(&value)?;
+					update_health_url = Some(value);
+				},
+				"--apply-update-now" => command = Some(LauncherCommand::ApplyUpdateNow),
+				"--print-child-args" => command = Some(LauncherCommand::PrintChildArgs),
+				"--emit-launch-plan" => command = Some(LauncherCommand::EmitLaunchPlan),
+				"--explain-launch-detection" => command = Some(LauncherCommand::ExplainLaunchDetection),
+				"--verify-only" => command = Some(LauncherCommand::VerifyOnly),
+				"--verify-update-bundle" => command = Some(LauncherCommand::VerifyUpdateBundle(value.into())),
+				"--prune-updates" => prune = true,
+				"--keep" => prune_keep = parse_value(flag, &value)?,
+				"--launcher-selftest" => command = Some(LauncherCommand::SelfTest),
+				// already read by `from_env`
+				"--launcher-config" => {},
+				_ => unreachable!("every flag in LAUNCHER_FLAGS is handled; qed"),
+			}
+		}
+
+		if warm_restart && !cfg!(unix) {
+			warn_main!("Warm restart is not supported on this platform, falling back to cold restart.");
+			warm_restart = false;
+		}
+
+		if decision_socket.is_some() && !cfg!(unix) {
+			warn_main!("Reporting update decisions is only supported on Unix, ignoring --decision-socket.");
+			decision_socket = None;
+		}
+
+		if (child_rlimit_as.is_some() || child_rlimit_cpu.is_some()) && !cfg!(unix) {
+			warn_main!("Limiting the child's resources is only supported on Unix, ignoring --child-rlimit-as and --child-rlimit-cpu.");
+			child_rlimit_as = None;
+			child_rlimit_cpu = None;
+		}
+
+		if let FdLimit::Files(_) = fd_limit {
+			if !cfg!(unix) {
+				warn_main!("Setting a specific open files limit is only supported on Unix, raising it as far as possible instead.");
+				fd_limit = FdLimit::Max;
+			}
+		}
+
+		if windows_service && !cfg!(windows) {
+			warn_main!("Running as a Windows service is only supported on Windows, ignoring --windows-service.");
+			windows_service = false;
+		}
+
+		let watchdog_misses = watchdog_misses.unwrap_or(DEFAULT_WATCHDOG_MISSES);
+		if watchdog_misses == 0 {
+			return Err("--watchdog-misses must be at least 1".into());
+		}
+
+		if let Some(ref path) = use_binary {
+			if force_direct || no_indirect {
+				return Err("--use-binary can't be combined with --force-direct or --no-indirect".into());
+			}
+			if !path.is_file() {
+				return Err(format!("Binary given to --use-binary doesn't exist: {}", path.display()));
+			}
+			check_executable(path).map_err(|e| format!("Binary given to --use-binary can't be run ({}): {}", e, path.display()))?;
+		}
+
+		if supervise && (force_direct || no_indirect) {
+			return Err("--supervise can't be combined with --force-direct or --no-indirect".into());
+		}
+
+		if max_identical_panics == 0 {
+			return Err("--max-identical-panics must be at least 1".into());
+		}
+
+		if prune {
+			command = Some(LauncherCommand::PruneUpdates { keep: prune_keep });
+		}
+
+		if command.is_none() {
+			command = match node_args.get(1).map(String::as_str) {
+				Some("clean-launcher-state") => Some(parse_clean_state(&node_args[2..])?),
+				Some("emit-restart-code") if node_args.len() == 2 => Some(LauncherCommand::EmitRestartCode),
+				Some("emit-restart-code") => return Err(format!("Unknown option for emit-restart-code: {}", node_args[2])),
+				_ => None,
+			};
+		}
+
+		Ok(LauncherConfig {
+			args: node_args,
+			current_exe,
+			updates_dir,
+			updates_dir_error: None,
+			working_dir: None,
+			force_direct: force_direct || no_indirect,
+			no_indirect,
+			updates_disabled: false,
+			readonly,
+			quiet,
+			supervise,
+			capture_crash_output,
+			stop_timeout,
+			warm_restart,
+			watchdog_interval,
+			watchdog_misses,
+			restart_grace,
+			restart_on_config_change,
+			restart_port_timeout,
+			restart_jitter,
+			spec_switch_cooldown,
+			exit_wait_timeout,
+			max_lifetime,
+			log_launcher_to,
+			log_reload_file,
+			effective_chain_file,
+			post_exit_cmd,
+			extra_child_env,
+			fallback_binaries,
+			launch_id: None,
+			slot_promote_after,
+			launcher_pidfile,
+			child_pidfile,
+			decision_socket,
+			restart_exit_code: restart_exit_code.unwrap_or(DEFAULT_RESTART_EXIT_CODE),
+			max_identical_panics,
+			crash_grace,
+			windows_service,
+			use_binary,
+			launcher_status_addr,
+			update_notify_url,
+			update_health_url,
+			child_rlimit_as,
+			child_rlimit_cpu,
+			fd_limit,
+			output_format,
+			update_check_only,
+			max_update_version,
+			update_channel,
+			update_settle_time,
+			expected_channel,
+			allow_downgrade,
+			command,
+		})
+	}
+
+	/// Reads launcher configuration of the running process.
+	///
+	/// Settings are read from the file given to `--launcher-config`, or else from `launcher.toml`
+	/// in the default updates directory if there is one. The command line overrides them.
+	pub fn from_env() -> Result<Self, String> {
+		// expanded first, so every flag is seen wherever it's given
+		let args = expand_response_files(env::args().collect())?;
+		let file = match launcher_config_path(&args)? {
+			Some(path) => read_config_file(&path, true)?,
+			None => read_config_file(&default_hypervisor_path().join(LAUNCHER_CONFIG_FILE), false)?,
+		};
+		let (updates_dir, updates_dir_error) = match file.updates_dir.clone() {
+			Some(dir) => (dir, None),
+			None => match try_default_hypervisor_path() {
+				Ok(dir) => (dir, None),
+				Err(e) => (default_hypervisor_path(), Some(e)),
+			},
+		};
+		let mut launcher = Self::new(file.prepend_to(args), env::current_exe().ok(), updates_dir)?;
+		launcher.updates_dir_error = updates_dir_error;
+		launcher.working_dir = env::current_dir().ok();
+		launcher.apply_env(|key| env::var(key).ok())?;
+		Ok(launcher)
+	}
+
+	/// Fills in settings handed down by a parent launcher. Command line flags take precedence.
+	fn apply_env<F>(&mut self, var: F) -> Result<(), String> where F: Fn(&str) -> Option<String> {
+		if self.watchdog_interval.is_none() {
+			if let Some(secs) = var(WATCHDOG_INTERVAL_ENV) {
+				self.watchdog_interval = Some(Duration::from_secs(parse_value(WATCHDOG_INTERVAL_ENV, &secs)?));
+				if let Some(misses) = var(WATCHDOG_MISSES_ENV) {
+					self.watchdog_misses = parse_value(WATCHDOG_MISSES_ENV, &misses)?;
+				}
+			}
+		}
+
+		if self.restart_grace == Duration::from_millis(0) {
+			if let Some(ms) = var(RESTART_GRACE_ENV) {
+				self.restart_grace = Duration::from_millis(parse_value(RESTART_GRACE_ENV, &ms)?);
+			}
+		}
+
+		if self.spec_switch_cooldown == Duration::from_secs(0) {
+			if let Some(secs) = var(SPEC_SWITCH_COOLDOWN_ENV) {
+				self.spec_switch_cooldown = Duration::from_secs(parse_value(SPEC_SWITCH_COOLDOWN_ENV, &secs)?);
+			}
+		}
+
+		if self.exit_wait_timeout.is_none() {
+			if let Some(secs) = var(EXIT_WAIT_TIMEOUT_ENV) {
+				self.exit_wait_timeout = Some(Duration::from_secs(parse_value(EXIT_WAIT_TIMEOUT_ENV, &secs)?));
+			}
+		}
+
+		if self.log_launcher_to.is_none() {
+			self.log_launcher_to = var(LAUNCHER_LOG_ENV).map(Into::into);
+		}
+
+		if self.restart_exit_code == DEFAULT_RESTART_EXIT_CODE {
+			if let Some(code) = var(RESTART_EXIT_CODE_ENV) {
+				self.restart_exit_code = parse_restart_exit_code(RESTART_EXIT_CODE_ENV, &code)?;
+			}
+		}
+
+		if self.log_reload_file.is_none() {
+			self.log_reload_file = var(LOG_RELOAD_FILE_ENV).map(Into::into);
+		}
+
+		if self.effective_chain_file.is_none() {
+			self.effective_chain_file = var(EFFECTIVE_CHAIN_FILE_ENV).map(Into::into);
+		}
+
+		if self.launch_id.is_none() {
+			self.launch_id = var(LAUNCH_ID_ENV);
+		}
+
+		if self.output_format == OutputFormat::default() {
+			if let Some(format) = var(OUTPUT_FORMAT_ENV) {
+				self.output_format = parse_value(OUTPUT_FORMAT_ENV, &format)?;
+			}
+		}
+
+		if self.fd_limit == FdLimit::default() {
+			if let Some(limit) = var(FD_LIMIT_ENV) {
+				self.fd_limit = parse_value(FD_LIMIT_ENV, &limit)?;
+			}
+		}
+
+		if !self.readonly {
+			self.readonly = var(READONLY_ENV).map_or(false, |value| value == "1");
+		}
+
+		if !self.quiet {
+			self.quiet = var(QUIET_ENV).map_or(false, |value| value == "1");
+		}
+
+		if !self.restart_on_config_change {
+			self.restart_on_config_change = var(RESTART_ON_CONFIG_CHANGE_ENV).map_or(false, |value| value == "1");
+		}
+
+		Ok(())
+	}
+
+	/// Environment variables handing the settings needed by the node process to a child.
+	pub fn child_env(&self) -> Vec<(&'static str, String)> {
+		let mut vars = Vec::new();
+		if let Some(interval) = self.watchdog_interval {
+			vars.push((WATCHDOG_INTERVAL_ENV, interval.as_secs().to_string()));
+			vars.push((WATCHDOG_MISSES_ENV, self.watchdog_misses.to_string()));
+		}
+		if self.restart_grace != Duration::from_millis(0) {
+			let ms = self.restart_grace.as_secs() * 1000 + u64::from(self.restart_grace.subsec_nanos()) / 1_000_000;
+			vars.push((RESTART_GRACE_ENV, ms.to_string()));
+		}
+		if self.spec_switch_cooldown != Duration::from_secs(0) {
+			vars.push((SPEC_SWITCH_COOLDOWN_ENV, self.spec_switch_cooldown.as_secs().to_string()));
+		}
+		if let Some(timeout) = self.exit_wait_timeout {
+			vars.push((EXIT_WAIT_TIMEOUT_ENV, timeout.as_secs().to_string()));
+		}
+		if let Some(ref path) = self.log_launcher_to {
+			vars.push((LAUNCHER_LOG_ENV, path.to_string_lossy().into_owned()));
+		}
+		if self.restart_exit_code != DEFAULT_RESTART_EXIT_CODE {
+			vars.push((RESTART_EXIT_CODE_ENV, self.restart_exit_code.to_string()));
+		}
+		if let Some(ref path) = self.log_reload_file {
+			vars.push((LOG_RELOAD_FILE_ENV, path.to_string_lossy().into_owned()));
+		}
+		if let Some(ref path) = self.effective_chain_file {
+			vars.push((EFFECTIVE_CHAIN_FILE_ENV, path.to_string_lossy().into_owned()));
+		}
+		if let Some(ref id) = self.launch_id {
+			vars.push((LAUNCH_ID_ENV, id.clone()));
+		}
+		if self.output_format != OutputFormat::default() {
+			vars.push((OUTPUT_FORMAT_ENV, self.output_format.as_str().to_owned()));
+		}
+		if self.fd_limit != FdLimit::default() {
+			vars.push((FD_LIMIT_ENV, self.fd_limit.to_string()));
+		}
+		if self.readonly {
+			vars.push((READONLY_ENV, "1".to_owned()));
+		}
+		if self.quiet {
+			vars.push((QUIET_ENV, "1".to_owned()));
+		}
+		if self.restart_on_config_change {
+			vars.push((RESTART_ON_CONFIG_CHANGE_ENV, "1".to_owned()));
+		}
+		vars
+	}
+
+	/// Starts a new launch with a fresh ID, returning the ID of the previous one.
+	pub fn renew_launch_id(&mut self) -> Option<String> {
+		let id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+		mem::replace(&mut self.launch_id, Some(id))
+	}
+
+	/// Chain the node command line selects, without a chain override.
+	pub fn chain(&self) -> String {
+		let mut chain = DEFAULT_CHAIN.to_owned();
+		let mut args = self.node_args().iter();
+		while let Some(arg) = args.next() {
+			match split_flag(arg) {
+				("--chain", Some(value)) => chain = value.to_owned(),
+				("--chain", None) => chain = args.next().cloned().unwrap_or(chain),
+				("--testnet", None) => chain = TESTNET_CHAIN.to_owned(),
+				_ => {},
+			}
+		}
+		chain
+	}
+
+	/// Ignores updates and launcher state, running the current executable directly.
+	pub fn disable_updates(&mut self) {
+		self.updates_disabled = true;
+		self.force_direct = true;
+	}
+
+	/// Whether launcher state may be written to the updates directory.
+	pub fn can_write_state(&self) -> bool {
+		!self.updates_disabled && !self.readonly
+	}
+
+	/// Merges arguments the node kept across a restart into the node command line.
+	pub fn keep_sticky_args(&mut self, sticky: &[String]) {
+		if self.args.is_empty() || sticky.is_empty() {
+			return;
+		}
+
+		let merged = merge_sticky_args(&self.args[1..], sticky);
+		self.args.truncate(1);
+		self.args.extend(merged);
+	}
+
+	/// Arguments that should be forwarded to the node, without the executable name.
+	pub fn node_args(&self) -> &[String] {
+		if self.args.is_empty() { &self.args } else { &self.args[1..] }
+	}
+
+	/// Path of a file within the updates directory.
+	pub fn updates_path(&self, name: &str) -> PathBuf {
+		self.updates_dir.join(name)
+	}
+
+	/// Path of the file standing for `latest_file`, e.g. `latest.version`, on the update channel.
+	///
+	/// The files of the candidate channel are named after its pointer, e.g. `candidate.version`.
+	pub fn channel_path(&self, latest_file: &str) -> PathBuf {
+		match self.update_channel {
+			UpdateChannel::Candidate if latest_file.starts_with(LATEST_FILE) => {
+				self.updates_path(&format!("{}{}", CANDIDATE_FILE, &latest_file[LATEST_FILE.len()..]))
+			},
+			_ => self.updates_path(latest_file),
+		}
+	}
+}
+
+/// Path of the launcher config file given on command line `args`, if any.
+fn launcher_config_path(args: &[String]) -> Result<Option<PathBuf>, String> {
+	let mut path = None;
+	let mut args = args.iter().skip(1).cloned();
+	while let Some(arg) = args.next() {
+		let (flag, value) = split_flag(&arg);
+		if flag == LAUNCHER_CONFIG_FLAG {
+			path = Some(flag_value(flag, value, &mut args)?.into());
+		}
+	}
+	Ok(path)
+}
+
+fn parse_restart_exit_code(flag: &str, value: &str) -> Result<i32, String> {
+	let code = parse_value(flag, value)?;
+	let (min, max) = RESTART_EXIT_CODES;
+	if code < min || code > max {
+		return Err(format!("{} must be between {} and {}, got {}", flag, min, max, code));
+	}
+	Ok(code)
+}
+
+/// Parses a resource limit given to `flag`, which must be at least `min`.
+fn parse_rlimit(flag: &str, value: &str, min: u64) -> Result<u64, String> {
+	let limit = parse_value(flag, value)?;
+	if limit < min {
+		return Err(format!("{} must be at least {}, got {}", flag, min, limit));
+	}
+	Ok(limit)
+}
+
+fn parse_clean_state(args: &[String]) -> Result<LauncherCommand, String> {
+	let mut keep_latest = false;
+	for arg in args {
+		match arg.as_str() {
+			"--keep-latest" => keep_latest = true,
+			_ => return Err(format!("Unknown option for clean-launcher-state: {}", arg)),
+		}
+	}
+
+	Ok(LauncherCommand::CleanState { keep_latest })
+}
+
+/// Looks `flag` up in `LAUNCHER_FLAGS`, returning its canonical name and whether it takes a value.
+///
+/// `--keep` is only a launcher flag following `--prune-updates`.
+pub fn launcher_flag(flag: &str, prune_seen: bool) -> Option<(&'static str, bool)> {
+	if flag == "--keep" {
+		return if prune_seen { Some(("--keep", true)) } else { None };
+	}
+	LAUNCHER_FLAGS.iter().find(|&&(name, _)| name == flag).cloned()
+}
+
+/// Parses the release channel name given to `flag`, which can't be blank.
+fn parse_channel_name(flag: &str, value: &str) -> Result<String, String> {
+	match value.trim() {
+		"" => Err(format!("Invalid value for {}: a channel name is expected", flag)),
+		name => Ok(name.to_owned()),
+	}
+}
+
+/// Parses a duration given to `flag`, in seconds or with one of the `s`, `m`, `h` or `d` units.
+fn parse_duration(flag: &str, value: &str) -> Result<Duration, String> {
+	let (number, unit) = match value.find(|c: char| !c.is_digit(10)) {
+		Some(pos) => (&value[..pos], &value[pos..]),
+		None => (&value[..], "s"),
+	};
+	let multiplier = match unit {
+		"s" => 1,
+		"m" => 60,
+		"h" => 60 * 60,
+		"d" => 24 * 60 * 60,
+		_ => return Err(format!("Invalid unit for {}: {} (expected s, m, h or d)", flag, value)),
+	};
+	let number: u64 = parse_value(flag, number)?;
+	number.checked_mul(multiplier)
+		.map(Duration::from_secs)
+		.ok_or_else(|| format!("Invalid value for {}: {}", flag, value))
+}
+
+/// Parses a `KEY=VALUE` environment variable given to `flag`.
+fn parse_env_var(flag: &str, var: &str) -> Result<(String, String), String> {
+	match var.find('=') {
+		Some(pos) if pos > 0 && !var.contains('\0') => Ok((var[..pos].to_owned(), var[pos + 1..].to_owned())),
+		_ => Err(format!("Invalid value for {}: {} (expected KEY=VALUE)", flag, var)),
+	}
+}
+
+/// Splits `--flag=value` into the flag and its value.
+pub fn split_flag(arg: &str) -> (&str, Option<&str>) {
+	match arg.find('=') {
+		Some(pos) if arg.starts_with("--") => (&arg[..pos], Some(&arg[pos + 1..])),
+		_ => (arg, None),
+	}
+}
+
+/// Returns the value of `flag`, given either inline or as the next argument.
+fn flag_value<I>(flag: &str, value: Option<&str>, args: &mut I) -> Result<String, String> where I: Iterator<Item = String> {
+	match value {
+		Some(value) => Ok(value.to_owned()),
+		None => args.next().ok_or_else(|| format!("{} requires a value", flag)),
+	}
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+	value.parse().map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::path::PathBuf;
+	use std::time::Duration;
+	use semver::Version;
+	use super::{launcher_config_path, FdLimit, LauncherCommand, LauncherConfig, OutputFormat, UpdateChannel, LAUNCHER_FLAGS};
+
+	fn config(args: &[&str]) -> LauncherConfig {
+		try_config(args).unwrap()
+	}
+
+	fn try_config(args: &[&str]) -> Result<LauncherConfig, String> {
+		let args = args.iter().map(|s| s.to_string()).collect();
+		LauncherConfig::new(args, Some(PathBuf::from("/usr/bin/parity")), PathBuf::from("/updates"))
+	}
+
+	#[test]
+	fn should_validate_use_binary() {
+		let binary = env::current_exe().unwrap();
+		let binary = binary.to_str().unwrap();
+		let launcher = config(&["parity", "--use-binary", binary, "--chain", "kovan"]);
+		assert_eq!(launcher.use_binary, Some(PathBuf::from(binary)));
+		assert_eq!(launcher.node_args(), &["--chain".to_owned(), "kovan".to_owned()]);
+		assert!(try_config(&["parity", "--use-binary", "/nonexistent/parity"]).is_err());
+		assert!(try_config(&["parity", "--use-binary", binary, "--force-direct"]).is_err());
+		assert!(try_config(&["parity", "--no-indirect", "--use-binary", binary]).is_err());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_refuse_non_executable_use_binary() {
+		use std::fs::File;
+		use tempdir::TempDir;
+
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("parity");
+		File::create(&path).unwrap();
+		assert!(try_config(&["parity", "--use-binary", path.to_str().unwrap()]).is_err());
+	}
+
+	#[test]
+	fn should_detect_force_direct() {
+		assert!(!config(&["parity"]).force_direct);
+		assert!(!config(&["parity", "--chain", "kovan"]).force_direct);
+		assert!(config(&["parity", "--force-direct"]).force_direct);
+		assert!(!config(&["parity", "--force-direct"]).no_indirect);
+		assert!(config(&["parity", "--no-indirect"]).force_direct);
+		assert!(config(&["parity", "--no-indirect"]).no_indirect);
+	}
+
+	#[test]
+	fn should_take_out_launcher_flags() {
+		let launcher = config(&["parity", "--warm-restart", "--chain", "kovan", "--watchdog-interval", "5", "--watchdog-misses=2", "--post-exit-cmd", "/bin/alert"]);
+		assert_eq!(launcher.warm_restart, cfg!(unix));
+		assert_eq!(launcher.post_exit_cmd, Some("/bin/alert".to_owned()));
+		assert_eq!(launcher.watchdog_interval, Some(Duration::from_secs(5)));
+		assert_eq!(launcher.watchdog_misses, 2);
+		assert_eq!(launcher.args, vec!["parity".to_owned(), "--chain".to_owned(), "kovan".to_owned()]);
+	}
+
+	#[test]
+	fn should_not_forward_any_launcher_flag() {
+		let sample = |flag: &str| match flag {
+			"--log-launcher-to" | "--post-exit-cmd" => "/tmp/x",
+			"--child-env" => "KEY=VALUE",
+			"--max-update-version" => "1.12.0",
+			"--update-notify-url" | "--update-health-url" => "http://localhost/",
+			"--output-format" => "json",
+			"--update-channel" => "candidate",
+			"--child-rlimit-as" => "1073741824",
+			_ => "5",
+		};
+		let mut args = vec!["parity".to_owned(), "--chain".to_owned(), "kovan".to_owned()];
+		// --use-binary and --supervise conflict with --no-indirect, so they're checked on their own
+		for &(flag, takes_value) in LAUNCHER_FLAGS.iter().filter(|&&(flag, _)| flag != "--use-binary" && flag != "--supervise") {
+			args.push(flag.to_owned());
+			if takes_value {
+				args.push(sample(flag).to_owned());
+			}
+		}
+		args.extend(vec!["--keep".to_owned(), "3".to_owned()]);
+
+		let launcher = LauncherConfig::new(args, None, PathBuf::from("/updates")).unwrap();
+		assert_eq!(launcher.node_args(), &["--chain".to_owned(), "kovan".to_owned()]);
+	}
+
+	#[test]
+	fn should_forward_keep_without_prune_updates() {
+		assert_eq!(config(&["parity", "--keep", "3"]).node_args(), &["--keep".to_owned(), "3".to_owned()]);
+	}
+
+	#[test]
+	fn should_parse_max_update_version() {
+		assert_eq!(config(&["parity"]).max_update_version, None);
+		let launcher = config(&["parity", "--max-update-version", "1.12.99"]);
+		assert_eq!(launcher.max_update_version, Some(Version::new(1, 12, 99)));
+		assert_eq!(launcher.args, vec!["parity".to_owned()]);
+	}
+
+	#[test]
+	fn should_parse_max_lifetime() {
+		assert_eq!(config(&["parity"]).max_lifetime, None);
+		assert_eq!(config(&["parity", "--max-lifetime", "90"]).max_lifetime, Some(Duration::from_secs(90)));
+		assert_eq!(config(&["parity", "--max-lifetime=30m"]).max_lifetime, Some(Duration::from_secs(30 * 60)));
+		assert_eq!(config(&["parity", "--max-lifetime=2d"]).max_lifetime, Some(Duration::from_secs(2 * 24 * 60 * 60)));
+
+		assert!(try_config(&["parity", "--max-lifetime=2w"]).is_err());
+		assert!(try_config(&["parity", "--max-lifetime=h"]).is_err());
+	}
+
+	#[test]
+	fn should_parse_extra_child_env() {
+		let launcher = config(&["parity", "--child-env", "RUST_LOG=trace", "--child-env=EMPTY=", "--chain", "kovan"]);
+		assert_eq!(launcher.extra_child_env, vec![("RUST_LOG".to_owned(), "trace".to_owned()), ("EMPTY".to_owned(), "".to_owned())]);
+		assert_eq!(launcher.args, vec!["parity".to_owned(), "--chain".to_owned(), "kovan".to_owned()]);
+
+		assert!(try_config(&["parity", "--child-env", "RUST_LOG"]).is_err());
+		assert!(try_config(&["parity", "--child-env", "=trace"]).is_err());
+		assert!(try_config(&["parity", "--child-env"]).is_err());
+	}
+
+	#[test]
+	fn should_parse_expected_channel() {
+		assert_eq!(config(&["parity"]).expected_channel, None);
+		assert_eq!(config(&["parity", "--expected-channel", "stable"]).expected_channel, Some("stable".into()));
+		assert_eq!(config(&["parity", "--expected-channel= beta "]).expected_channel, Some("beta".into()));
+		assert!(try_config(&["parity", "--expected-channel="]).is_err());
+	}
+
+	#[test]
+	fn should_parse_update_settle_time() {
+		assert_eq!(config(&["parity"]).update_settle_time, Duration::from_secs(5));
+		assert_eq!(config(&["parity", "--update-settle-time=30"]).update_settle_time, Duration::from_secs(30));
+		assert_eq!(config(&["parity", "--update-settle-time", "0"]).update_settle_time, Duration::from_secs(0));
+		assert!(try_config(&["parity", "--update-settle-time", "soon"]).is_err());
+	}
+
+	#[test]
+	fn should_parse_update_channel() {
+		assert_eq!(config(&["parity"]).update_channel, UpdateChannel::Stable);
+		assert_eq!(config(&["parity", "--update-channel", "candidate"]).update_channel, UpdateChannel::Candidate);
+		assert_eq!(config(&["parity", "--update-channel=stable"]).update_channel, UpdateChannel::Stable);
+		assert!(try_config(&["parity", "--update-channel", "nightly"]).is_err());
+	}
+
+	#[test]
+	fn should_name_channel_files_after_pointer() {
+		let mut launcher = config(&["parity"]);
+		assert_eq!(launcher.channel_path("latest.version"), PathBuf::from("/updates/latest.version"));
+		launcher.update_channel = UpdateChannel::Candidate;
+		assert_eq!(launcher.channel_path("latest.version"), PathBuf::from("/updates/candidate.version"));
+		assert_eq!(launcher.channel_path("latest.keccak"), PathBuf::from("/updates/candidate.keccak"));
+		assert_eq!(launcher.channel_path("bad_update"), PathBuf::from("/updates/bad_update"));
+	}
+
+	#[test]
+	fn should_supervise_only_indirect_launches() {
+		assert!(!config(&["parity"]).supervise);
+		let launcher = config(&["parity", "--supervise", "--chain", "kovan"]);
+		assert!(launcher.supervise);
+		assert_eq!(launcher.args, vec!["parity".to_owned(), "--chain".to_owned(), "kovan".to_owned()]);
+		assert!(try_config(&["parity", "--supervise", "--no-indirect"]).is_err());
+		assert!(try_config(&["parity", "--supervise", "--force-direct"]).is_err());
+
+		assert_eq!(launcher.stop_timeout, Duration::from_secs(30));
+		assert_eq!(config(&["parity", "--supervise", "--stop-timeout=2m"]).stop_timeout, Duration::from_secs(120));
+	}
+
+	#[test]
+	fn should_keep_fallback_binaries_in_order() {
+		let launcher = config(&["parity", "--fallback-binary", "/opt/parity-green", "--chain", "kovan", "--fallback-binary=/opt/parity-blue"]);
+		assert_eq!(launcher.fallback_binaries, vec![PathBuf::from("/opt/parity-green"), PathBuf::from("/opt/parity-blue")]);
+		assert_eq!(launcher.args, vec!["parity".to_owned(), "--chain".to_owned(), "kovan".to_owned()]);
+	}
+
+	#[test]
+	fn should_reject_invalid_flag_values() {
+		assert!(try_config(&["parity", "--watchdog-interval"]).is_err());
+		assert!(try_config(&["parity", "--watchdog-interval=soon"]).is_err());
+		assert!(try_config(&["parity", "--crash-grace=soon"]).is_err());
+		assert!(try_config(&["parity", "--watchdog-misses=0"]).is_err());
+		assert!(try_config(&["parity", "--max-update-version=2"]).is_err());
+	}
+
+	#[test]
+	fn should_parse_launcher_commands() {
+		assert_eq!(config(&["parity", "--chain", "kovan"]).command, None);
+		assert_eq!(config(&["parity", "clean-launcher-state"]).command, Some(LauncherCommand::CleanState { keep_latest: false }));
+		assert_eq!(config(&["parity", "clean-launcher-state", "--keep-latest"]).command, Some(LauncherCommand::CleanState { keep_latest: true }));
+		assert!(try_config(&["parity", "clean-launcher-state", "--all"]).is_err());
+		assert_eq!(config(&["parity", "emit-restart-code", "--restart-exit-code=75"]).command, Some(LauncherCommand::EmitRestartCode));
+		assert!(try_config(&["parity", "emit-restart-code", "--now"]).is_err());
+		assert_eq!(config(&["parity", "--launcher-selftest"]).command, Some(LauncherCommand::SelfTest));
+		assert_eq!(config(&["parity", "--apply-update-now"]).command, Some(LauncherCommand::ApplyUpdateNow));
+		assert_eq!(config(&["parity", "--print-child-args"]).command, Some(LauncherCommand::PrintChildArgs));
+		assert_eq!(config(&["parity", "--emit-launch-plan"]).command, Some(LauncherCommand::EmitLaunchPlan));
+		assert_eq!(config(&["parity", "--explain-launch-detection"]).command, Some(LauncherCommand::ExplainLaunchDetection));
+		assert_eq!(config(&["parity", "--verify-only"]).command, Some(LauncherCommand::VerifyOnly));
+		assert_eq!(
+			config(&["parity", "--verify-update-bundle", "/tmp/bundle"]).command,
+			Some(LauncherCommand::VerifyUpdateBundle("/tmp/bundle".into()))
+		);
+		assert_eq!(config(&["parity", "--prune-updates"]).command, Some(LauncherCommand::PruneUpdates { keep: 2 }));
+		assert_eq!(config(&["parity", "--prune-updates", "--keep", "5"]).command, Some(LauncherCommand::PruneUpdates { keep: 5 }));
+		assert!(try_config(&["parity", "--prune-updates", "--keep=many"]).is_err());
+		assert_eq!(config(&["parity", "--launcher-selftest-exit=42", "--force-direct"]).command, Some(LauncherCommand::SelfTestExit(42)));
+	}
+
+	#[test]
+	fn should_hand_settings_down_through_env() {
+		let parent = config(&[
+			"parity", "--watchdog-interval=5", "--watchdog-misses=2", "--restart-grace-ms", "250",
+			"--log-launcher-to", "/var/log/launcher.log", "--log-reload-file=/etc/parity/log-levels", "--exit-wait-timeout=1h", "--restart-exit-code=75",
+			"--output-format", "json", "--launcher-readonly", "--fd-limit=off", "--spec-switch-cooldown=2m", "--quiet-launcher",
+			"--restart-on-config-change", "--write-effective-chain", "/run/parity/chain",
+		]);
+		let vars = parent.child_env();
+
+		let mut child = config(&["parity"]);
+		child.apply_env(|key| vars.iter().find(|v| v.0 == key).map(|v| v.1.clone())).unwrap();
+		assert_eq!(child.watchdog_interval, parent.watchdog_interval);
+		assert_eq!(child.watchdog_misses, parent.watchdog_misses);
+		assert_eq!(child.restart_grace, Duration::from_millis(250));
+		assert_eq!(child.spec_switch_cooldown, Duration::from_secs(2 * 60));
+		assert_eq!(child.log_launcher_to, Some(PathBuf::from("/var/log/launcher.log")));
+		assert_eq!(child.log_reload_file, Some(PathBuf::from("/etc/parity/log-levels")));
+		assert_eq!(child.effective_chain_file, Some(PathBuf::from("/run/parity/chain")));
+		assert_eq!(child.exit_wait_timeout, Some(Duration::from_secs(60 * 60)));
+		assert_eq!(child.restart_exit_code, 75);
+		assert_eq!(child.output_format, OutputFormat::Json);
+		assert!(child.readonly);
+		assert!(child.quiet);
+		assert!(child.restart_on_config_change);
+		assert_eq!(child.fd_limit, FdLimit::Off);
+	}
+
+	#[test]
+	fn should_not_write_state_when_readonly() {
+		assert!(config(&["parity"]).can_write_state());
+		let launcher = config(&["parity", "--launcher-readonly", "--chain", "kovan"]);
+		assert!(launcher.readonly);
+		assert!(!launcher.can_write_state());
+		assert_eq!(launcher.chain(), "kovan");
+
+		let mut launcher = config(&["parity"]);
+		launcher.disable_updates();
+		assert!(!launcher.can_write_state());
+	}
+
+	#[test]
+	fn should_find_chain_on_command_line() {
+		assert_eq!(config(&["parity"]).chain(), "foundation");
+		assert_eq!(config(&["parity", "--chain", "kovan"]).chain(), "kovan");
+		assert_eq!(config(&["parity", "--chain=classic", "--jsonrpc-port", "8545"]).chain(), "classic");
+		assert_eq!(config(&["parity", "--testnet"]).chain(), "testnet");
+	}
+
+	#[test]
+	fn should_parse_output_format() {
+		assert_eq!(config(&["parity", "account", "list"]).output_format, OutputFormat::Human);
+		let launcher = config(&["parity", "account", "list", "--output-format=json"]);
+		assert_eq!(launcher.output_format, OutputFormat::Json);
+		assert_eq!(launcher.node_args(), &["account".to_owned(), "list".to_owned()][..]);
+		assert!(try_config(&["parity", "--output-format", "yaml"]).is_err());
+	}
+
+	#[test]
+	fn should_parse_restart_port_timeout() {
+		assert_eq!(config(&["parity"]).restart_port_timeout, Duration::from_secs(10));
+		assert_eq!(config(&["parity", "--restart-port-timeout", "1m"]).restart_port_timeout, Duration::from_secs(60));
+		assert_eq!(config(&["parity", "--restart-port-timeout=0"]).restart_port_timeout, Duration::from_secs(0));
+	}
+
+	#[test]
+	fn should_parse_restart_jitter() {
+		assert_eq!(config(&["parity"]).restart_jitter, Duration::from_secs(0));
+		assert_eq!(config(&["parity", "--restart-jitter", "5m"]).restart_jitter, Duration::from_secs(5 * 60));
+		assert!(try_config(&["parity", "--restart-jitter=soon"]).is_err());
+	}
+
+	#[test]
+	fn should_validate_child_rlimits() {
+		let launcher = config(&["parity", "--child-rlimit-as", "1073741824", "--child-rlimit-cpu=3600"]);
+		assert_eq!(launcher.child_rlimit_as, Some(1 << 30));
+		assert_eq!(launcher.child_rlimit_cpu, Some(3600));
+		assert_eq!(config(&["parity"]).child_rlimit_as, None);
+		assert!(try_config(&["parity", "--child-rlimit-as", "1048576"]).is_err());
+		assert!(try_config(&["parity", "--child-rlimit-cpu", "0"]).is_err());
+		assert!(try_config(&["parity", "--child-rlimit-cpu", "-1"]).is_err());
+		assert!(try_config(&["parity", "--child-rlimit-as", "1G"]).is_err());
+	}
+
+	#[test]
+	fn should_parse_fd_limit() {
+		assert_eq!(config(&["parity"]).fd_limit, FdLimit::Max);
+		assert_eq!(config(&["parity", "--fd-limit", "off"]).fd_limit, FdLimit::Off);
+		assert_eq!(config(&["parity", "--fd-limit=1024"]).fd_limit, FdLimit::Files(1024));
+		assert!(try_config(&["parity", "--fd-limit", "none"]).is_err());
+	}
+
+	#[test]
+	fn should_find_launcher_config_path() {
+		let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+		assert_eq!(launcher_config_path(&args(&["parity", "--chain", "kovan"])), Ok(None));
+		assert_eq!(launcher_config_path(&args(&["parity", "--launcher-config", "/etc/launcher.toml"])), Ok(Some("/etc/launcher.toml".into())));
+		assert_eq!(launcher_config_path(&args(&["parity", "--launcher-config=launcher.toml"])), Ok(Some("launcher.toml".into())));
+		assert!(launcher_config_path(&args(&["parity", "--launcher-config"])).is_err());
+		assert_eq!(config(&["parity", "--launcher-config", "launcher.toml"]).node_args(), &[] as &[String]);
+	}
+
+	#[test]
+	fn should_validate_restart_exit_code() {
+		assert_eq!(config(&["parity"]).restart_exit_code, 69);
+		assert_eq!(config(&["parity", "--restart-exit-code", "75"]).restart_exit_code, 75);
+		assert!(try_config(&["parity", "--restart-exit-code", "0"]).is_err());
+		assert!(try_config(&["parity", "--restart-exit-code", "137"]).is_err());
+		assert!(try_config(&["parity", "--restart-exit-code", "-1"]).is_err());
+	}
+
+	#[test]
+	fn should_hand_launch_id_down_to_child() {
+		let mut parent = config(&["parity"]);
+		assert_eq!(parent.renew_launch_id(), None);
+		let vars = parent.child_env();
+
+		let mut child = config(&["parity"]);
+		child.apply_env(|key| vars.iter().find(|v| v.0 == key).map(|v| v.1.clone())).unwrap();
+		assert!(child.launch_id.is_some());
+		assert_eq!(child.launch_id, parent.launch_id);
+	}
+
+	#[test]
+	fn should_link_renewed_launch_id_to_previous() {
+		let mut launcher = config(&["parity"]);
+		launcher.renew_launch_id();
+		let first = launcher.launch_id.clone();
+
+		assert_eq!(launcher.renew_launch_id(), first);
+		assert!(launcher.launch_id.is_some());
+		assert_ne!(launcher.launch_id, first);
+	}
+
+	#[test]
+	fn should_keep_sticky_args_after_executable_name() {
+		let mut launcher = config(&["parity", "--port", "30303"]);
+		launcher.keep_sticky_args(&["--port=30304".to_owned()]);
+		assert_eq!(launcher.args, vec!["parity".to_owned(), "--port=30304".to_owned()]);
+	}
+
+	#[test]
+	fn should_skip_executable_name_in_node_args() {
+		assert_eq!(config(&["parity", "--chain", "kovan"]).node_args(), &["--chain".to_owned(), "kovan".to_owned()]);
+		assert!(config(&[]).node_args().is_empty());
+	}
+
+	#[test]
+	fn should_resolve_updates_path() {
+		assert_eq!(config(&["parity"]).updates_path("latest"), PathBuf::from("/updates/latest"));
+	}
+}
@@ -0,0 +1,209 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Launcher settings read from a TOML file.
+//!
+//! Each setting is named after the launcher flag it stands for, without the leading dashes, e.g.
+//! `max-update-version = "1.12.0"` or `capture-crash-output = true`. Flags given more than once take an
+//! array. Besides flags, `updates-dir` replaces the directory holding updates and launcher state.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use toml::Value;
+use super::config::launcher_flag;
+
+/// File in the default updates directory holding launcher settings.
+pub const LAUNCHER_CONFIG_FILE: &str = "launcher.toml";
+
+/// Flag naming a launcher config file to read instead of the default one.
+pub const LAUNCHER_CONFIG_FLAG: &str = "--launcher-config";
+
+/// Launcher flags running a command, which can't be given as settings.
+const COMMAND_FLAGS: &[&str] = &[
+	"--apply-update-now", "--print-child-args", "--verify-only", "--prune-updates", "--launcher-selftest", LAUNCHER_CONFIG_FLAG,
+];
+
+/// Launcher settings read from a config file.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigFile {
+	/// Directory holding installed updates and launcher state, instead of the default one.
+	pub updates_dir: Option<PathBuf>,
+	/// Launcher flags standing for the other settings.
+	pub args: Vec<String>,
+}
+
+impl ConfigFile {
+	/// Inserts the settings in front of the flags of command line `args`, which thus override them.
+	pub fn prepend_to(&self, mut args: Vec<String>) -> Vec<String> {
+		let at = if args.is_empty() { 0 } else { 1 };
+		args.splice(at..at, self.args.iter().cloned());
+		args
+	}
+}
+
+/// Reads launcher settings from the file at `path`. A missing file is only an error if `required`.
+pub fn read_config_file(path: &Path, required: bool) -> Result<ConfigFile, String> {
+	let mut contents = String::new();
+	match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+		Ok(_) => {},
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(ConfigFile::default()),
+		Err(e) => return Err(format!("Couldn't read launcher config {}: {}", path.display(), e)),
+	}
+	parse_config_file(&contents).map_err(|e| format!("Invalid launcher config {}: {}", path.display(), e))
+}
+
+fn parse_config_file(contents: &str) -> Result<ConfigFile, String> {
+	let table = match contents.parse::<Value>().map_err(|e| e.to_string())? {
+		Value::Table(table) => table,
+		_ => return Err("expected a table of settings".into()),
+	};
+
+	let mut file = ConfigFile::default();
+	for (key, value) in table {
+		if key == "updates-dir" {
+			match value {
+				Value::String(dir) => file.updates_dir = Some(dir.into()),
+				_ => return Err(format!("{} must be a string", key)),
+			}
+			continue;
+		}
+
+		let flag = format!("--{}", key);
+		let takes_value = match launcher_flag(&flag, false) {
+			Some((_, takes_value)) if !COMMAND_FLAGS.contains(&flag.as_str()) => takes_value,
+			// also a node flag, and thus not in the launcher flags
+			None if flag == "--force-direct" => false,
+			_ => return Err(format!("unknown setting {}", key)),
+		};
+		push_setting(&mut file.args, &flag, takes_value, value)?;
+	}
+	Ok(file)
+}
+
+/// Adds the flags standing for setting `flag` to `value`.
+fn push_setting(args: &mut Vec<String>, flag: &str, takes_value: bool, value: Value) -> Result<(), String> {
+	match value {
+		Value::Boolean(set) if !takes_value => if set {
+			args.push(flag.to_owned());
+		},
+		Value::String(ref value) if takes_value => args.push(format!("{}={}", flag, value)),
+		Value::Integer(value) if takes_value => args.push(format!("{}={}", flag, value)),
+		Value::Array(values) if takes_value => for value in values {
+			push_setting(args, flag, takes_value, value)?;
+		},
+		_ => return Err(format!(
+			"{} must be {}", &flag[2..], if takes_value { "a string, a number or an array of them" } else { "true or false" }
+		)),
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+	use std::path::PathBuf;
+	use semver::Version;
+	use tempdir::TempDir;
+	use launcher::LauncherConfig;
+	use launcher::updates::UpdateChannel;
+	use super::{parse_config_file, read_config_file, ConfigFile};
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|s| s.to_string()).collect()
+	}
+
+	#[test]
+	fn should_turn_settings_into_flags() {
+		let file = parse_config_file(r#"
+			# launcher settings
+			updates-dir = "/opt/parity-updates"
+			capture-crash-output = true
+			update-check-only = false
+			max-update-version = "1.12.0"
+			restart-grace-ms = 250
+			child-env = ["RUST_BACKTRACE=1", "TZ=UTC"]
+		"#).unwrap();
+
+		assert_eq!(file.updates_dir, Some(PathBuf::from("/opt/parity-updates")));
+		let mut flags = file.args.clone();
+		flags.sort();
+		assert_eq!(flags, args(&[
+			"--capture-crash-output", "--child-env=RUST_BACKTRACE=1", "--child-env=TZ=UTC", "--max-update-version=1.12.0",
+			"--restart-grace-ms=250",
+		]));
+	}
+
+	#[test]
+	fn should_accept_force_direct_setting() {
+		assert_eq!(parse_config_file("force-direct = true").unwrap().args, args(&["--force-direct"]));
+	}
+
+	#[test]
+	fn should_reject_unknown_settings_and_commands() {
+		assert_eq!(parse_config_file("chain = \"kovan\""), Err("unknown setting chain".into()));
+		assert_eq!(parse_config_file("verify-only = true"), Err("unknown setting verify-only".into()));
+		assert_eq!(parse_config_file("keep = 3"), Err("unknown setting keep".into()));
+	}
+
+	#[test]
+	fn should_reject_mistyped_settings() {
+		assert_eq!(parse_config_file("capture-crash-output = \"yes\""), Err("capture-crash-output must be true or false".into()));
+		assert!(parse_config_file("max-update-version = true").is_err());
+		assert!(parse_config_file("updates-dir = 1").is_err());
+		assert!(parse_config_file("capture-crash-output = ").is_err());
+	}
+
+	#[test]
+	fn should_let_command_line_override_settings() {
+		let file = parse_config_file("max-update-version = \"1.12.0\"\nrestart-exit-code = 75").unwrap();
+		let launcher = LauncherConfig::new(
+			file.prepend_to(args(&["parity", "--max-update-version", "1.13.0", "--chain", "kovan"])),
+			None,
+			PathBuf::from("/updates"),
+		).unwrap();
+
+		assert_eq!(launcher.max_update_version, Some(Version::new(1, 13, 0)));
+		assert_eq!(launcher.restart_exit_code, 75);
+		assert_eq!(launcher.node_args(), &args(&["--chain", "kovan"])[..]);
+	}
+
+	#[test]
+	fn should_keep_update_channel_in_config_file() {
+		let file = parse_config_file("update-channel = \"candidate\"").unwrap();
+		let launcher = LauncherConfig::new(file.prepend_to(args(&["parity"])), None, PathBuf::from("/updates")).unwrap();
+		assert_eq!(launcher.update_channel, UpdateChannel::Candidate);
+	}
+
+	#[test]
+	fn should_require_only_given_config_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("launcher.toml");
+		assert_eq!(read_config_file(&path, false), Ok(ConfigFile::default()));
+		assert!(read_config_file(&path, true).is_err());
+	}
+
+	#[test]
+	fn should_fail_on_invalid_config_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("launcher.toml");
+		File::create(&path).unwrap().write_all(b"watchdog-interval = [").unwrap();
+
+		let err = read_config_file(&path, false).unwrap_err();
+		assert!(err.starts_with(&format!("Invalid launcher config {}: ", path.display())));
+	}
+}
@@ -0,0 +1,122 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Watching the node's config file, to restart the node once it's edited.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use super::system::{LauncherEnv, RealEnv};
+
+/// Time a changed config file has to stay unchanged before the node is restarted, so that an
+/// editor saving several times in a row restarts it once.
+pub const CONFIG_CHANGE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Modification time of a config file, polled for changes.
+pub struct ConfigWatch {
+	path: PathBuf,
+	debounce: Duration,
+	seen: Option<SystemTime>,
+	changed_at: Option<Instant>,
+}
+
+impl ConfigWatch {
+	/// Starts watching the file at `path`, as it is now.
+	pub fn new(path: PathBuf, debounce: Duration) -> Self {
+		Self::new_in(&RealEnv, path, debounce)
+	}
+
+	/// Starts watching the file at `path` in the given environment.
+	pub fn new_in<E: LauncherEnv>(env: &E, path: PathBuf, debounce: Duration) -> Self {
+		let seen = env.file_mtime(&path).ok();
+		ConfigWatch { path, debounce, seen, changed_at: None }
+	}
+
+	/// Whether the file changed and then stayed unchanged for the debounce time.
+	pub fn poll(&mut self) -> bool {
+		self.poll_in(&RealEnv, Instant::now())
+	}
+
+	/// Whether the file changed and then stayed unchanged until `now`, in the given environment.
+	///
+	/// A missing file, e.g. while an editor replaces it, is waited for rather than seen as a change.
+	pub fn poll_in<E: LauncherEnv>(&mut self, env: &E, now: Instant) -> bool {
+		let mtime = match env.file_mtime(&self.path) {
+			Ok(mtime) => Some(mtime),
+			Err(_) => return false,
+		};
+		if mtime != self.seen {
+			trace_main!("Config file {} changed", self.path.display());
+			self.seen = mtime;
+			self.changed_at = Some(now);
+			return false;
+		}
+		match self.changed_at {
+			Some(at) if now.duration_since(at) >= self.debounce => {
+				self.changed_at = None;
+				true
+			},
+			_ => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+	use launcher::system::test_env::TestEnv;
+	use super::ConfigWatch;
+
+	#[test]
+	fn should_report_change_once_settled() {
+		let env = TestEnv::default();
+		env.add_file_at("/etc/parity/config.toml", "", 100);
+		let start = Instant::now();
+		let mut watch = ConfigWatch::new_in(&env, "/etc/parity/config.toml".into(), Duration::from_secs(2));
+		assert!(!watch.poll_in(&env, start));
+
+		env.add_file_at("/etc/parity/config.toml", "", 200);
+		assert!(!watch.poll_in(&env, start + Duration::from_secs(1)));
+		assert!(!watch.poll_in(&env, start + Duration::from_secs(2)));
+		assert!(watch.poll_in(&env, start + Duration::from_secs(3)));
+		assert!(!watch.poll_in(&env, start + Duration::from_secs(10)));
+	}
+
+	#[test]
+	fn should_debounce_rapid_edits() {
+		let env = TestEnv::default();
+		env.add_file_at("/etc/parity/config.toml", "", 100);
+		let start = Instant::now();
+		let mut watch = ConfigWatch::new_in(&env, "/etc/parity/config.toml".into(), Duration::from_secs(2));
+
+		for (i, secs) in [200, 201, 202].iter().enumerate() {
+			env.add_file_at("/etc/parity/config.toml", "", *secs);
+			assert!(!watch.poll_in(&env, start + Duration::from_secs(i as u64)));
+		}
+		assert!(!watch.poll_in(&env, start + Duration::from_secs(3)));
+		assert!(watch.poll_in(&env, start + Duration::from_secs(4)));
+	}
+
+	#[test]
+	fn should_wait_for_missing_config_file() {
+		let env = TestEnv::default();
+		let start = Instant::now();
+		let mut watch = ConfigWatch::new_in(&env, "/etc/parity/config.toml".into(), Duration::from_secs(2));
+		assert!(!watch.poll_in(&env, start + Duration::from_secs(5)));
+
+		env.add_file_at("/etc/parity/config.toml", "", 100);
+		assert!(!watch.poll_in(&env, start + Duration::from_secs(6)));
+		assert!(watch.poll_in(&env, start + Duration::from_secs(8)));
+	}
+}
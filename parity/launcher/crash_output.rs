@@ -0,0 +1,171 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Capture of the tail of the node's stderr with `--capture-crash-output`.
+//!
+//! The node's stderr is piped through the launcher, which passes it on to its own stderr while
+//! keeping the last few kilobytes. When the node ends abnormally, they're written to the launcher
+//! log, as its last words are often what explains the crash.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::process::Child;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use parking_lot::Mutex;
+
+/// Number of bytes of the node's stderr kept for the launcher log.
+const CAPTURE_LIMIT: usize = 16 * 1024;
+
+/// Time given to the output still in the pipe to be read once the node ended.
+///
+/// The pipe may be held open for longer by processes the node started, which isn't waited for.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Prefix of the lines of the node's output written to the launcher log.
+const LINE_PREFIX: &str = "[node stderr] ";
+
+/// Last bytes written to a stream, up to a limit.
+struct Tail {
+	bytes: VecDeque<u8>,
+	limit: usize,
+}
+
+impl Tail {
+	fn new(limit: usize) -> Self {
+		Tail { bytes: VecDeque::with_capacity(limit), limit }
+	}
+
+	/// Keeps `data`, dropping the oldest bytes beyond the limit.
+	fn push(&mut self, data: &[u8]) {
+		let data = if data.len() > self.limit { &data[data.len() - self.limit..] } else { data };
+		let overflow = (self.bytes.len() + data.len()).saturating_sub(self.limit);
+		self.bytes.drain(..overflow);
+		self.bytes.extend(data);
+	}
+
+	fn contents(&self) -> Vec<u8> {
+		self.bytes.iter().cloned().collect()
+	}
+}
+
+/// Tail of the stderr of a running child, passed on to the launcher's stderr as it's read.
+pub struct CrashOutput {
+	tail: Arc<Mutex<Tail>>,
+	drained: Receiver<()>,
+}
+
+impl CrashOutput {
+	/// Writes the captured output to the launcher log, the child having ended as `ended`.
+	pub fn report(self, ended: &str) {
+		let tail = self.wait_tail();
+		if tail.is_empty() {
+			trace_main!("The node {} without writing anything to stderr", ended);
+			return;
+		}
+		trace_main!("The node {}; the last {} bytes it wrote to stderr follow", ended, tail.len());
+		for line in prefixed_lines(&tail) {
+			trace_main!("{}", line);
+		}
+	}
+
+	/// Output captured once the child's stderr was read to the end, or `DRAIN_TIMEOUT` passed.
+	fn wait_tail(&self) -> Vec<u8> {
+		let _ = self.drained.recv_timeout(DRAIN_TIMEOUT);
+		self.tail.lock().contents()
+	}
+}
+
+/// Starts capturing the stderr of `child`, spawned with it piped.
+///
+/// Returns `None` if its stderr isn't piped.
+pub fn capture_stderr(child: &mut Child) -> Option<CrashOutput> {
+	let mut stderr = child.stderr.take()?;
+	let tail = Arc::new(Mutex::new(Tail::new(CAPTURE_LIMIT)));
+	let (done, drained) = channel();
+	let capture = tail.clone();
+	thread::spawn(move || {
+		let mut buf = [0u8; 4096];
+		loop {
+			match stderr.read(&mut buf) {
+				Ok(0) => break,
+				Ok(read) => {
+					// the node's output is passed on whatever happens with the launcher's own
+					let _ = io::stderr().write_all(&buf[..read]);
+					capture.lock().push(&buf[..read]);
+				},
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(_) => break,
+			}
+		}
+		let _ = done.send(());
+	});
+	Some(CrashOutput { tail, drained })
+}
+
+/// Lines of `output`, marked as the node's.
+fn prefixed_lines(output: &[u8]) -> Vec<String> {
+	String::from_utf8_lossy(output).lines().map(|line| format!("{}{}", LINE_PREFIX, line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::process::{Command, Stdio};
+	use super::{capture_stderr, prefixed_lines, Tail, CAPTURE_LIMIT};
+
+	#[test]
+	fn should_keep_only_last_bytes() {
+		let mut tail = Tail::new(8);
+		tail.push(b"abc");
+		tail.push(b"defg");
+		assert_eq!(tail.contents(), b"abcdefg");
+		tail.push(b"hij");
+		assert_eq!(tail.contents(), b"cdefghij");
+		tail.push(b"0123456789");
+		assert_eq!(tail.contents(), b"23456789");
+	}
+
+	#[test]
+	fn should_mark_lines_of_node_output() {
+		assert_eq!(prefixed_lines(b"first\nsecond\n"), ["[node stderr] first", "[node stderr] second"]);
+		assert_eq!(prefixed_lines(b"partial"), ["[node stderr] partial"]);
+		assert!(prefixed_lines(b"").is_empty());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_capture_tail_of_child_stderr() {
+		// the shell stands in for the node, writing twice the limit to stderr before saying goodbye
+		let script = format!("head -c {} /dev/zero | tr '\\0' x >&2; echo >&2; echo goodbye >&2; exit 3", 2 * CAPTURE_LIMIT);
+		let mut child = Command::new("/bin/sh").args(&["-c", &script]).stderr(Stdio::piped()).spawn().unwrap();
+		let output = capture_stderr(&mut child).unwrap();
+
+		assert_eq!(child.wait().unwrap().code(), Some(3));
+		let tail = output.wait_tail();
+		assert_eq!(tail.len(), CAPTURE_LIMIT);
+		assert!(tail.ends_with(b"xxx\ngoodbye\n"));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_not_capture_inherited_stderr() {
+		let mut child = Command::new("/bin/sh").args(&["-c", "exit 0"]).spawn().unwrap();
+		assert!(capture_stderr(&mut child).is_none());
+		child.wait().unwrap();
+	}
+}
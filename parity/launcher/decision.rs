@@ -0,0 +1,253 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decision between running the current binary and an installed update.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use semver::Version;
+use super::Error;
+
+/// Update decision made on a launcher loop iteration, as reported to monitoring.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LaunchDecision {
+	/// ID of the launch the decision was made for.
+	pub launch_id: Option<String>,
+	/// Path of the installed update, if it could be resolved.
+	pub latest: Option<String>,
+	/// State of installed updates, as given by `describe_update_state`.
+	pub state: String,
+	/// An update is installed.
+	pub have_update: bool,
+	/// The running binary isn't the installed update.
+	pub current_is_not_latest: bool,
+	/// The update was installed after the running binary.
+	pub update_is_newer: bool,
+	/// The update's version doesn't exceed the configured ceiling.
+	pub update_is_allowed: bool,
+	/// The update is run instead of the current binary.
+	pub run_update: bool,
+}
+
+/// Whether the installed update should be run instead of the current binary.
+///
+/// The facts are gathered from the filesystem by the caller:
+/// - `have_update`: an update is installed,
+/// - `current_is_not_latest`: the running binary isn't the installed update,
+/// - `update_is_newer`: the update was installed after the running binary,
+/// - `update_is_allowed`: the update's version doesn't exceed the configured ceiling.
+pub fn should_run_update(have_update: bool, current_is_not_latest: bool, update_is_newer: bool, update_is_allowed: bool) -> bool {
+	have_update && current_is_not_latest && update_is_newer && update_is_allowed
+}
+
+/// Whether an update of version `staged` may be run given the `ceiling`, if any.
+///
+/// An update of unknown version is refused when a ceiling is set.
+pub fn is_allowed_version(staged: Option<&Version>, ceiling: Option<&Version>) -> bool {
+	match (staged, ceiling) {
+		(_, None) => true,
+		(Some(staged), Some(ceiling)) => staged <= ceiling,
+		(None, Some(_)) => false,
+	}
+}
+
+/// Whether an update built for the release channel `staged` may be run given the `expected` one, if any.
+///
+/// Channels are compared ignoring case. An update without a recorded channel is accepted, as
+/// updaters which don't record it are still around.
+pub fn is_expected_channel(staged: Option<&str>, expected: Option<&str>) -> bool {
+	match (staged, expected) {
+		(Some(staged), Some(expected)) => staged.eq_ignore_ascii_case(expected),
+		_ => true,
+	}
+}
+
+/// Whether running an update of version `staged` would go back from the `current` version.
+///
+/// An update or current binary of unknown version isn't taken for a downgrade.
+pub fn is_downgrade(staged: Option<&Version>, current: Option<&Version>) -> bool {
+	match (staged, current) {
+		(Some(staged), Some(current)) => staged < current,
+		_ => false,
+	}
+}
+
+/// Whether the launcher is restarting the node to switch it to the update `next`.
+///
+/// The node isn't switching when it ran `previous` already, or when it's restarted after a
+/// panic, which shouldn't be delayed.
+pub fn is_update_restart(restarting: bool, previous: Option<&Path>, next: Option<&Path>, panicked: bool) -> bool {
+	restarting && !panicked && next.is_some() && next != previous
+}
+
+/// Whether a node exiting with `exit_code` after running for `ran_for` most likely crashed.
+///
+/// A nonzero exit within the `grace` period is taken for a crash, while later ones are the node's
+/// normal way of reporting a failure. A zero grace period never takes an exit for a crash.
+pub fn is_likely_crash(exit_code: i32, ran_for: Duration, grace: Duration) -> bool {
+	exit_code != 0 && ran_for < grace
+}
+
+/// Describes the state of installed updates for the decision log.
+///
+/// `latest` is the outcome of resolving the `latest` pointer; the flags are the same as for
+/// `should_run_update`.
+pub fn describe_update_state(latest: &Result<PathBuf, Error>, current_is_not_latest: bool, update_is_newer: bool) -> String {
+	match *latest {
+		Err(Error::NotConfigured) => "updates not configured".into(),
+		Err(ref e) => format!("update check failed: {}", e),
+		Ok(_) if !current_is_not_latest => "running the latest update".into(),
+		Ok(_) if !update_is_newer => "update available but not newer".into(),
+		Ok(_) => "newer update available".into(),
+	}
+}
+
+/// Describes a chain override which didn't take effect, given the chain the node `running` reported.
+///
+/// A node that didn't report its chain, e.g. because it failed before starting, isn't a mismatch.
+pub fn describe_spec_mismatch(requested: &str, running: Option<&str>) -> Option<String> {
+	match running {
+		Some(running) if running != requested => Some(format!(
+			"The node was asked to switch to chain {} but ran {} instead; check for a --chain argument overriding it.",
+			requested, running
+		)),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::{Path, PathBuf};
+	use std::time::Duration;
+	use semver::Version;
+	use launcher::Error;
+	use super::{
+		describe_spec_mismatch, describe_update_state, is_allowed_version, is_downgrade, is_expected_channel, is_likely_crash,
+		is_update_restart, should_run_update,
+	};
+
+	#[test]
+	fn should_run_update_only_when_all_conditions_hold() {
+		// (have_update, current_is_not_latest, update_is_newer) => run_update, within the ceiling
+		let table = [
+			((false, false, false), false),
+			((false, false, true), false),
+			((false, true, false), false),
+			((false, true, true), false),
+			((true, false, false), false),
+			((true, false, true), false),
+			((true, true, false), false),
+			((true, true, true), true),
+		];
+		for &((have_update, current_is_not_latest, update_is_newer), run_update) in &table {
+			assert_eq!(
+				should_run_update(have_update, current_is_not_latest, update_is_newer, true),
+				run_update,
+				"have_update: {}, current_is_not_latest: {}, update_is_newer: {}",
+				have_update, current_is_not_latest, update_is_newer
+			);
+		}
+		assert!(!should_run_update(true, true, true, false));
+	}
+
+	#[test]
+	fn should_not_run_update_older_than_current() {
+		assert!(!should_run_update(true, true, false, true));
+	}
+
+	#[test]
+	fn should_not_rerun_current_binary() {
+		assert!(!should_run_update(true, false, true, true));
+	}
+
+	#[test]
+	fn should_not_run_newer_update_above_ceiling() {
+		let ceiling = Version::new(1, 12, 99);
+		assert!(is_allowed_version(Some(&Version::new(2, 0, 0)), None));
+		assert!(is_allowed_version(Some(&Version::new(1, 12, 3)), Some(&ceiling)));
+		assert!(is_allowed_version(Some(&ceiling), Some(&ceiling)));
+		assert!(!is_allowed_version(Some(&Version::new(2, 0, 0)), Some(&ceiling)));
+		assert!(!is_allowed_version(None, Some(&ceiling)));
+	}
+
+	#[test]
+	fn should_accept_only_expected_channel() {
+		assert!(is_expected_channel(Some("beta"), None));
+		assert!(is_expected_channel(None, None));
+		assert!(is_expected_channel(Some("stable"), Some("stable")));
+		assert!(is_expected_channel(Some("Stable"), Some("stable")));
+		assert!(!is_expected_channel(Some("beta"), Some("stable")));
+		assert!(is_expected_channel(None, Some("stable")));
+	}
+
+	#[test]
+	fn should_detect_downgrades() {
+		let current = Version::new(1, 12, 0);
+		assert!(!is_downgrade(Some(&Version::new(1, 12, 1)), Some(&current)));
+		assert!(!is_downgrade(Some(&Version::new(2, 0, 0)), Some(&current)));
+		assert!(!is_downgrade(Some(&current), Some(&current)));
+		assert!(is_downgrade(Some(&Version::new(1, 11, 9)), Some(&current)));
+		assert!(!is_downgrade(None, Some(&current)));
+		assert!(!is_downgrade(Some(&Version::new(1, 11, 9)), None));
+	}
+
+	#[test]
+	fn should_only_take_switches_to_update_for_update_restarts() {
+		let current = Path::new("/updates/parity-1.11.0");
+		let update = Path::new("/updates/parity-1.12.0");
+		assert!(is_update_restart(true, None, Some(update), false));
+		assert!(is_update_restart(true, Some(current), Some(update), false));
+		assert!(!is_update_restart(false, None, Some(update), false));
+		assert!(!is_update_restart(true, Some(update), Some(update), false));
+		assert!(!is_update_restart(true, None, Some(update), true));
+		assert!(!is_update_restart(true, Some(update), None, false));
+	}
+
+	#[test]
+	fn should_take_only_quick_failures_for_crashes() {
+		let grace = Duration::from_millis(500);
+		assert!(is_likely_crash(1, Duration::from_millis(100), grace));
+		assert!(is_likely_crash(139, Duration::from_millis(499), grace));
+		assert!(!is_likely_crash(0, Duration::from_millis(100), grace));
+		assert!(!is_likely_crash(1, Duration::from_millis(500), grace));
+		assert!(!is_likely_crash(1, Duration::from_secs(3600), grace));
+		assert!(!is_likely_crash(1, Duration::from_millis(0), Duration::from_millis(0)));
+	}
+
+	#[test]
+	fn should_describe_update_state() {
+		let latest = Ok(PathBuf::from("/updates/parity-1.12.0"));
+		assert_eq!(describe_update_state(&Err(Error::NotConfigured), true, false), "updates not configured");
+		assert_eq!(describe_update_state(&Err(Error::BinaryNotFound), true, false), "update check failed: the latest update is not a runnable binary");
+		assert_eq!(
+			describe_update_state(&Err(Error::PointerUnreadable("permission denied".into())), true, false),
+			"update check failed: couldn't read the latest pointer: permission denied"
+		);
+		assert_eq!(describe_update_state(&latest, false, true), "running the latest update");
+		assert_eq!(describe_update_state(&latest, true, false), "update available but not newer");
+		assert_eq!(describe_update_state(&latest, true, true), "newer update available");
+	}
+
+	#[test]
+	fn should_describe_spec_mismatch() {
+		assert_eq!(describe_spec_mismatch("kovan", Some("kovan")), None);
+		assert_eq!(describe_spec_mismatch("kovan", None), None);
+		assert_eq!(
+			describe_spec_mismatch("kovan", Some("foundation")),
+			Some("The node was asked to switch to chain kovan but ran foundation instead; check for a --chain argument overriding it.".into())
+		);
+	}
+}
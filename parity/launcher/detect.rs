@@ -0,0 +1,622 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Detection of how the launcher was started.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use hash::{keccak_buffer, H256};
+use parking_lot::Mutex;
+use super::LauncherConfig;
+use super::system::{resolve_symlinks, LauncherEnv, RealEnv};
+
+lazy_static! {
+	/// Hashes of the binaries compared by content, by path, modification time and length.
+	///
+	/// They're compared on every run of the node, and hardly ever change in between.
+	static ref CONTENT_HASHES: Mutex<HashMap<(PathBuf, SystemTime, u64), H256>> = Mutex::new(HashMap::new());
+}
+
+/// Environment variable forcing development mode, e.g. for a binary copied out of `target`.
+const DEV_ENV: &str = "PARITY_LAUNCHER_DEV";
+
+/// How the launcher was started, detected once at startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Startup {
+	/// Path of the running executable, if it could be determined.
+	pub exe: Option<PathBuf>,
+	/// Updates are ignored with `--force-direct`.
+	pub force_direct: bool,
+	/// The executable was built in a cargo `target` directory.
+	pub development: bool,
+	/// The executable is named like an installed `parity` executable.
+	pub same_name: bool,
+	/// The launcher runs under a debugger, which wouldn't follow the node into a child process.
+	pub traced: bool,
+}
+
+impl Startup {
+	/// Detects how the launcher configured by `launcher` was started.
+	pub fn detect(launcher: &LauncherConfig) -> Self {
+		let tracer = tracer_pid();
+		if let Some(pid) = tracer {
+			warn_main!("Running under a debugger (tracer PID {}), so the node is run directly in this process.", pid);
+		}
+		Self::detect_with(launcher, |key| env::var(key).ok(), tracer.is_some())
+	}
+
+	/// Detects how the launcher was started, reading environment variables through `var`.
+	///
+	/// Setting `PARITY_LAUNCHER_DEV` to anything but `0` forces development mode.
+	fn detect_with<F>(launcher: &LauncherConfig, var: F, traced: bool) -> Self where F: Fn(&str) -> Option<String> {
+		let exe = launcher.current_exe.clone();
+		let development = match forced_development(&var) {
+			Some(_) => {
+				trace_main!("Development mode forced by {}", DEV_ENV);
+				true
+			},
+			None => {
+				let development = exe.as_ref().map_or(false, |p| is_development(p));
+				trace_main!("Development mode detected from the executable path: {}", development);
+				development
+			},
+		};
+
+		Startup {
+			force_direct: launcher.force_direct,
+			development,
+			same_name: exe.as_ref().map_or(false, |p| is_same_name(p)),
+			traced,
+			exe,
+		}
+	}
+
+	/// Whether installed updates should be looked for and run, restarting them when asked to.
+	pub fn should_hypervise(&self) -> bool {
+		!self.force_direct && !self.development && self.same_name && !self.traced
+	}
+}
+
+impl fmt::Display for Startup {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.exe {
+			Some(ref exe) => write!(f, "{}", exe.display())?,
+			None => write!(f, "<unknown>")?,
+		}
+		write!(
+			f, " (force-direct: {}, development: {}, same-name: {}, traced: {})",
+			self.force_direct, self.development, self.same_name, self.traced
+		)
+	}
+}
+
+/// Value of `PARITY_LAUNCHER_DEV` read through `var`, if it forces development mode.
+fn forced_development<F>(var: &F) -> Option<String> where F: Fn(&str) -> Option<String> {
+	match var(DEV_ENV) {
+		Some(value) => if value.is_empty() || value == "0" { None } else { Some(value) },
+		None => None,
+	}
+}
+
+/// Explains how the launcher configured by `launcher` was detected to be started, and whether
+/// that engages the update loop, as printed by `--explain-launch-detection`.
+pub fn explain_launch_detection(launcher: &LauncherConfig) -> Vec<String> {
+	explain_with(launcher, |key| env::var(key).ok(), tracer_pid().is_some())
+}
+
+/// Explains the detection, reading environment variables through `var`.
+fn explain_with<F>(launcher: &LauncherConfig, var: F, traced: bool) -> Vec<String> where F: Fn(&str) -> Option<String> {
+	let forced = forced_development(&var);
+	let startup = Startup::detect_with(launcher, var, traced);
+	let exe = startup.exe.as_ref().map(PathBuf::as_path);
+	let parent = exe.and_then(Path::parent);
+	let grandparent = parent.and_then(Path::parent);
+	let name = |dir: Option<&Path>| dir.and_then(Path::file_name).map_or("none".to_owned(), |n| format!("`{}`", n.to_string_lossy()));
+
+	let development = match (forced, exe) {
+		(Some(value), _) => format!("true, forced by {}={}", DEV_ENV, value),
+		(None, None) => "false, as the executable path is unknown".to_owned(),
+		(None, Some(_)) if startup.development => "true, as the grandparent directory is `target`, like a cargo build's".to_owned(),
+		(None, Some(_)) => "false, as the grandparent directory isn't `target`".to_owned(),
+	};
+	let same_name = match exe {
+		None => "false, as the executable path is unknown".to_owned(),
+		Some(_) if startup.same_name => "true, as the file stem is `parity`".to_owned(),
+		Some(exe) => match exe.file_stem().map(|stem| stem.to_string_lossy()) {
+			Some(ref stem) if stem == "parity" => "false, as the file stem is `parity` but the extension isn't `exe`".to_owned(),
+			stem => format!("false, as the file stem is `{}`, not `parity`", stem.unwrap_or_default()),
+		},
+	};
+	// mirrors the checks made before the update loop is entered
+	let update_loop = if launcher.no_indirect {
+		"not engaged, as --no-indirect runs the node in this process".to_owned()
+	} else if startup.should_hypervise() {
+		"engaged".to_owned()
+	} else if launcher.use_binary.is_some() {
+		"engaged for --use-binary".to_owned()
+	} else if launcher.supervise {
+		"engaged for --supervise".to_owned()
+	} else {
+		let reasons: Vec<_> = vec![
+			(startup.force_direct, "--force-direct is given"),
+			(startup.development, "the executable is a development build"),
+			(!startup.same_name, "the executable isn't named `parity`"),
+			(startup.traced, "the launcher runs under a debugger"),
+		].into_iter().filter(|&(applies, _)| applies).map(|(_, reason)| reason).collect();
+		format!("not engaged, as {}", reasons.join(", "))
+	};
+
+	vec![
+		format!("Executable: {}", exe.map_or("unknown".to_owned(), |exe| exe.display().to_string())),
+		format!("Parent directory: {}", name(parent)),
+		format!("Grandparent directory: {}", name(grandparent)),
+		format!("Development: {}", development),
+		format!("Same name: {}", same_name),
+		format!("Force direct: {}", startup.force_direct),
+		format!("Traced: {}", startup.traced),
+		format!("Update loop: {}", update_loop),
+	]
+}
+
+/// PID of the debugger tracing the launcher, if any.
+#[cfg(unix)]
+fn tracer_pid() -> Option<u32> {
+	let mut status = String::new();
+	File::open("/proc/self/status").and_then(|mut f| f.read_to_string(&mut status)).ok()?;
+	parse_tracer_pid(&status)
+}
+
+/// PID of the debugger tracing the launcher, if any; only detected on Unix.
+#[cfg(not(unix))]
+fn tracer_pid() -> Option<u32> {
+	None
+}
+
+/// Parses the `TracerPid` line of a `/proc/<pid>/status` file, `0` meaning no tracer.
+fn parse_tracer_pid(status: &str) -> Option<u32> {
+	status.lines()
+		.find(|line| line.starts_with("TracerPid:"))
+		.and_then(|line| line["TracerPid:".len()..].trim().parse().ok())
+		.and_then(|pid| if pid == 0 { None } else { Some(pid) })
+}
+
+/// Whether `a` and `b` are the same file, possibly reached through different paths.
+///
+/// On Unix, hardlinks and bind mounts are detected by comparing device and inode numbers.
+/// Elsewhere, or when the files can't be inspected, canonical paths are compared.
+pub fn is_same_binary(a: &Path, b: &Path) -> bool {
+	if let Some(same) = is_same_inode(a, b) {
+		return same;
+	}
+	match (a.canonicalize(), b.canonicalize()) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => a == b,
+	}
+}
+
+/// Whether the running `exe` isn't the installed update `latest`.
+///
+/// Atomic deployments may swap a symlinked updates directory, so paths through it can point
+/// elsewhere from one run to the next. When `updates_dir` is a symlink the binaries are compared
+/// by content instead.
+pub fn is_not_latest(updates_dir: &Path, exe: &Path, latest: &Path) -> bool {
+	let by_path = !is_same_binary(exe, latest);
+	if !is_symlink(updates_dir) {
+		return by_path;
+	}
+
+	let by_content = is_same_content(exe, latest).map(|same| !same).unwrap_or(by_path);
+	trace_main!("Updates directory {} is a symlink; not latest by path: {}, by content: {}", updates_dir.display(), by_path, by_content);
+	by_content
+}
+
+/// Whether the installed update `latest` was modified after the running `exe`.
+pub fn latest_binary_is_newer(latest: &Path, exe: &Path) -> bool {
+	latest_binary_is_newer_in(&RealEnv, latest, exe)
+}
+
+/// Whether `latest` was modified after `exe` in the given environment.
+///
+/// A symlinked `latest` is dated by the binary it ends at. Either file missing its modification
+/// time counts as not newer. An update dated in the future, e.g. copied from a host with a skewed
+/// clock, still counts as newer.
+pub fn latest_binary_is_newer_in<E: LauncherEnv>(env: &E, latest: &Path, exe: &Path) -> bool {
+	let latest_mtime = resolve_symlinks(env, latest).and_then(|target| env.file_mtime(&target));
+	match (latest_mtime, env.file_mtime(exe)) {
+		(Ok(latest_time), Ok(exe_time)) => {
+			if latest_time > env.now() {
+				warn!("Update {} is dated in the future; is the clock right?", latest.display());
+			}
+			latest_time > exe_time
+		},
+		_ => false,
+	}
+}
+
+/// How long the installed update `latest` has yet to wait before it was last modified at least
+/// `settle_time` ago, if it wasn't already.
+pub fn update_settle_remaining(latest: &Path, settle_time: Duration) -> Option<Duration> {
+	update_settle_remaining_in(&RealEnv, latest, settle_time)
+}
+
+/// How long `latest` has yet to settle in the given environment.
+///
+/// A symlinked `latest` is dated by the binary it ends at. An update missing its modification time
+/// or dated in the future can't be told apart from a settled one, so it counts as settled.
+pub fn update_settle_remaining_in<E: LauncherEnv>(env: &E, latest: &Path, settle_time: Duration) -> Option<Duration> {
+	let mtime = resolve_symlinks(env, latest).and_then(|target| env.file_mtime(&target)).ok()?;
+	match env.now().duration_since(mtime) {
+		Ok(age) if age < settle_time => Some(settle_time - age),
+		_ => None,
+	}
+}
+
+fn is_symlink(path: &Path) -> bool {
+	fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+fn is_same_content(a: &Path, b: &Path) -> io::Result<bool> {
+	let (a_meta, b_meta) = (fs::metadata(a)?, fs::metadata(b)?);
+	if a_meta.len() != b_meta.len() {
+		return Ok(false);
+	}
+	Ok(content_hash(a, &a_meta)? == content_hash(b, &b_meta)?)
+}
+
+/// Hash of the binary at `path`, read again only if it was modified since it was last hashed.
+fn content_hash(path: &Path, meta: &fs::Metadata) -> io::Result<H256> {
+	let key = (path.to_owned(), meta.modified()?, meta.len());
+	if let Some(hash) = CONTENT_HASHES.lock().get(&key) {
+		return Ok(*hash);
+	}
+
+	let hash = keccak_buffer(&mut BufReader::new(File::open(path)?))?;
+	let mut hashes = CONTENT_HASHES.lock();
+	hashes.retain(|&(ref hashed, _, _), _| hashed != path);
+	hashes.insert(key, hash);
+	Ok(hash)
+}
+
+#[cfg(unix)]
+fn is_same_inode(a: &Path, b: &Path) -> Option<bool> {
+	use std::fs::metadata;
+	use std::os::unix::fs::MetadataExt;
+
+	match (metadata(a), metadata(b)) {
+		(Ok(a), Ok(b)) => Some(a.dev() == b.dev() && a.ino() == b.ino()),
+		_ => None,
+	}
+}
+
+#[cfg(not(unix))]
+fn is_same_inode(_a: &Path, _b: &Path) -> Option<bool> {
+	None
+}
+
+/// Whether `exe` was built in a cargo `target` directory, e.g. `target/release/parity`.
+pub fn is_development(exe: &Path) -> bool {
+	exe.parent().and_then(|p| p.parent()).and_then(|p| p.file_name()).map_or(false, |n| n == "target")
+}
+
+/// Whether `exe` is named like an installed `parity` executable.
+///
+/// The launcher only looks for updates when started under the expected name: `parity` with
+/// any extension on Unix, `parity.exe` on Windows.
+pub fn is_same_name(exe: &Path) -> bool {
+	let stem = exe.file_stem().and_then(|s| s.to_str());
+	let extension = exe.extension().and_then(|x| x.to_str());
+	let same_name = stem == Some("parity") && extension_matches(extension);
+	trace_main!("Executable stem: {:?}, extension: {:?} (same-name: {})", stem, extension, same_name);
+	same_name
+}
+
+#[cfg(windows)]
+fn extension_matches(extension: Option<&str>) -> bool {
+	extension.map_or(false, |x| x.eq_ignore_ascii_case("exe"))
+}
+
+#[cfg(not(windows))]
+fn extension_matches(_extension: Option<&str>) -> bool {
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::{Path, PathBuf};
+	use std::time::Duration;
+	use launcher::LauncherConfig;
+	use launcher::system::LauncherEnv;
+	use launcher::system::test_env::{Entry, TestEnv};
+	use super::{
+		explain_with, is_development, is_not_latest, is_same_binary, is_same_name, latest_binary_is_newer_in,
+		parse_tracer_pid, update_settle_remaining_in, Startup, CONTENT_HASHES,
+	};
+
+	fn startup(args: &[&str], exe: &str) -> Startup {
+		let args = args.iter().map(|s| s.to_string()).collect();
+		Startup::detect_with(&LauncherConfig::new(args, Some(PathBuf::from(exe)), PathBuf::from("/updates")).unwrap(), |_| None, false)
+	}
+
+	#[test]
+	fn should_detect_development_builds() {
+		assert!(is_development(Path::new("/src/parity/target/release/parity")));
+		assert!(is_development(Path::new("/src/parity/target/debug/parity")));
+		assert!(!is_development(Path::new("/usr/bin/parity")));
+		assert!(!is_development(Path::new("parity")));
+	}
+
+	#[cfg(not(windows))]
+	#[test]
+	fn should_hypervise_only_installed_parity() {
+		let installed = startup(&["parity"], "/usr/bin/parity");
+		assert_eq!(installed, Startup {
+			exe: Some("/usr/bin/parity".into()), force_direct: false, development: false, same_name: true, traced: false,
+		});
+		assert!(installed.should_hypervise());
+
+		assert!(!startup(&["parity", "--force-direct"], "/usr/bin/parity").should_hypervise());
+		assert!(!startup(&["parity"], "/src/parity/target/release/parity").should_hypervise());
+		assert!(!startup(&["parity"], "/usr/bin/parity-1.12.0").should_hypervise());
+		assert!(!Startup { traced: true, ..installed }.should_hypervise());
+	}
+
+	#[test]
+	fn should_parse_tracer_pid() {
+		let untraced = "Name:\tparity\nState:\tS (sleeping)\nPid:\t4242\nPPid:\t1\nTracerPid:\t0\nUid:\t1000\t1000\t1000\t1000\n";
+		let traced = "Name:\tparity\nState:\tt (tracing stop)\nPid:\t4242\nPPid:\t4241\nTracerPid:\t4241\nUid:\t1000\t1000\t1000\t1000\n";
+		assert_eq!(parse_tracer_pid(untraced), None);
+		assert_eq!(parse_tracer_pid(traced), Some(4241));
+		assert_eq!(parse_tracer_pid("Name:\tparity\n"), None);
+	}
+
+	#[test]
+	fn should_force_development_mode_from_env() {
+		let args = vec!["parity".to_owned()];
+		let launcher = LauncherConfig::new(args, Some(PathBuf::from("/tmp/parity")), PathBuf::from("/updates")).unwrap();
+		let forced = |value: &'static str| move |key: &str| if key == "PARITY_LAUNCHER_DEV" { Some(value.to_owned()) } else { None };
+
+		assert!(!Startup::detect_with(&launcher, |_| None, false).development);
+		assert!(Startup::detect_with(&launcher, forced("1"), false).development);
+		assert!(!Startup::detect_with(&launcher, forced("0"), false).development);
+		assert!(!Startup::detect_with(&launcher, forced(""), false).development);
+	}
+
+	#[cfg(not(windows))]
+	#[test]
+	fn should_explain_why_update_loop_is_not_engaged() {
+		let config = |args: &[&str], exe: &str| {
+			let args = Some("parity").into_iter().chain(args.iter().cloned()).map(String::from).collect();
+			LauncherConfig::new(args, Some(PathBuf::from(exe)), PathBuf::from("/updates")).unwrap()
+		};
+
+		assert_eq!(explain_with(&config(&[], "/src/parity/target/release/parity"), |_| None, false), [
+			"Executable: /src/parity/target/release/parity",
+			"Parent directory: `release`",
+			"Grandparent directory: `target`",
+			"Development: true, as the grandparent directory is `target`, like a cargo build's",
+			"Same name: true, as the file stem is `parity`",
+			"Force direct: false",
+			"Traced: false",
+			"Update loop: not engaged, as the executable is a development build",
+		]);
+
+		let forced = |key: &str| if key == "PARITY_LAUNCHER_DEV" { Some("1".to_owned()) } else { None };
+		let lines = explain_with(&config(&["--force-direct"], "/usr/bin/ethereum"), forced, true);
+		assert_eq!(lines[3], "Development: true, forced by PARITY_LAUNCHER_DEV=1");
+		assert_eq!(lines[4], "Same name: false, as the file stem is `ethereum`, not `parity`");
+		assert_eq!(lines[7], "Update loop: not engaged, as --force-direct is given, the executable is a development build, \
+			the executable isn't named `parity`, the launcher runs under a debugger");
+	}
+
+	#[cfg(not(windows))]
+	#[test]
+	fn should_explain_when_update_loop_is_engaged() {
+		let update_loop = |args: &[&str], exe: &str| {
+			let args = Some("parity").into_iter().chain(args.iter().cloned()).map(String::from).collect();
+			let launcher = LauncherConfig::new(args, Some(PathBuf::from(exe)), PathBuf::from("/updates")).unwrap();
+			explain_with(&launcher, |_| None, false).pop().unwrap()
+		};
+
+		assert_eq!(update_loop(&[], "/usr/bin/parity"), "Update loop: engaged");
+		assert_eq!(update_loop(&["--supervise"], "/usr/bin/parity-1.12.0"), "Update loop: engaged for --supervise");
+		assert_eq!(
+			update_loop(&["--no-indirect"], "/usr/bin/parity"),
+			"Update loop: not engaged, as --no-indirect runs the node in this process"
+		);
+	}
+
+	#[test]
+	fn should_describe_startup_on_one_line() {
+		let startup = Startup { exe: None, force_direct: true, development: false, same_name: false, traced: false };
+		assert_eq!(startup.to_string(), "<unknown> (force-direct: true, development: false, same-name: false, traced: false)");
+	}
+
+	#[test]
+	fn should_require_parity_stem() {
+		assert!(!is_same_name(Path::new("/usr/bin/parity-1.12.0")));
+		assert!(!is_same_name(Path::new("/usr/bin/ethereum")));
+	}
+
+	#[cfg(not(windows))]
+	#[test]
+	fn should_accept_any_extension_on_unix() {
+		assert!(is_same_name(Path::new("/usr/bin/parity")));
+		assert!(is_same_name(Path::new("/usr/bin/parity.bin")));
+	}
+
+	#[cfg(windows)]
+	#[test]
+	fn should_require_exe_extension_on_windows() {
+		assert!(is_same_name(Path::new("C:\\Parity\\parity.exe")));
+		assert!(!is_same_name(Path::new("C:\\Parity\\parity")));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_detect_hardlinked_binary() {
+		use std::fs::{hard_link, File};
+		use tempdir::TempDir;
+
+		let tempdir = TempDir::new("").unwrap();
+		let exe = tempdir.path().join("parity");
+		let link = tempdir.path().join("parity-1.12.0");
+		let other = tempdir.path().join("parity-1.12.1");
+		File::create(&exe).unwrap();
+		File::create(&other).unwrap();
+		hard_link(&exe, &link).unwrap();
+
+		assert_ne!(exe.canonicalize().unwrap(), link.canonicalize().unwrap());
+		assert!(is_same_binary(&exe, &link));
+		assert!(!is_same_binary(&exe, &other));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_compare_binaries_by_content_in_symlinked_updates_dir() {
+		use std::fs::{create_dir, File};
+		use std::io::Write;
+		use std::os::unix::fs::symlink;
+		use tempdir::TempDir;
+
+		let tempdir = TempDir::new("").unwrap();
+		let real = tempdir.path().join("releases-1");
+		let current = tempdir.path().join("current");
+		create_dir(&real).unwrap();
+		symlink(&real, &current).unwrap();
+		let exe = tempdir.path().join("parity");
+		File::create(&exe).unwrap().write_all(b"binary").unwrap();
+		File::create(real.join("parity-1.12.0")).unwrap().write_all(b"binary").unwrap();
+		File::create(real.join("parity-1.12.1")).unwrap().write_all(b"other binary").unwrap();
+
+		assert!(!is_not_latest(&current, &exe, &current.join("parity-1.12.0")));
+		assert!(is_not_latest(&current, &exe, &current.join("parity-1.12.1")));
+		// a plain updates directory is still compared by path
+		assert!(is_not_latest(&real, &exe, &real.join("parity-1.12.0")));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_compare_binaries_of_same_length_by_hash_once() {
+		use std::fs::{create_dir, metadata, File};
+		use std::io::Write;
+		use std::os::unix::fs::symlink;
+		use tempdir::TempDir;
+
+		let tempdir = TempDir::new("").unwrap();
+		let real = tempdir.path().join("releases-1");
+		let current = tempdir.path().join("current");
+		create_dir(&real).unwrap();
+		symlink(&real, &current).unwrap();
+		let exe = tempdir.path().join("parity");
+		File::create(&exe).unwrap().write_all(b"binary 1").unwrap();
+		File::create(real.join("parity-1.12.0")).unwrap().write_all(b"binary 1").unwrap();
+		File::create(real.join("parity-1.12.1")).unwrap().write_all(b"binary 2").unwrap();
+
+		assert!(!is_not_latest(&current, &exe, &current.join("parity-1.12.0")));
+		assert!(is_not_latest(&current, &exe, &current.join("parity-1.12.1")));
+		let meta = metadata(&exe).unwrap();
+		let key = (exe.clone(), meta.modified().unwrap(), meta.len());
+		assert!(CONTENT_HASHES.lock().contains_key(&key));
+		assert!(!is_not_latest(&current, &exe, &current.join("parity-1.12.0")));
+		assert_eq!(CONTENT_HASHES.lock().keys().filter(|&&(ref path, _, _)| *path == exe).count(), 1);
+	}
+
+	#[test]
+	fn should_compare_missing_binaries_by_path() {
+		assert!(!is_same_binary(Path::new("/nonexistent/parity"), Path::new("/usr/bin/parity")));
+	}
+
+	#[test]
+	fn should_run_update_modified_after_current_binary() {
+		let env = TestEnv::default();
+		env.add_file_at("/updates/parity-1.12.0", "", 200);
+		env.add_file_at("/usr/bin/parity", "", 100);
+
+		assert!(latest_binary_is_newer_in(&env, Path::new("/updates/parity-1.12.0"), Path::new("/usr/bin/parity")));
+		assert!(!latest_binary_is_newer_in(&env, Path::new("/usr/bin/parity"), Path::new("/updates/parity-1.12.0")));
+		assert!(!latest_binary_is_newer_in(&env, Path::new("/usr/bin/parity"), Path::new("/usr/bin/parity")));
+	}
+
+	#[test]
+	fn should_not_consider_missing_binaries_newer() {
+		let env = TestEnv::default();
+		env.add_file_at("/usr/bin/parity", "", 100);
+		env.add("/updates/parity-1.12.0", Entry::File("".into()), 1);
+
+		assert!(!latest_binary_is_newer_in(&env, Path::new("/updates/parity-1.12.0"), Path::new("/usr/bin/parity")));
+		assert!(!latest_binary_is_newer_in(&env, Path::new("/usr/bin/parity"), Path::new("/updates/parity-1.12.0")));
+	}
+
+	#[test]
+	fn should_wait_for_freshly_written_update_to_settle() {
+		let env = TestEnv::default();
+		let latest = Path::new("/updates/parity-1.12.0");
+		env.add_file_at(latest, "", 98);
+		env.sleep(Duration::from_secs(100));
+
+		assert_eq!(update_settle_remaining_in(&env, latest, Duration::from_secs(5)), Some(Duration::from_secs(3)));
+		assert_eq!(update_settle_remaining_in(&env, latest, Duration::from_secs(2)), None);
+		assert_eq!(update_settle_remaining_in(&env, latest, Duration::from_secs(0)), None);
+	}
+
+	#[test]
+	fn should_consider_undated_and_future_updates_settled() {
+		let env = TestEnv::default();
+		env.add_file_at("/updates/parity-1.12.0", "", 200);
+		env.sleep(Duration::from_secs(100));
+
+		assert_eq!(update_settle_remaining_in(&env, Path::new("/updates/parity-1.12.0"), Duration::from_secs(5)), None);
+		assert_eq!(update_settle_remaining_in(&env, Path::new("/updates/parity-1.13.0"), Duration::from_secs(5)), None);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_date_symlinked_update_by_its_target() {
+		use std::fs::{self, File};
+		use std::os::unix::fs::symlink;
+		use std::thread;
+		use std::time::Duration;
+		use tempdir::TempDir;
+		use launcher::system::RealEnv;
+
+		// rewrites `path` until it's dated after `earlier`, whatever the filesystem's time resolution
+		let create_after = |path: &Path, earlier: &Path| loop {
+			File::create(path).unwrap();
+			if fs::metadata(path).unwrap().modified().unwrap() > fs::metadata(earlier).unwrap().modified().unwrap() {
+				break;
+			}
+			thread::sleep(Duration::from_millis(10));
+		};
+		let tempdir = TempDir::new("").unwrap();
+		let old = tempdir.path().join("parity-1.11.0");
+		let exe = tempdir.path().join("parity-current");
+		let new = tempdir.path().join("parity-1.12.0");
+		File::create(&old).unwrap();
+		create_after(&exe, &old);
+		create_after(&new, &exe);
+		// links created after `exe`, so only their targets tell which update is newer
+		symlink("parity-1.11.0", tempdir.path().join("old")).unwrap();
+		symlink("parity-1.12.0", tempdir.path().join("new")).unwrap();
+
+		assert!(!latest_binary_is_newer_in(&RealEnv, &tempdir.path().join("old"), &exe));
+		assert!(latest_binary_is_newer_in(&RealEnv, &tempdir.path().join("new"), &exe));
+	}
+}
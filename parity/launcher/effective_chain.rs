@@ -0,0 +1,92 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Record of the chain the node runs, so that operators can check chain overrides land.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use super::updates::SpecOverride;
+
+/// Chain the node runs, given the chain override applied, if any, and its command line settings.
+///
+/// An override replaces both the chain and `--testnet`.
+pub fn effective_chain(spec_override: Option<&SpecOverride>, testnet: bool, chain: &str) -> String {
+	match spec_override {
+		Some(spec_override) => spec_override.chain.clone(),
+		None if testnet => "testnet".into(),
+		None => chain.into(),
+	}
+}
+
+/// Writes `chain` to the file at `path`, replacing it at once so that it's never seen half-written.
+///
+/// A file which can't be written is reported without giving up, as the node runs all the same.
+pub fn write_effective_chain(path: &Path, chain: &str) {
+	let mut partial = OsString::from(path.as_os_str());
+	partial.push(".partial");
+	let partial = PathBuf::from(partial);
+	let result = File::create(&partial)
+		.and_then(|mut f| f.write_all(chain.as_bytes()))
+		.and_then(|_| fs::rename(&partial, path));
+	if let Err(e) = result {
+		warn_main!("Couldn't record the effective chain in {}: {}", path.display(), e);
+		let _ = fs::remove_file(&partial);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Read;
+	use std::path::Path;
+	use tempdir::TempDir;
+	use super::{effective_chain, write_effective_chain};
+
+	fn read(path: &Path) -> String {
+		let mut content = String::new();
+		File::open(path).unwrap().read_to_string(&mut content).unwrap();
+		content
+	}
+
+	#[test]
+	fn should_resolve_effective_chain() {
+		assert_eq!(effective_chain(None, false, "foundation"), "foundation");
+		assert_eq!(effective_chain(None, true, "foundation"), "testnet");
+		assert_eq!(effective_chain(Some(&"kovan".into()), true, "foundation"), "kovan");
+	}
+
+	#[test]
+	fn should_write_override_as_effective_chain() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("chain");
+
+		write_effective_chain(&path, &effective_chain(None, false, "foundation"));
+		assert_eq!(read(&path), "foundation");
+
+		// each run replaces the record of the previous one
+		write_effective_chain(&path, &effective_chain(Some(&"kovan".into()), false, "foundation"));
+		assert_eq!(read(&path), "kovan");
+		assert_eq!(tempdir.path().read_dir().unwrap().count(), 1);
+	}
+
+	#[test]
+	fn should_continue_without_writable_effective_chain_file() {
+		let tempdir = TempDir::new("").unwrap();
+		write_effective_chain(&tempdir.path().join("missing").join("chain"), "kovan");
+	}
+}
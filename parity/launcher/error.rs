@@ -0,0 +1,183 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Launcher errors.
+
+use std::{error, fmt};
+
+const SIGHUP: i32 = 1;
+const SIGINT: i32 = 2;
+const SIGKILL: i32 = 9;
+const SIGTERM: i32 = 15;
+
+/// Exit code reported when the child's termination reason is unknown.
+const UNKNOWN_EXIT_CODE: i32 = 128;
+
+/// Errors occurring while looking for or running an installed update.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+	/// There's no `latest` pointer in the updates directory, so no update was ever installed.
+	NotConfigured,
+	/// The default updates directory couldn't be determined, e.g. for lack of a home directory.
+	NoUpdatesDir(String),
+	/// The `latest` pointer couldn't be read.
+	PointerUnreadable(String),
+	/// The `latest` pointer at the given path isn't a regular file, e.g. a directory left behind
+	/// by a botched update.
+	LatestFileUnreadable(String),
+	/// The `latest` pointer doesn't name a runnable binary.
+	BinaryNotFound,
+	/// The child couldn't be started, or its exit status couldn't be determined.
+	UnknownStatusCode,
+	/// The child was terminated by the given signal.
+	KilledBySignal(i32),
+	/// The child was stopped for not becoming healthy in time.
+	Unhealthy,
+	/// The `latest` pointer names a binary which was stopped for not becoming healthy before.
+	MarkedBad,
+	/// The `latest` pointer names a path instead of a binary in the updates directory.
+	UnsafeUpdatePath(String),
+	/// The compressed latest update couldn't be unpacked, or didn't match its hash.
+	UnpackFailed(String),
+}
+
+impl Error {
+	/// Whether running the current binary instead of the update is worth trying.
+	///
+	/// A child stopped from the outside (including `SIGKILL`, which usually means the system ran
+	/// out of memory) would likely meet the same fate, so only crashes fall back.
+	pub fn should_fall_back(&self) -> bool {
+		match *self {
+			Error::KilledBySignal(SIGHUP) | Error::KilledBySignal(SIGINT) |
+			Error::KilledBySignal(SIGKILL) | Error::KilledBySignal(SIGTERM) => false,
+			_ => true,
+		}
+	}
+
+	/// Whether the child was most likely killed for running out of memory.
+	pub fn is_likely_oom(&self) -> bool {
+		*self == Error::KilledBySignal(SIGKILL)
+	}
+
+	/// Exit code the launcher should report; a signal is reported as `128 + signal`.
+	pub fn exit_code(&self) -> i32 {
+		match *self {
+			Error::KilledBySignal(signal) => UNKNOWN_EXIT_CODE + signal,
+			_ => UNKNOWN_EXIT_CODE,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Error::NotConfigured => write!(f, "no update installed"),
+			Error::NoUpdatesDir(ref e) => write!(f, "couldn't determine the updates directory: {}", e),
+			Error::PointerUnreadable(ref e) => write!(f, "couldn't read the latest pointer: {}", e),
+			Error::LatestFileUnreadable(ref path) => write!(f, "the latest pointer {} is not a regular file", path),
+			Error::BinaryNotFound => write!(f, "the latest update is not a runnable binary"),
+			Error::UnknownStatusCode => write!(f, "couldn't determine the exit status of the update"),
+			Error::KilledBySignal(signal) => write!(f, "the update was killed by signal {} ({})", signal, signal_name(signal)),
+			Error::Unhealthy => write!(f, "the update didn't become healthy in time"),
+			Error::MarkedBad => write!(f, "the latest update failed its health check before"),
+			Error::UnpackFailed(ref e) => write!(f, "couldn't unpack the latest update: {}", e),
+			Error::UnsafeUpdatePath(ref name) => write!(f, "the latest pointer names {:?}, outside of the updates directory", name),
+		}
+	}
+}
+
+impl error::Error for Error {}
+
+/// Summary of a failed fallback, if the current binary run after the update failed with `update`
+/// didn't succeed either.
+pub fn describe_fallback_failure(update: &Error, direct_exit_code: i32, restart_exit_code: i32) -> Option<String> {
+	if direct_exit_code == 0 || direct_exit_code == restart_exit_code {
+		return None;
+	}
+	Some(format!(
+		"Couldn't run the node: the latest update failed ({}), and so did the current binary (exit code {}).",
+		update, direct_exit_code
+	))
+}
+
+/// Human-readable name of the signals defined by POSIX with fixed numbers.
+pub fn signal_name(signal: i32) -> &'static str {
+	match signal {
+		SIGHUP => "SIGHUP",
+		SIGINT => "SIGINT",
+		3 => "SIGQUIT",
+		6 => "SIGABRT",
+		SIGKILL => "SIGKILL",
+		11 => "SIGSEGV",
+		13 => "SIGPIPE",
+		14 => "SIGALRM",
+		SIGTERM => "SIGTERM",
+		_ => "unknown signal",
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{describe_fallback_failure, Error};
+
+	#[test]
+	fn should_fall_back_only_on_crashes() {
+		assert!(Error::NotConfigured.should_fall_back());
+		assert!(Error::NoUpdatesDir("no home directory".into()).should_fall_back());
+		assert!(Error::BinaryNotFound.should_fall_back());
+		assert!(Error::UnknownStatusCode.should_fall_back());
+		assert!(Error::KilledBySignal(11).should_fall_back());
+		assert!(Error::Unhealthy.should_fall_back());
+		assert!(!Error::KilledBySignal(9).should_fall_back());
+		assert!(!Error::KilledBySignal(15).should_fall_back());
+	}
+
+	#[test]
+	fn should_report_signal_as_exit_code() {
+		assert_eq!(Error::KilledBySignal(9).exit_code(), 137);
+		assert_eq!(Error::KilledBySignal(11).exit_code(), 139);
+	}
+
+	#[test]
+	fn should_describe_failed_fallback() {
+		assert_eq!(
+			describe_fallback_failure(&Error::KilledBySignal(11), 1, 69),
+			Some("Couldn't run the node: the latest update failed (the update was killed by signal 11 (SIGSEGV)), and so did the current binary (exit code 1).".into())
+		);
+		assert_eq!(describe_fallback_failure(&Error::BinaryNotFound, 0, 69), None);
+		assert_eq!(describe_fallback_failure(&Error::BinaryNotFound, 69, 69), None);
+	}
+
+	#[test]
+	fn should_display_human_friendly_messages() {
+		assert_eq!(Error::NotConfigured.to_string(), "no update installed");
+		assert_eq!(Error::PointerUnreadable("permission denied".into()).to_string(), "couldn't read the latest pointer: permission denied");
+		assert_eq!(
+			Error::LatestFileUnreadable("/updates/latest".into()).to_string(),
+			"the latest pointer /updates/latest is not a regular file"
+		);
+		assert_eq!(Error::BinaryNotFound.to_string(), "the latest update is not a runnable binary");
+		assert_eq!(Error::UnknownStatusCode.to_string(), "couldn't determine the exit status of the update");
+		assert_eq!(Error::KilledBySignal(11).to_string(), "the update was killed by signal 11 (SIGSEGV)");
+		assert_eq!(Error::KilledBySignal(64).to_string(), "the update was killed by signal 64 (unknown signal)");
+	}
+
+	#[test]
+	fn should_be_usable_as_std_error() {
+		let e: Box<::std::error::Error> = Box::new(Error::BinaryNotFound);
+		assert_eq!(e.to_string(), "the latest update is not a runnable binary");
+	}
+}
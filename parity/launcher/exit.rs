@@ -0,0 +1,157 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Exit requests the main thread waits for while the node is running.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use parking_lot::{Condvar, Mutex};
+use super::Housekeeping;
+
+/// Exit request: whether the node asked for a restart, and the chain it wants to restart into.
+pub type ExitState = (bool, Option<String>);
+
+/// Shared between the main thread and everything that may end the node's run.
+///
+/// The node's exit handlers and the watchdog ask for a restart, while Ctrl-C (or a test standing in
+/// for it) interrupts the wait without one.
+pub struct ExitSignal {
+	state: Mutex<ExitState>,
+	condvar: Condvar,
+	interrupted: AtomicBool,
+	switch_cooldown: Duration,
+	last_switch: Mutex<Option<Instant>>,
+}
+
+impl ExitSignal {
+	/// Creates a signal nothing was requested through yet, ignoring chain switches requested within
+	/// `cooldown` of the last honoured one.
+	///
+	/// `last_switch` is when the node last switched chains, if it was started to do so.
+	pub fn with_switch_cooldown(cooldown: Duration, last_switch: Option<Instant>) -> Self {
+		ExitSignal {
+			state: Mutex::new((false, None)),
+			condvar: Condvar::new(),
+			interrupted: AtomicBool::new(false),
+			switch_cooldown: cooldown,
+			last_switch: Mutex::new(last_switch),
+		}
+	}
+
+	/// Asks for the node to be restarted, into `new_chain` if given.
+	pub fn request_restart(&self, new_chain: Option<String>) {
+		*self.state.lock() = (true, new_chain);
+		self.condvar.notify_all();
+	}
+
+	/// Asks for the node to be restarted into `new_chain`, unless it switched chains too recently.
+	///
+	/// Returns whether the switch is honoured; an ignored one keeps the node on its current chain.
+	pub fn request_spec_switch(&self, new_chain: String) -> bool {
+		let now = Instant::now();
+		{
+			let mut last_switch = self.last_switch.lock();
+			if let Some(last) = *last_switch {
+				if now.duration_since(last) < self.switch_cooldown {
+					warn_main!(
+						"Ignoring the switch to chain {}: the last one was {}s ago, within the {}s cooldown.",
+						new_chain, now.duration_since(last).as_secs(), self.switch_cooldown.as_secs()
+					);
+					return false;
+				}
+			}
+			*last_switch = Some(now);
+		}
+		self.request_restart(Some(new_chain));
+		true
+	}
+
+	/// Releases the wait without asking for a restart.
+	pub fn interrupt(&self) {
+		self.interrupted.store(true, Ordering::SeqCst);
+		self.condvar.notify_all();
+	}
+
+	/// Waits for a restart request or an interruption, running `housekeeping` meanwhile.
+	///
+	/// Waiting longer than `timeout` returns the state as it is as an error.
+	pub fn wait(&self, housekeeping: &mut Housekeeping, timeout: Option<Duration>) -> Result<ExitState, ExitState> {
+		housekeeping.wait_until(&self.state, &self.condvar, timeout, |state| state.0 || self.interrupted.load(Ordering::SeqCst))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::{Duration, Instant};
+	use launcher::Housekeeping;
+	use super::ExitSignal;
+
+	fn signal_after<F>(f: F) -> Arc<ExitSignal> where F: FnOnce(&ExitSignal) + Send + 'static {
+		let exit = Arc::new(ExitSignal::with_switch_cooldown(Duration::from_secs(0), None));
+		let e = exit.clone();
+		thread::spawn(move || {
+			thread::sleep(Duration::from_millis(20));
+			f(&e);
+		});
+		exit
+	}
+
+	#[test]
+	fn should_stop_waiting_when_interrupted() {
+		let exit = signal_after(|exit| exit.interrupt());
+		let mut housekeeping = Housekeeping::new(Duration::from_millis(5));
+		assert_eq!(exit.wait(&mut housekeeping, Some(Duration::from_secs(5))), Ok((false, None)));
+	}
+
+	#[test]
+	fn should_stop_waiting_on_restart_request() {
+		let exit = signal_after(|exit| exit.request_restart(Some("kovan".into())));
+		let mut housekeeping = Housekeeping::new(Duration::from_millis(5));
+		assert_eq!(exit.wait(&mut housekeeping, Some(Duration::from_secs(5))), Ok((true, Some("kovan".into()))));
+	}
+
+	#[test]
+	fn should_honour_one_of_two_rapid_spec_switches() {
+		let exit = ExitSignal::with_switch_cooldown(Duration::from_secs(60), None);
+		assert!(exit.request_spec_switch("kovan".into()));
+		assert!(!exit.request_spec_switch("ropsten".into()));
+		let mut housekeeping = Housekeeping::new(Duration::from_millis(5));
+		assert_eq!(exit.wait(&mut housekeeping, Some(Duration::from_secs(5))), Ok((true, Some("kovan".into()))));
+	}
+
+	#[test]
+	fn should_ignore_spec_switch_right_after_starting_on_one() {
+		let exit = ExitSignal::with_switch_cooldown(Duration::from_secs(60), Some(Instant::now()));
+		assert!(!exit.request_spec_switch("kovan".into()));
+		let mut housekeeping = Housekeeping::new(Duration::from_millis(5));
+		assert_eq!(exit.wait(&mut housekeeping, Some(Duration::from_millis(30))), Err((false, None)));
+	}
+
+	#[test]
+	fn should_honour_every_spec_switch_without_cooldown() {
+		let exit = ExitSignal::with_switch_cooldown(Duration::from_secs(0), None);
+		assert!(exit.request_spec_switch("kovan".into()));
+		assert!(exit.request_spec_switch("ropsten".into()));
+	}
+
+	#[test]
+	fn should_keep_waiting_without_request() {
+		let exit = ExitSignal::with_switch_cooldown(Duration::from_secs(0), None);
+		let mut housekeeping = Housekeeping::new(Duration::from_millis(5));
+		assert_eq!(exit.wait(&mut housekeeping, Some(Duration::from_millis(30))), Err((false, None)));
+	}
+}
@@ -0,0 +1,182 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Limit on the number of files the node process may open.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use fdlimit::raise_fd_limit;
+
+/// Fewest open files a full node is recommended to be allowed, for its database and peers.
+const RECOMMENDED_FD_LIMIT: u64 = 4096;
+
+/// How the limit on open files of the node process is set, as given to `--fd-limit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FdLimit {
+	/// Raise the soft limit as far as the system allows.
+	Max,
+	/// Set the soft limit to the given number of files, capped at the hard limit.
+	Files(u64),
+	/// Leave the limit the node was started with.
+	Off,
+}
+
+impl Default for FdLimit {
+	fn default() -> Self {
+		FdLimit::Max
+	}
+}
+
+impl fmt::Display for FdLimit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			FdLimit::Max => write!(f, "max"),
+			FdLimit::Files(files) => write!(f, "{}", files),
+			FdLimit::Off => write!(f, "off"),
+		}
+	}
+}
+
+impl FromStr for FdLimit {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, ()> {
+		match s {
+			"max" => Ok(FdLimit::Max),
+			"off" => Ok(FdLimit::Off),
+			_ => match s.parse() {
+				Ok(files) if files > 0 => Ok(FdLimit::Files(files)),
+				_ => Err(()),
+			},
+		}
+	}
+}
+
+/// Applies `limit` to the running process, logging the limits before and after.
+///
+/// Raising the limit may silently fail, e.g. in a sandbox, so the limit achieved is checked
+/// against the one recommended for a full node.
+pub fn apply_fd_limit(limit: FdLimit) {
+	let before = fd_limits();
+	match limit {
+		FdLimit::Off => trace_main!("Leaving the open files limit at {}", describe(before)),
+		FdLimit::Max => raise_fd_limit(),
+		FdLimit::Files(files) => match set_soft_fd_limit(files) {
+			Ok(set) if set < files => warn_main!("Open files limit {} is above the hard limit, using {} instead.", files, set),
+			Ok(_) => {},
+			Err(e) => warn_main!("Couldn't set the open files limit to {}: {}", files, e),
+		},
+	}
+	let after = fd_limits();
+	if limit != FdLimit::Off {
+		trace_main!("Open files limit changed from {} to {}", describe(before), describe(after));
+	}
+	if let Some(warning) = after.and_then(|(soft, hard)| low_fd_limit_warning(soft, hard)) {
+		warn_main!("{}", warning);
+	}
+}
+
+fn describe(limits: Option<(u64, u64)>) -> String {
+	limits.map_or("unknown".to_owned(), |(soft, hard)| format!("{} (hard limit {})", soft, hard))
+}
+
+/// Warning about a `soft` limit too low for a full node, with what can be done given the `hard` one.
+fn low_fd_limit_warning(soft: u64, hard: u64) -> Option<String> {
+	if soft >= RECOMMENDED_FD_LIMIT {
+		return None;
+	}
+	let advice = if hard >= RECOMMENDED_FD_LIMIT {
+		format!("use --fd-limit={} or --fd-limit=max to raise it", RECOMMENDED_FD_LIMIT)
+	} else {
+		"raise the hard limit, e.g. in /etc/security/limits.conf or the service's LimitNOFILE".to_owned()
+	};
+	Some(format!(
+		"Open files limit {} is below the {} recommended for a full node, which may fail with \"too many open files\"; {}.",
+		soft, RECOMMENDED_FD_LIMIT, advice
+	))
+}
+
+/// Returns the soft and hard limits on open files of the running process.
+#[cfg(unix)]
+fn fd_limits() -> Option<(u64, u64)> {
+	use libc;
+
+	let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+	if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+		return None;
+	}
+	Some((limit.rlim_cur as u64, limit.rlim_max as u64))
+}
+
+#[cfg(not(unix))]
+fn fd_limits() -> Option<(u64, u64)> {
+	None
+}
+
+/// Sets the soft limit on open files to `files`, capped at the hard limit, returning the limit set.
+#[cfg(unix)]
+fn set_soft_fd_limit(files: u64) -> io::Result<u64> {
+	use libc;
+
+	let (_, hard) = fd_limits().ok_or_else(io::Error::last_os_error)?;
+	let files = if files < hard { files } else { hard };
+	let limit = libc::rlimit { rlim_cur: files as libc::rlim_t, rlim_max: hard as libc::rlim_t };
+	if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(files)
+}
+
+#[cfg(not(unix))]
+fn set_soft_fd_limit(_files: u64) -> io::Result<u64> {
+	Err(io::Error::new(io::ErrorKind::Other, "not supported on this platform"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{low_fd_limit_warning, FdLimit};
+
+	#[test]
+	fn should_parse_fd_limit() {
+		assert_eq!("max".parse(), Ok(FdLimit::Max));
+		assert_eq!("off".parse(), Ok(FdLimit::Off));
+		assert_eq!("4096".parse(), Ok(FdLimit::Files(4096)));
+		assert_eq!("0".parse::<FdLimit>(), Err(()));
+		assert_eq!("unlimited".parse::<FdLimit>(), Err(()));
+		for limit in &[FdLimit::Max, FdLimit::Off, FdLimit::Files(4096)] {
+			assert_eq!(limit.to_string().parse(), Ok(*limit));
+		}
+	}
+
+	#[test]
+	fn should_warn_about_low_fd_limit() {
+		assert_eq!(low_fd_limit_warning(4096, 4096), None);
+		assert_eq!(low_fd_limit_warning(65536, 1048576), None);
+		assert!(low_fd_limit_warning(1024, 1048576).unwrap().contains("--fd-limit=4096"));
+		assert!(low_fd_limit_warning(1024, 1024).unwrap().contains("raise the hard limit"));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_cap_fd_limit_at_hard_limit() {
+		use super::{fd_limits, set_soft_fd_limit};
+
+		let (soft, hard) = fd_limits().unwrap();
+		assert_eq!(set_soft_fd_limit(u64::max_value()).unwrap(), hard);
+		assert_eq!(fd_limits(), Some((hard, hard)));
+		assert_eq!(set_soft_fd_limit(soft).unwrap(), soft);
+	}
+}
@@ -0,0 +1,474 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Warm restarts, handing the listening sockets of the node over to the one replacing it.
+//!
+//! With `--warm-restart`, each child is given one end of a socket pair in `PARITY_HANDOFF_FD`.
+//! Before exiting to be restarted, the node sends duplicates of its listeners over it, which the
+//! launcher holds on to and passes to the next child. Those are listed in `PARITY_LISTEN_FDS`,
+//! much like systemd's `LISTEN_FDS` but naming each descriptor, e.g. `p2p=5`. The sockets are
+//! never closed in between, so connections are queued instead of refused while the node restarts.
+//! Descriptors can only be passed between processes that way on Unix.
+
+use std::net::TcpListener;
+use std::process::Command;
+#[cfg(unix)]
+use std::env;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::ptr;
+#[cfg(unix)]
+use std::str;
+#[cfg(unix)]
+use libc;
+#[cfg(unix)]
+use parking_lot::Mutex;
+use super::child::EnvOverride;
+
+/// Variable giving the node the socket to hand its listeners over through.
+#[cfg(unix)]
+const HANDOFF_FD_ENV: &str = "PARITY_HANDOFF_FD";
+
+/// Variable listing the listeners handed down to the node, as `name=fd` separated by commas.
+#[cfg(unix)]
+const LISTEN_FDS_ENV: &str = "PARITY_LISTEN_FDS";
+
+/// Most listeners handed over at once.
+#[cfg(unix)]
+const MAX_LISTENERS: usize = 8;
+
+/// Size of the buffers the listeners are handed over with, in words so they're aligned for the
+/// control message header.
+#[cfg(unix)]
+const CONTROL_WORDS: usize = 16;
+#[cfg(unix)]
+const NAMES_LEN: usize = 256;
+
+#[cfg(unix)]
+lazy_static! {
+	static ref HANDOFF: Mutex<Launcher> = Mutex::new(Launcher::default());
+}
+
+/// Warm restart state of the launcher.
+#[cfg(unix)]
+#[derive(Default)]
+struct Launcher {
+	/// Listeners handed over by the last child, for the next one.
+	listeners: Vec<(String, TcpListener)>,
+	/// End of the socket pair the running child hands its listeners over to.
+	receiver: Option<UnixDatagram>,
+	/// End given to the child, only kept open until it's spawned.
+	sender: Option<UnixDatagram>,
+}
+
+/// Sets `command` up to spawn a child able to hand its listeners over, and listening on the ones
+/// handed over by the previous child. Returns the changes to its environment telling it so.
+#[cfg(unix)]
+pub fn offer_handoff(command: &mut Command) -> Vec<EnvOverride> {
+	use std::os::unix::process::CommandExt;
+
+	let mut handoff = HANDOFF.lock();
+	let mut fds: Vec<RawFd> = handoff.listeners.iter().map(|&(_, ref listener)| listener.as_raw_fd()).collect();
+	let listen_fds = if handoff.listeners.is_empty() {
+		None
+	} else {
+		let listeners: Vec<_> = handoff.listeners.iter().map(|&(ref name, ref listener)| (name.as_str(), listener.as_raw_fd())).collect();
+		Some(format_listen_fds(&listeners))
+	};
+	let mut overrides = vec![(LISTEN_FDS_ENV.to_owned(), listen_fds)];
+
+	match UnixDatagram::pair() {
+		Ok((receiver, sender)) => {
+			fds.push(sender.as_raw_fd());
+			overrides.push((HANDOFF_FD_ENV.to_owned(), Some(sender.as_raw_fd().to_string())));
+			handoff.receiver = Some(receiver);
+			handoff.sender = Some(sender);
+		},
+		Err(e) => {
+			warn_main!("Couldn't set up the warm restart, the node will restart cold: {}", e);
+			overrides.push((HANDOFF_FD_ENV.to_owned(), None));
+			handoff.receiver = None;
+			handoff.sender = None;
+		},
+	}
+
+	// only the child gets the descriptors, which are closed on exec everywhere else
+	let inherit = move || {
+		for &fd in &fds {
+			set_close_on_exec(fd, false)?;
+		}
+		Ok(())
+	};
+	unsafe { command.pre_exec(inherit); }
+	overrides
+}
+
+#[cfg(not(unix))]
+pub fn offer_handoff(_command: &mut Command) -> Vec<EnvOverride> {
+	Vec::new()
+}
+
+/// Closes what the launcher only kept open for the child it just spawned.
+///
+/// Even the handed over listeners are closed, so they're released if the child doesn't listen on
+/// them after all.
+#[cfg(unix)]
+pub fn handoff_spawned() {
+	let mut handoff = HANDOFF.lock();
+	handoff.sender = None;
+	handoff.listeners.clear();
+}
+
+#[cfg(not(unix))]
+pub fn handoff_spawned() {}
+
+/// Takes the listeners the child which just exited handed over, if it's to be `restarted`.
+#[cfg(unix)]
+pub fn collect_handoff(restarted: bool) {
+	let mut handoff = HANDOFF.lock();
+	handoff.sender = None;
+	handoff.listeners.clear();
+	let receiver = match handoff.receiver.take() {
+		Some(receiver) => receiver,
+		None => return,
+	};
+	if !restarted {
+		return;
+	}
+	match receive_listeners(&receiver) {
+		Ok(listeners) => {
+			trace_main!("Holding on to the {} listener(s) over the restart", listeners.iter().map(|&(ref name, _)| name.as_str()).collect::<Vec<_>>().join(", "));
+			handoff.listeners = listeners;
+		},
+		Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => trace_main!("No listeners were handed over, the node will restart cold"),
+		Err(e) => warn_main!("Couldn't take the listeners handed over, the node will restart cold: {}", e),
+	}
+}
+
+#[cfg(not(unix))]
+pub fn collect_handoff(_restarted: bool) {}
+
+/// Socket the node was given to hand its listeners over to the launcher through.
+pub struct Handoff {
+	#[cfg(unix)]
+	socket: UnixDatagram,
+}
+
+impl Handoff {
+	/// Hands duplicates of the node's `listeners` over to the launcher, for the node replacing it.
+	///
+	/// A warm restart is best-effort: when they can't be handed over, the next node binds anew.
+	#[cfg(unix)]
+	pub fn hand_over(&self, listeners: &[(String, TcpListener)]) {
+		if listeners.is_empty() {
+			return;
+		}
+		match send_listeners(&self.socket, listeners) {
+			Ok(()) => trace_main!("Handed the listeners over to the launcher for the restart"),
+			Err(e) => warn_main!("Couldn't hand the listeners over to the launcher, the node will restart cold: {}", e),
+		}
+	}
+
+	#[cfg(not(unix))]
+	pub fn hand_over(&self, _listeners: &[(String, TcpListener)]) {}
+}
+
+/// Takes the socket the node is to hand its listeners over through, when run with `--warm-restart`.
+#[cfg(unix)]
+pub fn take_handoff() -> Option<Handoff> {
+	let value = env::var(HANDOFF_FD_ENV).ok()?;
+	env::remove_var(HANDOFF_FD_ENV);
+	match value.parse::<RawFd>() {
+		Ok(fd) if fd > 2 => {
+			if let Err(e) = set_close_on_exec(fd, true) {
+				warn_main!("Can't hand the listeners over through descriptor {}: {}", fd, e);
+				return None;
+			}
+			Some(Handoff { socket: unsafe { UnixDatagram::from_raw_fd(fd) } })
+		},
+		_ => {
+			warn_main!("Ignoring {} of {:?}, which isn't a descriptor.", HANDOFF_FD_ENV, value);
+			None
+		},
+	}
+}
+
+#[cfg(not(unix))]
+pub fn take_handoff() -> Option<Handoff> {
+	None
+}
+
+/// Takes the listeners handed down to the node by the one it replaces.
+#[cfg(unix)]
+pub fn take_inherited_listeners() -> Vec<(String, TcpListener)> {
+	let value = match env::var(LISTEN_FDS_ENV) {
+		Ok(value) => value,
+		Err(_) => return Vec::new(),
+	};
+	env::remove_var(LISTEN_FDS_ENV);
+	parse_listen_fds(&value).into_iter().filter_map(|(name, fd)| match set_close_on_exec(fd, true) {
+		Ok(()) => {
+			trace_main!("Taking over the {} listener handed down as descriptor {}", name, fd);
+			Some((name, unsafe { TcpListener::from_raw_fd(fd) }))
+		},
+		Err(e) => {
+			warn_main!("Not taking over the {} listener handed down as descriptor {}: {}", name, fd, e);
+			None
+		},
+	}).collect()
+}
+
+#[cfg(not(unix))]
+pub fn take_inherited_listeners() -> Vec<(String, TcpListener)> {
+	Vec::new()
+}
+
+/// Lists `listeners` the way `PARITY_LISTEN_FDS` does.
+#[cfg(unix)]
+fn format_listen_fds(listeners: &[(&str, RawFd)]) -> String {
+	listeners.iter().map(|&(name, fd)| format!("{}={}", name, fd)).collect::<Vec<_>>().join(",")
+}
+
+/// Parses the listeners listed in `PARITY_LISTEN_FDS`, skipping any which aren't a named descriptor.
+#[cfg(unix)]
+fn parse_listen_fds(value: &str) -> Vec<(String, RawFd)> {
+	value.split(',').filter(|entry| !entry.is_empty()).filter_map(|entry| {
+		let mut parts = entry.splitn(2, '=');
+		match (parts.next(), parts.next().map(str::parse::<RawFd>)) {
+			(Some(name), Some(Ok(fd))) if !name.is_empty() && fd > 2 => Some((name.to_owned(), fd)),
+			_ => {
+				warn_main!("Ignoring {} entry {:?}, which isn't a named descriptor.", LISTEN_FDS_ENV, entry);
+				None
+			},
+		}
+	}).collect()
+}
+
+/// Sends `listeners` over `socket` in a single message: their names as its data, separated by
+/// commas, and their descriptors alongside.
+#[cfg(unix)]
+fn send_listeners(socket: &UnixDatagram, listeners: &[(String, TcpListener)]) -> io::Result<()> {
+	if listeners.len() > MAX_LISTENERS {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("can't hand over more than {} listeners", MAX_LISTENERS)));
+	}
+	let names = listeners.iter().map(|&(ref name, _)| name.as_str()).collect::<Vec<_>>().join(",");
+	let fds: Vec<RawFd> = listeners.iter().map(|&(_, ref listener)| listener.as_raw_fd()).collect();
+	let fds_len = fds.len() * mem::size_of::<RawFd>();
+
+	let mut control = [0usize; CONTROL_WORDS];
+	let mut iov = libc::iovec { iov_base: names.as_ptr() as *mut _, iov_len: names.len() as _ };
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as *mut _;
+	msg.msg_controllen = cmsg_space(fds_len) as _;
+	unsafe {
+		let header = control.as_mut_ptr() as *mut libc::cmsghdr;
+		(*header).cmsg_level = libc::SOL_SOCKET;
+		(*header).cmsg_type = libc::SCM_RIGHTS;
+		(*header).cmsg_len = cmsg_len(fds_len) as _;
+		ptr::copy_nonoverlapping(fds.as_ptr(), cmsg_data(header) as *mut RawFd, fds.len());
+	}
+
+	if unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+/// Receives the listeners sent over `socket` by `send_listeners`, without waiting for them.
+#[cfg(unix)]
+fn receive_listeners(socket: &UnixDatagram) -> io::Result<Vec<(String, TcpListener)>> {
+	let mut names = [0u8; NAMES_LEN];
+	let mut control = [0usize; CONTROL_WORDS];
+	let mut iov = libc::iovec { iov_base: names.as_mut_ptr() as *mut _, iov_len: names.len() as _ };
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as *mut _;
+	msg.msg_controllen = mem::size_of_val(&control) as _;
+
+	socket.set_nonblocking(true)?;
+	let read = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+	if read < 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	// the descriptors are owned first, so they're closed again if anything is amiss
+	let mut listeners = Vec::new();
+	if msg.msg_controllen as usize >= cmsg_len(0) {
+		let header = control.as_ptr() as *const libc::cmsghdr;
+		unsafe {
+			if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_RIGHTS {
+				let count = ((*header).cmsg_len as usize - cmsg_len(0)) / mem::size_of::<RawFd>();
+				let fds = cmsg_data(header) as *const RawFd;
+				for i in 0..count {
+					listeners.push(TcpListener::from_raw_fd(*fds.add(i)));
+				}
+			}
+		}
+	}
+	for listener in &listeners {
+		set_close_on_exec(listener.as_raw_fd(), true)?;
+	}
+	if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "more listeners were handed over than can be taken"));
+	}
+
+	let names = str::from_utf8(&names[..read as usize])
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "listener names aren't UTF-8"))?;
+	let names: Vec<_> = names.split(',').map(str::to_owned).collect();
+	if names.len() != listeners.len() {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} listeners were handed over for {:?}", listeners.len(), names)));
+	}
+	Ok(names.into_iter().zip(listeners).collect())
+}
+
+/// Has `fd` closed on exec, or inherited by the executed program.
+#[cfg(unix)]
+fn set_close_on_exec(fd: RawFd, close: bool) -> io::Result<()> {
+	let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+	if flags < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let flags = if close { flags | libc::FD_CLOEXEC } else { flags & !libc::FD_CLOEXEC };
+	if unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+// `CMSG_ALIGN`, `CMSG_LEN`, `CMSG_SPACE` and `CMSG_DATA`, for a single control message header.
+#[cfg(unix)]
+fn cmsg_align(len: usize) -> usize {
+	let align = if cfg!(any(target_os = "macos", target_os = "ios")) { mem::size_of::<u32>() } else { mem::size_of::<usize>() };
+	(len + align - 1) & !(align - 1)
+}
+
+#[cfg(unix)]
+fn cmsg_len(len: usize) -> usize {
+	cmsg_align(mem::size_of::<libc::cmsghdr>()) + len
+}
+
+#[cfg(unix)]
+fn cmsg_space(len: usize) -> usize {
+	cmsg_align(mem::size_of::<libc::cmsghdr>()) + cmsg_align(len)
+}
+
+#[cfg(unix)]
+unsafe fn cmsg_data(header: *const libc::cmsghdr) -> *const u8 {
+	(header as *const u8).offset(cmsg_len(0) as isize)
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::io::{Read, Write};
+	use std::net::{TcpListener, TcpStream};
+	use std::os::unix::io::AsRawFd;
+	use std::os::unix::net::UnixDatagram;
+	use std::process::Command;
+	use launcher::child::build_child_env;
+	use super::{
+		collect_handoff, format_listen_fds, handoff_spawned, offer_handoff, parse_listen_fds, receive_listeners,
+		send_listeners, HANDOFF,
+	};
+
+	#[test]
+	fn should_list_listen_fds() {
+		assert_eq!(format_listen_fds(&[("p2p", 5), ("rpc", 7)]), "p2p=5,rpc=7");
+		assert_eq!(parse_listen_fds("p2p=5,rpc=7"), vec![("p2p".to_owned(), 5), ("rpc".to_owned(), 7)]);
+	}
+
+	#[test]
+	fn should_skip_listen_fds_which_are_not_named_descriptors() {
+		assert_eq!(parse_listen_fds(""), vec![]);
+		assert_eq!(parse_listen_fds("p2p,=5,rpc=seven,ws=1,ipfs=8"), vec![("ipfs".to_owned(), 8)]);
+	}
+
+	#[test]
+	fn should_hand_listener_over_through_socket() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let (receiver, sender) = UnixDatagram::pair().unwrap();
+
+		send_listeners(&sender, &[("p2p".to_owned(), listener)]).unwrap();
+		let listeners = receive_listeners(&receiver).unwrap();
+
+		// the original is closed already, so a connection can only be accepted by the one handed over
+		assert_eq!(listeners.len(), 1);
+		assert_eq!(listeners[0].0, "p2p");
+		let mut client = TcpStream::connect(addr).unwrap();
+		let (mut accepted, _) = listeners[0].1.accept().unwrap();
+		client.write_all(b"hello").unwrap();
+		let mut greeting = [0u8; 5];
+		accepted.read_exact(&mut greeting).unwrap();
+		assert_eq!(&greeting, b"hello");
+	}
+
+	#[test]
+	fn should_not_wait_for_listeners_never_handed_over() {
+		let (receiver, _sender) = UnixDatagram::pair().unwrap();
+		assert_eq!(receive_listeners(&receiver).unwrap_err().kind(), ::std::io::ErrorKind::WouldBlock);
+	}
+
+	#[test]
+	fn should_hold_listeners_over_restart_for_next_child() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		// the first child, standing in for the node, hands the listener over before exiting
+		let mut first = Command::new("/bin/sh");
+		first.args(&["-c", "exit 69"]);
+		offer_handoff(&mut first);
+		send_listeners(HANDOFF.lock().sender.as_ref().unwrap(), &[("p2p".to_owned(), listener)]).unwrap();
+		handoff_spawned();
+		collect_handoff(true);
+		let fd = {
+			let handoff = HANDOFF.lock();
+			assert_eq!(handoff.listeners.len(), 1);
+			assert_eq!(handoff.listeners[0].1.local_addr().unwrap(), addr);
+			handoff.listeners[0].1.as_raw_fd()
+		};
+
+		// the next one is told about it, and inherits it along with a socket of its own
+		let script = format!(
+			"test \"$PARITY_LISTEN_FDS\" = p2p={0} -a -n \"$PARITY_HANDOFF_FD\" && \
+				{{ test ! -d /proc/self/fd || test -h /proc/self/fd/{0} -a -h /proc/self/fd/$PARITY_HANDOFF_FD; }}",
+			fd,
+		);
+		let mut next = Command::new("/bin/sh");
+		next.args(&["-c", &script]);
+		let overrides = offer_handoff(&mut next);
+		next.env_clear().envs(build_child_env(::std::env::vars_os(), &overrides));
+		let mut child = next.spawn().unwrap();
+		handoff_spawned();
+		assert!(child.wait().unwrap().success());
+
+		// nothing was handed over by then, and nothing is held for a child which isn't restarted
+		assert!(HANDOFF.lock().listeners.is_empty());
+		collect_handoff(false);
+		assert!(HANDOFF.lock().listeners.is_empty());
+		assert!(HANDOFF.lock().receiver.is_none());
+	}
+}
@@ -0,0 +1,190 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Health check of a freshly started update.
+//!
+//! An update may come up broken without exiting, e.g. stuck before serving any request. With
+//! `--update-health-url`, the URL is probed while the update starts; an update which doesn't
+//! answer successfully in time is stopped, so that the launcher can go back to the current binary.
+
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::system::retry_on_interrupt;
+use super::http::get;
+
+/// Time an update has to become healthy in.
+pub const HEALTH_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Time between two probes of an update which isn't healthy yet.
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time allowed for each step of a probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time between two checks of whether the child exited, while it's being probed.
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// Time a child asked to stop has to exit before it's killed.
+const STOP_GRACE: Duration = Duration::from_secs(30);
+
+/// How a supervised child ended.
+#[derive(Debug)]
+pub struct Supervised {
+	/// Exit status of the child.
+	pub status: io::Result<ExitStatus>,
+	/// The child was stopped for not becoming healthy in time.
+	pub unhealthy: bool,
+}
+
+/// Waits for `child` to exit, stopping it if `url` doesn't answer successfully within `window`.
+///
+/// The URL is probed from a supervisor thread every `interval`, until it succeeds once.
+pub fn supervise(child: &mut Child, url: &str, window: Duration, interval: Duration) -> Supervised {
+	let (healthy_tx, healthy_rx) = mpsc::channel();
+	let done = Arc::new(AtomicBool::new(false));
+	{
+		let url = url.to_owned();
+		let done = done.clone();
+		thread::spawn(move || {
+			let started = Instant::now();
+			while !done.load(Ordering::SeqCst) {
+				match get(&url, PROBE_TIMEOUT) {
+					Ok(()) => {
+						let _ = healthy_tx.send(true);
+						return;
+					},
+					Err(ref e) if started.elapsed() >= window => {
+						let _ = healthy_tx.send(false);
+						trace_main!("Last health probe of {} failed: {}", url, e);
+						return;
+					},
+					Err(_) => thread::sleep(interval),
+				}
+			}
+		});
+	}
+
+	let mut unhealthy = false;
+	let status = loop {
+		match child.try_wait() {
+			Ok(Some(status)) => break Ok(status),
+			Ok(None) => {},
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => break Err(e),
+		}
+
+		match healthy_rx.try_recv() {
+			Ok(true) => {
+				trace_main!("Update became healthy at {}", url);
+				break wait(child);
+			},
+			Ok(false) => {
+				warn_main!("Update didn't become healthy at {} within {}s, stopping it.", url, window.as_secs());
+				unhealthy = true;
+				break stop(child);
+			},
+			Err(TryRecvError::Empty) => thread::sleep(Duration::from_millis(POLL_INTERVAL_MS)),
+			Err(TryRecvError::Disconnected) => break wait(child),
+		}
+	};
+	done.store(true, Ordering::SeqCst);
+	Supervised { status, unhealthy }
+}
+
+fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+	retry_on_interrupt(|| child.wait())
+}
+
+/// Asks `child` to shut down, killing it if it hasn't exited after `STOP_GRACE`.
+fn stop(child: &mut Child) -> io::Result<ExitStatus> {
+	terminate(child);
+	let asked = Instant::now();
+	while asked.elapsed() < STOP_GRACE {
+		if let Some(status) = child.try_wait()? {
+			return Ok(status);
+		}
+		thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+	}
+	warn_main!("Update didn't shut down within {}s, killing it.", STOP_GRACE.as_secs());
+	let _ = child.kill();
+	wait(child)
+}
+
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+	use libc;
+	unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM); }
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+	let _ = child.kill();
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::io::{Read, Write};
+	use std::net::TcpListener;
+	use std::os::unix::process::ExitStatusExt;
+	use std::process::Command;
+	use std::thread;
+	use std::time::Duration;
+	use super::supervise;
+
+	#[test]
+	fn should_stop_child_never_healthy() {
+		// nothing listens on the port of a listener that was dropped
+		let url = format!("http://{}/health", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+		let mut child = Command::new("/bin/sh").args(&["-c", "sleep 30"]).spawn().unwrap();
+
+		let supervised = supervise(&mut child, &url, Duration::from_millis(300), Duration::from_millis(50));
+		assert!(supervised.unhealthy);
+		assert_eq!(supervised.status.unwrap().signal(), Some(15));
+	}
+
+	#[test]
+	fn should_let_healthy_child_run() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}/health", listener.local_addr().unwrap());
+		thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut request = [0u8; 1024];
+			let _ = stream.read(&mut request).unwrap();
+			stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+		});
+		let mut child = Command::new("/bin/sh").args(&["-c", "sleep 1; exit 3"]).spawn().unwrap();
+
+		let supervised = supervise(&mut child, &url, Duration::from_millis(300), Duration::from_millis(50));
+		assert!(!supervised.unhealthy);
+		assert_eq!(supervised.status.unwrap().code(), Some(3));
+	}
+
+	#[test]
+	fn should_not_probe_child_which_exited() {
+		let url = format!("http://{}/health", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+		let mut child = Command::new("/bin/sh").args(&["-c", "exit 1"]).spawn().unwrap();
+
+		let supervised = supervise(&mut child, &url, Duration::from_secs(60), Duration::from_millis(50));
+		assert!(!supervised.unhealthy);
+		assert_eq!(supervised.status.unwrap().code(), Some(1));
+	}
+}
@@ -0,0 +1,101 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Commands run by the launcher around the node's lifetime.
+
+use std::fmt;
+use std::io;
+use std::process::{Command, ExitStatus};
+use super::LauncherConfig;
+
+/// Why the node exited, as passed to the post-exit command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitReason {
+	/// The node exited and won't be started again.
+	Exit,
+	/// The node asked to be restarted.
+	Restart,
+}
+
+impl fmt::Display for ExitReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ExitReason::Exit => write!(f, "exit"),
+			ExitReason::Restart => write!(f, "restart"),
+		}
+	}
+}
+
+/// Runs the `--post-exit-cmd` command, if any, passing it the exit reason and code.
+///
+/// A failing command is only reported; it doesn't change how the launcher exits.
+pub fn run_post_exit_cmd(launcher: &LauncherConfig, reason: ExitReason, code: i32) {
+	if let Some(ref cmd) = launcher.post_exit_cmd {
+		trace_main!("Running post-exit command {} ({} {})", cmd, reason, code);
+		match post_exit_status(cmd, reason, code) {
+			Ok(ref status) if status.success() => {},
+			Ok(status) => warn_main!("Post-exit command {} failed: {}", cmd, status),
+			Err(e) => warn_main!("Couldn't run post-exit command {}: {}", cmd, e),
+		}
+	}
+}
+
+fn post_exit_status(cmd: &str, reason: ExitReason, code: i32) -> io::Result<ExitStatus> {
+	Command::new(cmd).arg(reason.to_string()).arg(code.to_string()).status()
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::fs::{self, File};
+	use std::io::{Read, Write};
+	use std::os::unix::fs::PermissionsExt;
+	use std::path::Path;
+	use tempdir::TempDir;
+	use super::{post_exit_status, ExitReason};
+
+	fn stub_command(dir: &Path) -> String {
+		let path = dir.join("post-exit");
+		let script = format!("#!/bin/sh\necho \"$1 $2\" > {}\n", dir.join("called").display());
+		File::create(&path).unwrap().write_all(script.as_bytes()).unwrap();
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+		path.to_string_lossy().into_owned()
+	}
+
+	fn called_with(dir: &Path) -> String {
+		let mut content = String::new();
+		File::open(dir.join("called")).unwrap().read_to_string(&mut content).unwrap();
+		content
+	}
+
+	#[test]
+	fn should_pass_exit_reason_and_code() {
+		let tempdir = TempDir::new("").unwrap();
+		let cmd = stub_command(tempdir.path());
+
+		assert!(post_exit_status(&cmd, ExitReason::Exit, 137).unwrap().success());
+		assert_eq!(called_with(tempdir.path()), "exit 137\n");
+
+		assert!(post_exit_status(&cmd, ExitReason::Restart, 69).unwrap().success());
+		assert_eq!(called_with(tempdir.path()), "restart 69\n");
+	}
+
+	#[test]
+	fn should_report_missing_command() {
+		let tempdir = TempDir::new("").unwrap();
+		assert!(post_exit_status(&tempdir.path().join("missing").to_string_lossy(), ExitReason::Exit, 0).is_err());
+	}
+}
@@ -0,0 +1,136 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Periodic work done by the main thread while it waits for the node to exit.
+
+use std::time::{Duration, Instant};
+use parking_lot::{Condvar, Mutex};
+
+/// Housekeeping tasks, run whenever waiting for the node to exit times out.
+pub struct Housekeeping {
+	interval: Duration,
+	tasks: Vec<Box<FnMut() + Send>>,
+}
+
+impl Housekeeping {
+	/// Creates housekeeping running its tasks every `interval`.
+	pub fn new(interval: Duration) -> Self {
+		Housekeeping {
+			interval,
+			tasks: Vec::new(),
+		}
+	}
+
+	/// Registers a task to run periodically.
+	pub fn register<F>(&mut self, task: F) where F: FnMut() + Send + 'static {
+		self.tasks.push(Box::new(task));
+	}
+
+	/// Waits until `done` holds for the state guarded by `mutex`, returning a copy of the state.
+	///
+	/// The state is re-checked on every wakeup, so spurious wakeups are harmless. The tasks are run
+	/// with the lock released, so that they may notify the waiting thread themselves. Waiting longer
+	/// than `timeout` returns the state as it is as an error.
+	pub fn wait_until<T, F>(&mut self, mutex: &Mutex<T>, condvar: &Condvar, timeout: Option<Duration>, done: F) -> Result<T, T> where
+		T: Clone,
+		F: Fn(&T) -> bool,
+	{
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		loop {
+			{
+				let mut lock = mutex.lock();
+				if done(&lock) {
+					return Ok(lock.clone());
+				}
+				let wait = match deadline {
+					Some(deadline) => {
+						let now = Instant::now();
+						if now >= deadline {
+							return Err(lock.clone());
+						}
+						self.interval.min(deadline - now)
+					},
+					None => self.interval,
+				};
+				let timed_out = condvar.wait_for(&mut lock, wait).timed_out();
+				if done(&lock) {
+					return Ok(lock.clone());
+				}
+				if !timed_out {
+					continue;
+				}
+			}
+
+			for task in &mut self.tasks {
+				task();
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::thread;
+	use std::time::{Duration, Instant};
+	use parking_lot::{Condvar, Mutex};
+	use super::Housekeeping;
+
+	#[test]
+	fn should_run_housekeeping_while_waiting() {
+		let runs = Arc::new(AtomicUsize::new(0));
+		let exit = Arc::new((Mutex::new(false), Condvar::new()));
+		let mut housekeeping = Housekeeping::new(Duration::from_millis(10));
+		housekeeping.register({
+			let runs = runs.clone();
+			move || { runs.fetch_add(1, Ordering::SeqCst); }
+		});
+
+		let notifier = {
+			let exit = exit.clone();
+			thread::spawn(move || {
+				thread::sleep(Duration::from_millis(100));
+				*exit.0.lock() = true;
+				exit.1.notify_all();
+			})
+		};
+
+		assert_eq!(housekeeping.wait_until(&exit.0, &exit.1, None, |done| *done), Ok(true));
+		assert!(runs.load(Ordering::SeqCst) >= 1);
+		notifier.join().unwrap();
+	}
+
+	#[test]
+	fn should_not_wait_when_already_done() {
+		let exit = (Mutex::new(true), Condvar::new());
+		let mut housekeeping = Housekeeping::new(Duration::from_secs(60));
+		housekeeping.register(|| panic!("housekeeping shouldn't run"));
+
+		assert_eq!(housekeeping.wait_until(&exit.0, &exit.1, None, |done| *done), Ok(true));
+	}
+
+	#[test]
+	fn should_give_up_waiting_after_timeout() {
+		let exit = (Mutex::new(false), Condvar::new());
+		let mut housekeeping = Housekeeping::new(Duration::from_secs(60));
+
+		let started = Instant::now();
+		assert_eq!(housekeeping.wait_until(&exit.0, &exit.1, Some(Duration::from_millis(50)), |done| *done), Err(false));
+		assert!(started.elapsed() >= Duration::from_millis(50));
+		assert!(started.elapsed() < Duration::from_secs(60));
+	}
+}
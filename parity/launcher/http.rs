@@ -0,0 +1,136 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Minimal HTTP client for the launcher's notifications and probes, supporting `http://` only.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Checks `url` is an `http://` URL, returning the host, port and path to request.
+pub fn parse_http_url(url: &str) -> Result<(String, u16, String), String> {
+	if !url.starts_with("http://") {
+		return Err(format!("Only http:// URLs are supported, got {}", url));
+	}
+	let rest = &url["http://".len()..];
+	let (authority, path) = match rest.find('/') {
+		Some(pos) => (&rest[..pos], &rest[pos..]),
+		None => (rest, "/"),
+	};
+	let (host, port) = match authority.rfind(':') {
+		Some(pos) => (&authority[..pos], authority[pos + 1..].parse().map_err(|_| format!("Invalid port in {}", url))?),
+		None => (authority, 80),
+	};
+	if host.is_empty() {
+		return Err(format!("Missing host in {}", url));
+	}
+	Ok((host.to_owned(), port, path.to_owned()))
+}
+
+/// Posts JSON `body` to `url`, succeeding if the response has a `2xx` status.
+pub fn post(url: &str, body: &str, timeout: Duration) -> io::Result<()> {
+	send(url, timeout, |path, host| format!(
+		"POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		path, host, body.len(), body
+	))
+}
+
+/// Gets `url`, succeeding if the response has a `2xx` status.
+pub fn get(url: &str, timeout: Duration) -> io::Result<()> {
+	send(url, timeout, |path, host| format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host))
+}
+
+/// Sends the request built by `request` from the path and host of `url`, allowing `timeout` for
+/// each step.
+fn send<F>(url: &str, timeout: Duration, request: F) -> io::Result<()> where F: FnOnce(&str, &str) -> String {
+	let (host, port, path) = parse_http_url(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+	let addr = (host.as_str(), port).to_socket_addrs()?.next()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} doesn't resolve", host)))?;
+	let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+	stream.set_read_timeout(Some(timeout))?;
+	stream.set_write_timeout(Some(timeout))?;
+	stream.write_all(request(&path, &host).as_bytes())?;
+
+	let mut status = [0u8; 12];
+	stream.read_exact(&mut status)?;
+	match status[9] {
+		b'2' => Ok(()),
+		_ => Err(io::Error::new(io::ErrorKind::Other, String::from_utf8_lossy(&status).into_owned())),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Read, Write};
+	use std::net::TcpListener;
+	use std::thread;
+	use std::time::Duration;
+	use super::{get, parse_http_url, post};
+
+	/// Answers a single request with `response`, returning the request once it ends with `end`.
+	fn serve_once(response: &'static [u8], end: &'static [u8]) -> (String, thread::JoinHandle<String>) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let server = thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut request = Vec::new();
+			let mut chunk = [0u8; 4096];
+			while !request.ends_with(end) {
+				let len = stream.read(&mut chunk).unwrap();
+				assert!(len > 0, "connection closed before the request ended");
+				request.extend_from_slice(&chunk[..len]);
+			}
+			stream.write_all(response).unwrap();
+			String::from_utf8_lossy(&request).into_owned()
+		});
+		(format!("http://{}", addr), server)
+	}
+
+	#[test]
+	fn should_parse_http_url() {
+		assert_eq!(parse_http_url("http://monitor:8080/hooks/parity"), Ok(("monitor".into(), 8080, "/hooks/parity".into())));
+		assert_eq!(parse_http_url("http://monitor"), Ok(("monitor".into(), 80, "/".into())));
+		assert!(parse_http_url("https://monitor/hooks").is_err());
+		assert!(parse_http_url("http://monitor:http/").is_err());
+		assert!(parse_http_url("http:///hooks").is_err());
+	}
+
+	#[test]
+	fn should_post_json_body() {
+		let (url, server) = serve_once(b"HTTP/1.1 204 No Content\r\n\r\n", b"}");
+
+		post(&format!("{}/hooks", url), r#"{"reason":"check_only"}"#, Duration::from_secs(5)).unwrap();
+		let request = server.join().unwrap();
+		assert!(request.starts_with("POST /hooks HTTP/1.1\r\n"));
+		assert!(request.ends_with("\r\n\r\n{\"reason\":\"check_only\"}"));
+	}
+
+	#[test]
+	fn should_fail_get_on_error_status() {
+		let (url, server) = serve_once(b"HTTP/1.1 503 Service Unavailable\r\n\r\n", b"\r\n\r\n");
+
+		assert!(get(&format!("{}/health", url), Duration::from_secs(5)).is_err());
+		assert!(server.join().unwrap().starts_with("GET /health HTTP/1.1\r\n"));
+	}
+
+	#[test]
+	fn should_get_successfully() {
+		let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\n\r\n", b"\r\n\r\n");
+
+		get(&url, Duration::from_secs(5)).unwrap();
+		assert!(server.join().unwrap().starts_with("GET / HTTP/1.1\r\n"));
+	}
+}
@@ -0,0 +1,65 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Random delay spreading the restarts of nodes switching to the same update at once.
+
+use std::thread;
+use std::time::Duration;
+use rand::{self, Rng};
+
+/// Waits for a random time below `max` before restarting into an update, if `max` isn't zero.
+///
+/// The generator is seeded for each process, so nodes updated together restart at different times.
+pub fn wait_restart_jitter(max: Duration) {
+	let delay = restart_jitter(&mut rand::thread_rng(), max);
+	if delay > Duration::from_millis(0) {
+		trace_main!("Waiting {}ms before restarting into the update", millis(delay));
+		thread::sleep(delay);
+	}
+}
+
+/// Picks a delay uniformly below `max`, to the millisecond.
+fn restart_jitter<R: Rng>(rng: &mut R, max: Duration) -> Duration {
+	match millis(max) {
+		0 => Duration::from_millis(0),
+		max => Duration::from_millis(rng.gen_range(0, max)),
+	}
+}
+
+fn millis(duration: Duration) -> u64 {
+	duration.as_secs() * 1000 + u64::from(duration.subsec_nanos()) / 1_000_000
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use rand;
+	use super::restart_jitter;
+
+	#[test]
+	fn should_pick_jitter_below_max() {
+		let mut rng = rand::thread_rng();
+		for _ in 0..100 {
+			assert!(restart_jitter(&mut rng, Duration::from_secs(2)) < Duration::from_secs(2));
+		}
+	}
+
+	#[test]
+	fn should_not_wait_without_jitter() {
+		let mut rng = rand::thread_rng();
+		assert_eq!(restart_jitter(&mut rng, Duration::from_millis(0)), Duration::from_millis(0));
+		assert_eq!(restart_jitter(&mut rng, Duration::from_millis(1)), Duration::from_millis(0));
+	}
+}
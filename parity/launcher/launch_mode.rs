@@ -0,0 +1,85 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! How the node was launched, told to it through `PARITY_LAUNCH_MODE`.
+//!
+//! The node process always has exactly one of these values set:
+//! - `updated`: it's an installed update, run as a child of the launcher,
+//! - `supervised`: it's the current binary, run by the launcher which restarts it when asked, in
+//!   the launcher process or as its child with `--supervise`,
+//! - `direct`: it's the current binary, run without the launcher restarting it.
+
+use std::env;
+
+/// Environment variable telling the node how it was launched.
+pub const LAUNCH_MODE_ENV: &str = "PARITY_LAUNCH_MODE";
+
+/// How the node was launched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LaunchMode {
+	/// An installed update, run as a child of the launcher.
+	Updated,
+	/// The current binary, run by the launcher which restarts it when asked.
+	Supervised,
+	/// The current binary, run without the launcher restarting it.
+	Direct,
+}
+
+impl LaunchMode {
+	/// Value of `PARITY_LAUNCH_MODE` for this mode.
+	pub fn as_str(&self) -> &'static str {
+		match *self {
+			LaunchMode::Updated => "updated",
+			LaunchMode::Supervised => "supervised",
+			LaunchMode::Direct => "direct",
+		}
+	}
+
+	/// Mode of a node run in this process, `supervised` by the launcher loop or not.
+	///
+	/// A node which isn't supervised is an update or a supervised child if its launcher said so in
+	/// `inherited`.
+	fn detect(supervised: bool, inherited: Option<&str>) -> Self {
+		match inherited {
+			_ if supervised => LaunchMode::Supervised,
+			Some("updated") => LaunchMode::Updated,
+			Some("supervised") => LaunchMode::Supervised,
+			_ => LaunchMode::Direct,
+		}
+	}
+}
+
+/// Tells the node run in this process how it was launched, replacing any inherited mode.
+pub fn set_launch_mode(supervised: bool) -> LaunchMode {
+	let mode = LaunchMode::detect(supervised, env::var(LAUNCH_MODE_ENV).ok().as_ref().map(String::as_str));
+	env::set_var(LAUNCH_MODE_ENV, mode.as_str());
+	mode
+}
+
+#[cfg(test)]
+mod tests {
+	use super::LaunchMode;
+
+	#[test]
+	fn should_detect_launch_mode() {
+		assert_eq!(LaunchMode::detect(true, None), LaunchMode::Supervised);
+		assert_eq!(LaunchMode::detect(true, Some("updated")), LaunchMode::Supervised);
+		assert_eq!(LaunchMode::detect(false, Some("updated")), LaunchMode::Updated);
+		assert_eq!(LaunchMode::detect(false, None), LaunchMode::Direct);
+		assert_eq!(LaunchMode::detect(false, Some("supervised")), LaunchMode::Supervised);
+		assert_eq!(LaunchMode::detect(false, Some("direct")), LaunchMode::Direct);
+		assert_eq!(LaunchMode::detect(false, Some("bogus")), LaunchMode::Direct);
+	}
+}
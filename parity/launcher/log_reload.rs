@@ -0,0 +1,147 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Changing the log levels of the running node on `SIGHUP`, without restarting it.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use parity::LogReloader;
+
+/// Set by the `SIGHUP` handler, and taken on the next housekeeping wakeup.
+static HANGUP: AtomicBool = AtomicBool::new(false);
+
+/// Logger whose levels can be changed while it runs.
+pub trait ReloadLog {
+	/// Rebuilds the levels from the startup configuration, with `levels` applied on top.
+	fn reload(&self, levels: Option<&str>) -> Result<(), String>;
+}
+
+impl ReloadLog for LogReloader {
+	fn reload(&self, levels: Option<&str>) -> Result<(), String> {
+		LogReloader::reload(self, levels)
+	}
+}
+
+/// Makes `SIGHUP` ask for the log levels to be reloaded, instead of terminating the process.
+#[cfg(unix)]
+pub fn reload_log_on_hangup() {
+	use libc;
+
+	unsafe { libc::signal(libc::SIGHUP, on_hangup as libc::sighandler_t); }
+}
+
+#[cfg(unix)]
+extern "C" fn on_hangup(_signal: ::libc::c_int) {
+	// setting a flag is all that's async-signal-safe; the reload happens on housekeeping
+	HANGUP.store(true, Ordering::SeqCst);
+}
+
+#[cfg(not(unix))]
+pub fn reload_log_on_hangup() {}
+
+/// Reloads the log levels of `logger` if `SIGHUP` arrived since the last call.
+///
+/// Levels are read from `file` if given, and otherwise from `RUST_LOG` as the node was started
+/// with. An empty file brings back the levels the node was started with.
+pub fn reload_log_if_asked<R: ReloadLog>(logger: &R, file: Option<&Path>) {
+	reload_if_set(&HANGUP, logger, file);
+}
+
+fn reload_if_set<R: ReloadLog>(asked: &AtomicBool, logger: &R, file: Option<&Path>) -> bool {
+	if !asked.swap(false, Ordering::SeqCst) {
+		return false;
+	}
+
+	let levels = match file {
+		Some(path) => match read_levels(path) {
+			Ok(levels) => levels,
+			Err(e) => {
+				warn_main!("Not reloading log levels, couldn't read {}: {}", path.display(), e);
+				return false;
+			},
+		},
+		None => None,
+	};
+	trace_main!("Reloading log levels ({})", levels.as_ref().map_or("as started", String::as_str));
+	if let Err(e) = logger.reload(levels.as_ref().map(String::as_str)) {
+		warn_main!("Couldn't reload log levels: {}", e);
+		return false;
+	}
+	true
+}
+
+fn read_levels(path: &Path) -> io::Result<Option<String>> {
+	let mut levels = String::new();
+	File::open(path)?.read_to_string(&mut levels)?;
+	let levels = levels.trim();
+	Ok(if levels.is_empty() { None } else { Some(levels.to_owned()) })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::fs::File;
+	use std::io::Write;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use tempdir::TempDir;
+	use super::{reload_if_set, ReloadLog};
+
+	#[derive(Default)]
+	struct Logger {
+		reloads: RefCell<Vec<Option<String>>>,
+	}
+
+	impl ReloadLog for Logger {
+		fn reload(&self, levels: Option<&str>) -> Result<(), String> {
+			self.reloads.borrow_mut().push(levels.map(Into::into));
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn should_reload_only_once_asked() {
+		let logger = Logger::default();
+		let asked = AtomicBool::new(false);
+
+		assert!(!reload_if_set(&asked, &logger, None));
+		assert!(logger.reloads.borrow().is_empty());
+
+		asked.store(true, Ordering::SeqCst);
+		assert!(reload_if_set(&asked, &logger, None));
+		assert!(!reload_if_set(&asked, &logger, None));
+		assert_eq!(*logger.reloads.borrow(), vec![None]);
+	}
+
+	#[test]
+	fn should_reload_levels_from_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("log-levels");
+		File::create(&path).unwrap().write_all(b"sync=debug,rpc=trace\n").unwrap();
+		let logger = Logger::default();
+		let asked = AtomicBool::new(true);
+
+		assert!(reload_if_set(&asked, &logger, Some(&path)));
+		File::create(&path).unwrap();
+		asked.store(true, Ordering::SeqCst);
+		assert!(reload_if_set(&asked, &logger, Some(&path)));
+		assert_eq!(*logger.reloads.borrow(), vec![Some("sync=debug,rpc=trace".into()), None]);
+
+		asked.store(true, Ordering::SeqCst);
+		assert!(!reload_if_set(&asked, &logger, Some(&tempdir.path().join("missing"))));
+		assert_eq!(logger.reloads.borrow().len(), 2);
+	}
+}
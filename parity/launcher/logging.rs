@@ -0,0 +1,188 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Dedicated log file for the launcher's own messages.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use parking_lot::Mutex;
+
+/// Whether launcher messages are kept off stdout and stderr, leaving them to the node.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+	static ref LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+	/// Held while writing a launcher message anywhere, so that messages logged from several threads
+	/// at once don't interleave.
+	static ref STDOUT: Mutex<io::Stdout> = Mutex::new(io::stdout());
+}
+
+/// Keeps launcher messages off stdout and stderr if `quiet`, writing them to the log file only.
+///
+/// The node's own output is unaffected.
+pub fn set_quiet(quiet: bool) {
+	QUIET.store(quiet, Ordering::SeqCst);
+}
+
+/// Appends launcher messages to the file at `path`, on top of the usual output.
+///
+/// A file that can't be opened is reported on stderr and otherwise ignored.
+pub fn log_launcher_to(path: &Path) {
+	match OpenOptions::new().create(true).append(true).open(path) {
+		Ok(file) => *LOG_FILE.lock() = Some(file),
+		Err(e) => {
+			let _ = write_console_line(&mut io::stderr(), &format!("Couldn't open launcher log {}: {}", path.display(), e));
+		},
+	}
+}
+
+/// Whether launcher traces are printed on stdout given `RUST_LOG`, which is only when asked for
+/// with `main=trace`.
+///
+/// Stdout is otherwise left to the node, whose output may be parsed.
+pub fn traces_to_stdout(rust_log: Option<&str>) -> bool {
+	rust_log.map_or(false, |levels| levels.contains("main=trace"))
+}
+
+/// Writes a launcher trace message to the log file, if any, and to stdout if `to_stdout`.
+pub fn write_trace_line(line: &str, to_stdout: bool) {
+	let stdout = STDOUT.lock();
+	write_log_line(line);
+	if to_stdout {
+		let _ = write_console_line(&mut stdout.lock(), line);
+	}
+}
+
+/// Writes a launcher warning to the log file, if any, and to stderr.
+pub fn write_warn_line(line: &str) {
+	let _stdout = STDOUT.lock();
+	write_log_line(line);
+	let _ = write_console_line(&mut io::stderr(), line);
+}
+
+fn write_log_line(line: &str) {
+	if let Some(ref mut file) = *LOG_FILE.lock() {
+		let _ = write_line(file, line);
+	}
+}
+
+/// Writes `line` to stdout or stderr `out`, unless launcher messages are kept off them.
+fn write_console_line<W: Write>(out: &mut W, line: &str) -> io::Result<()> {
+	if QUIET.load(Ordering::SeqCst) {
+		return Ok(());
+	}
+	write_line(out, line)
+}
+
+/// Writes `line` with a single write, so that it can't be split by another writer.
+fn write_line<W: Write>(out: &mut W, line: &str) -> io::Result<()> {
+	let mut buf = String::with_capacity(line.len() + 1);
+	buf.push_str(line);
+	buf.push('\n');
+	out.write_all(buf.as_bytes())?;
+	out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::{self, Read, Write};
+	use parking_lot::Mutex;
+	use tempdir::TempDir;
+	use super::{log_launcher_to, set_quiet, traces_to_stdout, write_console_line, write_line, LOG_FILE};
+
+	lazy_static! {
+		/// Held by tests opening a launcher log, so that they don't see each other's log file.
+		static ref LOGGING: Mutex<()> = Mutex::new(());
+	}
+
+	/// Records each write separately.
+	#[derive(Default)]
+	struct Writes(Vec<Vec<u8>>);
+
+	impl Write for Writes {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.push(buf.to_vec());
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn should_write_line_at_once() {
+		let mut writes = Writes::default();
+		write_line(&mut writes, "first").unwrap();
+		write_line(&mut writes, "second").unwrap();
+		assert_eq!(writes.0, vec![b"first\n".to_vec(), b"second\n".to_vec()]);
+	}
+
+	#[test]
+	fn should_keep_traces_off_stdout_by_default() {
+		assert!(!traces_to_stdout(None));
+		assert!(!traces_to_stdout(Some("info")));
+		assert!(!traces_to_stdout(Some("main=debug,sync=trace")));
+		assert!(traces_to_stdout(Some("main=trace")));
+		assert!(traces_to_stdout(Some("info,main=trace")));
+	}
+
+	#[test]
+	fn should_keep_launcher_lines_off_console_when_quiet() {
+		let mut console = Writes::default();
+		set_quiet(true);
+		write_console_line(&mut console, "launcher message").unwrap();
+		set_quiet(false);
+		assert!(console.0.is_empty());
+
+		write_console_line(&mut console, "launcher message").unwrap();
+		assert_eq!(console.0, vec![b"launcher message\n".to_vec()]);
+	}
+
+	#[test]
+	fn should_write_only_launcher_messages_to_log_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("launcher.log");
+		let _logging = LOGGING.lock();
+
+		log_launcher_to(&path);
+		warn_main!("launcher message");
+		trace_main!("launcher trace");
+		println!("node output");
+		*LOG_FILE.lock() = None;
+
+		let mut content = String::new();
+		File::open(&path).unwrap().read_to_string(&mut content).unwrap();
+		assert!(content.contains("launcher message\n"));
+		assert!(content.contains("launcher trace\n"));
+		assert!(!content.contains("node output"));
+	}
+
+	#[test]
+	fn should_continue_without_unopenable_log_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let _logging = LOGGING.lock();
+
+		log_launcher_to(&tempdir.path().join("missing").join("launcher.log"));
+		assert!(LOG_FILE.lock().is_none());
+		warn_main!("still reported");
+		assert!(LOG_FILE.lock().is_none());
+		assert!(!tempdir.path().join("missing").exists());
+	}
+}
@@ -0,0 +1,105 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Launcher logic of the `parity` executable.
+//!
+//! The launcher decides whether to run the current binary or an installed update, and restarts
+//! the node when it asks for it.
+
+mod args;
+mod child;
+mod commands;
+mod compressed;
+mod config;
+mod config_file;
+mod config_watch;
+mod crash_output;
+mod decision;
+mod detect;
+mod effective_chain;
+mod error;
+mod exit;
+mod fd_limit;
+mod handoff;
+mod health;
+mod hooks;
+mod http;
+mod housekeeping;
+mod jitter;
+mod launch_mode;
+mod log_reload;
+mod logging;
+mod notify;
+mod output;
+mod panics;
+mod patch;
+mod pidfile;
+mod plan;
+mod ports;
+mod selftest;
+mod service;
+mod slots;
+mod sockets;
+mod status;
+mod supervisor;
+mod system;
+mod telemetry;
+mod updates;
+mod verify;
+mod version;
+mod watchdog;
+
+pub use self::args::merge_sticky_args;
+pub use self::child::{run_current, run_fallback_binary, run_latest};
+pub use self::commands::execute;
+pub use self::config::{LauncherCommand, LauncherConfig};
+pub use self::config_watch::{ConfigWatch, CONFIG_CHANGE_DEBOUNCE};
+pub use self::decision::{
+	describe_spec_mismatch, describe_update_state, is_allowed_version, is_downgrade, is_likely_crash, is_update_restart,
+	should_run_update, LaunchDecision,
+};
+pub use self::detect::{is_not_latest, is_same_binary, latest_binary_is_newer, Startup};
+pub use self::effective_chain::{effective_chain, write_effective_chain};
+pub use self::error::{describe_fallback_failure, Error};
+pub use self::exit::ExitSignal;
+pub use self::hooks::{run_post_exit_cmd, ExitReason};
+pub use self::housekeeping::Housekeeping;
+pub use self::jitter::wait_restart_jitter;
+pub use self::launch_mode::set_launch_mode;
+pub use self::logging::{log_launcher_to, set_quiet, traces_to_stdout, write_trace_line, write_warn_line};
+pub use self::notify::{notify_deferred_update, DeferReason};
+pub use self::fd_limit::apply_fd_limit;
+pub use self::handoff::{take_handoff, take_inherited_listeners};
+pub use self::log_reload::{reload_log_if_asked, reload_log_on_hangup};
+pub use self::output::render_instant_output;
+pub use self::panics::{record_panics, take_panic_marker, PanicMarker, PanicTracker};
+pub use self::patch::apply_pending_patch;
+pub use self::pidfile::{remove_pid_file, write_pid_file};
+pub use self::plan::{plan_update, UpdatePlan};
+pub use self::ports::{node_ports, wait_ports_free};
+pub use self::service::{is_likely_service, run_as_service, set_service_stop_handler};
+pub use self::slots::{trial_slot, Trial};
+pub use self::sockets::init_sockets;
+pub use self::status::{LauncherStatus, StatusServer, METRICS_PATH};
+pub use self::supervisor::{forward_signals, is_supervised_crash, stop_signal, supervised_exit_code};
+pub use self::telemetry::report_decision;
+pub use self::updates::{
+	ack_spec_name_override, ensure_updates_dir, is_direct_chain, latest_exe_path, peek_spec_name_override, set_running_spec,
+	set_spec_name_override, set_sticky_args, take_running_spec, take_spec_name_override, take_sticky_args,
+	take_update_apply_request,
+};
+pub use self::version::{current_version, staged_version};
+pub use self::watchdog::Watchdog;
@@ -0,0 +1,113 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Webhook notification of installed updates the launcher doesn't run.
+//!
+//! Each staged version is notified once, even across restarts. The notification is posted from
+//! a background thread, so a slow or missing receiver never holds the node back.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use semver::Version;
+use serde_json;
+use super::LauncherConfig;
+use super::http::post;
+
+/// File holding the last staged version a notification was sent for.
+pub const NOTIFIED_VERSION_FILE: &str = "notified_version";
+
+/// Time allowed for each step of posting a notification.
+const POST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why an installed update isn't run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeferReason {
+	/// The update's version is above `--max-update-version`.
+	VersionCeiling,
+	/// The chain is pinned to the current binary by a marker file.
+	ChainPinned,
+	/// The launcher runs with `--update-check-only`.
+	CheckOnly,
+	/// The update's version is below the current one, and `--allow-downgrade` isn't given.
+	Downgrade,
+	/// The update was built for another release channel than `--expected-channel`.
+	ChannelMismatch,
+	/// The update was modified less than `--update-settle-time` ago, so it may still be written.
+	Unsettled,
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+	current_version: &'a str,
+	available_version: &'a str,
+	reason: DeferReason,
+}
+
+/// Notifies `--update-notify-url` that the update of version `available` isn't run, unless that
+/// version was notified already.
+///
+/// A read-only launcher can't record notified versions, so it notifies on every run.
+pub fn notify_deferred_update(launcher: &LauncherConfig, available: Option<&Version>, reason: DeferReason) {
+	let url = match launcher.update_notify_url {
+		Some(ref url) => url.clone(),
+		None => return,
+	};
+	let available = available.map_or("unknown".to_owned(), |v| v.to_string());
+	if launcher.updates_disabled || (!launcher.readonly && !claim_notification(&launcher.updates_dir, &available)) {
+		return;
+	}
+
+	let body = serde_json::to_string(&Notification {
+		current_version: env!("CARGO_PKG_VERSION"),
+		available_version: &available,
+		reason,
+	}).expect("the notification only holds strings; qed");
+	thread::spawn(move || {
+		if let Err(e) = post(&url, &body, POST_TIMEOUT) {
+			trace_main!("Couldn't notify {} of the deferred update: {}", url, e);
+		}
+	});
+}
+
+/// Records `version` as notified, returning whether it wasn't already.
+fn claim_notification(dir: &Path, version: &str) -> bool {
+	let path = dir.join(NOTIFIED_VERSION_FILE);
+	let mut notified = String::new();
+	if File::open(&path).and_then(|mut f| f.read_to_string(&mut notified)).is_ok() && notified == version {
+		return false;
+	}
+	if let Err(e) = File::create(&path).and_then(|mut f| f.write_all(version.as_bytes())) {
+		trace_main!("Couldn't record the notified version at {}: {}", path.display(), e);
+	}
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use tempdir::TempDir;
+	use super::claim_notification;
+
+	#[test]
+	fn should_notify_each_version_once() {
+		let tempdir = TempDir::new("").unwrap();
+		assert!(claim_notification(tempdir.path(), "1.12.0"));
+		assert!(!claim_notification(tempdir.path(), "1.12.0"));
+		assert!(claim_notification(tempdir.path(), "1.12.1"));
+	}
+}
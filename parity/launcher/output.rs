@@ -0,0 +1,95 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Printing the output of commands which don't start a node.
+
+use std::str::FromStr;
+use serde_json::{self, Value};
+
+/// How the output of commands which don't start a node is printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+	/// The human-readable text.
+	Human,
+	/// The structured form as JSON, for the commands which have one.
+	Json,
+}
+
+impl OutputFormat {
+	/// Name of the format, as given to `--output-format`.
+	pub fn as_str(&self) -> &'static str {
+		match *self {
+			OutputFormat::Human => "human",
+			OutputFormat::Json => "json",
+		}
+	}
+}
+
+impl Default for OutputFormat {
+	fn default() -> Self {
+		OutputFormat::Human
+	}
+}
+
+impl FromStr for OutputFormat {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, ()> {
+		match s {
+			"human" => Ok(OutputFormat::Human),
+			"json" => Ok(OutputFormat::Json),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Renders a command's output in `format`, falling back to `text` when there is no `structured` form.
+pub fn render_instant_output(text: &str, structured: Option<&Value>, format: OutputFormat) -> String {
+	match (format, structured) {
+		(OutputFormat::Json, Some(value)) => serde_json::to_string(value).unwrap_or_else(|_| text.to_owned()),
+		_ => text.to_owned(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::Value;
+	use super::{render_instant_output, OutputFormat};
+
+	#[test]
+	fn should_parse_output_format() {
+		assert_eq!("human".parse(), Ok(OutputFormat::Human));
+		assert_eq!("json".parse(), Ok(OutputFormat::Json));
+		assert_eq!("yaml".parse::<OutputFormat>(), Err(()));
+	}
+
+	#[test]
+	fn should_render_human_output() {
+		let accounts = Value::from(vec!["0x01".to_owned(), "0x02".to_owned()]);
+		assert_eq!(render_instant_output("0x01\n0x02", Some(&accounts), OutputFormat::Human), "0x01\n0x02");
+	}
+
+	#[test]
+	fn should_render_json_output() {
+		let accounts = Value::from(vec!["0x01".to_owned(), "0x02".to_owned()]);
+		assert_eq!(render_instant_output("0x01\n0x02", Some(&accounts), OutputFormat::Json), r#"["0x01","0x02"]"#);
+	}
+
+	#[test]
+	fn should_fall_back_to_text_without_structured_output() {
+		assert_eq!(render_instant_output("Parity v1.12", None, OutputFormat::Json), "Parity v1.12");
+	}
+}
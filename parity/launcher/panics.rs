@@ -0,0 +1,184 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Panic markers surviving the restart loop, so a crash loop can be detected and reported.
+//!
+//! Whichever process panics, the launcher or its child, leaves a marker in the updates directory
+//! identifying the panic. The launcher takes it after the node exited and stops relaunching once
+//! the same panic happened too many times in a row.
+
+use std::fs::{remove_file, File};
+use std::io::{Read, Write};
+use std::panic::{self, PanicInfo};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use hash::keccak;
+use super::LauncherConfig;
+
+/// File holding the marker of the last panic.
+pub const PANIC_MARKER_FILE: &str = "last_panic";
+
+/// Panic left behind by a node run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanicMarker {
+	/// Short hash of the panic message and location, equal for repeated panics.
+	pub id: String,
+	/// Human-readable description of the panic.
+	pub report: String,
+}
+
+impl PanicMarker {
+	/// Marker standing for a node which exited with `exit_code` within the crash grace period
+	/// without panicking, identical for repeated exits with the same code.
+	pub fn quick_exit(exit_code: i32, ran_for: Duration) -> Self {
+		PanicMarker {
+			id: format!("exit-{}", exit_code),
+			report: format!("exited with code {} after {}ms", exit_code, ran_for.as_secs() * 1000 + u64::from(ran_for.subsec_nanos()) / 1_000_000),
+		}
+	}
+}
+
+/// Makes panics leave a marker in the updates directory, on top of the current panic hook.
+pub fn record_panics(launcher: &LauncherConfig) {
+	if !launcher.can_write_state() {
+		return;
+	}
+	let path = launcher.updates_path(PANIC_MARKER_FILE);
+	let previous = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		write_marker(&path, &describe(info));
+		previous(info);
+	}));
+}
+
+/// Takes the marker left by the last panic, removing it from disk.
+pub fn take_panic_marker(launcher: &LauncherConfig) -> Option<PanicMarker> {
+	if !launcher.can_write_state() {
+		return None;
+	}
+	take_marker(&launcher.updates_path(PANIC_MARKER_FILE))
+}
+
+/// Counts how many times in a row the node panicked the same way.
+#[derive(Debug, Default)]
+pub struct PanicTracker {
+	last: Option<PanicMarker>,
+	repeats: u32,
+}
+
+impl PanicTracker {
+	/// Records how a node run ended, returning the number of identical panics in a row.
+	///
+	/// A run that didn't panic resets the count.
+	pub fn record(&mut self, marker: Option<PanicMarker>) -> u32 {
+		match marker {
+			Some(marker) => {
+				let same = self.last.as_ref().map_or(false, |last| last.id == marker.id);
+				self.repeats = if same { self.repeats + 1 } else { 1 };
+				self.last = Some(marker);
+			},
+			None => {
+				self.last = None;
+				self.repeats = 0;
+			},
+		}
+		self.repeats
+	}
+
+	/// The last recorded panic, if the last run panicked.
+	pub fn last(&self) -> Option<&PanicMarker> {
+		self.last.as_ref()
+	}
+}
+
+fn describe(info: &PanicInfo) -> String {
+	let msg = match info.payload().downcast_ref::<&'static str>() {
+		Some(s) => *s,
+		None => match info.payload().downcast_ref::<String>() {
+			Some(s) => &s[..],
+			None => "Box<Any>",
+		},
+	};
+	let location = info.location().map_or("<unknown>".to_owned(), |l| format!("{}:{}", l.file(), l.line()));
+	format!("Thread '{}' panicked at '{}', {}", thread::current().name().unwrap_or("<unnamed>"), msg, location)
+}
+
+fn write_marker(path: &Path, report: &str) {
+	// the thread name isn't part of the identity, as the same bug may hit any worker thread
+	let identity = report.splitn(2, " panicked at ").nth(1).unwrap_or(report);
+	let id: String = keccak(identity)[..8].iter().map(|b| format!("{:02x}", b)).collect();
+	let _ = File::create(path).and_then(|mut f| write!(f, "{}\n{}", id, report));
+}
+
+fn take_marker(path: &Path) -> Option<PanicMarker> {
+	let mut content = String::new();
+	File::open(path).and_then(|mut f| f.read_to_string(&mut content)).ok()?;
+	let _ = remove_file(path);
+
+	let mut lines = content.splitn(2, '\n');
+	match (lines.next(), lines.next()) {
+		(Some(id), Some(report)) if !id.is_empty() => Some(PanicMarker { id: id.into(), report: report.into() }),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use tempdir::TempDir;
+	use super::{take_marker, write_marker, PanicMarker, PanicTracker};
+
+	fn marker(id: &str) -> Option<PanicMarker> {
+		Some(PanicMarker { id: id.into(), report: format!("panic {}", id) })
+	}
+
+	#[test]
+	fn should_identify_panics_regardless_of_thread() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("last_panic");
+
+		write_marker(&path, "Thread 'IO Worker #1' panicked at 'boom', src/lib.rs:1");
+		let first = take_marker(&path).unwrap();
+		assert_eq!(first.report, "Thread 'IO Worker #1' panicked at 'boom', src/lib.rs:1");
+		assert!(!path.exists());
+
+		write_marker(&path, "Thread 'IO Worker #2' panicked at 'boom', src/lib.rs:1");
+		assert_eq!(take_marker(&path).unwrap().id, first.id);
+		write_marker(&path, "Thread 'IO Worker #1' panicked at 'bang', src/lib.rs:1");
+		assert!(take_marker(&path).unwrap().id != first.id);
+	}
+
+	#[test]
+	fn should_count_repeated_quick_exits_as_identical() {
+		let mut tracker = PanicTracker::default();
+		assert_eq!(tracker.record(Some(PanicMarker::quick_exit(1, Duration::from_millis(120)))), 1);
+		assert_eq!(tracker.record(Some(PanicMarker::quick_exit(1, Duration::from_millis(80)))), 2);
+		assert_eq!(tracker.last().unwrap().report, "exited with code 1 after 80ms");
+		assert_eq!(tracker.record(Some(PanicMarker::quick_exit(2, Duration::from_millis(80)))), 1);
+	}
+
+	#[test]
+	fn should_count_identical_panics_in_a_row() {
+		let mut tracker = PanicTracker::default();
+		assert_eq!(tracker.record(marker("a")), 1);
+		assert_eq!(tracker.record(marker("a")), 2);
+		assert_eq!(tracker.record(marker("b")), 1);
+		assert_eq!(tracker.last(), marker("b").as_ref());
+		assert_eq!(tracker.record(None), 0);
+		assert_eq!(tracker.last(), None);
+		assert_eq!(tracker.record(marker("b")), 1);
+	}
+}
@@ -0,0 +1,281 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Binary delta updates, applied by the launcher to the running executable.
+//!
+//! The patch format follows bsdiff, without compression:
+//!
+//! - `PARPATCH` magic,
+//! - keccak hash of the patched binary (32 bytes),
+//! - name of the patched binary in the updates directory (u16 length, then UTF-8 bytes),
+//! - length of the patched binary (u64),
+//! - control blocks until the patched binary is complete, each made of the lengths of the diff
+//!   and extra data (u64 each), a seek in the base binary (i64), the diff data, added bytewise to
+//!   the base binary, and the extra data, copied as is.
+//!
+//! Integers are little-endian.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use hash::keccak;
+use semver::Version;
+use super::LauncherConfig;
+use super::updates::LATEST_FILE;
+use super::version::BINARY_PREFIX;
+
+/// File holding a patch turning the running executable into the latest update.
+pub const LATEST_PATCH_FILE: &str = "latest.patch";
+
+/// File holding the version of the executable `latest.patch` applies to.
+pub const PATCH_BASE_VERSION_FILE: &str = "patch-base-version";
+
+const MAGIC: &[u8] = b"PARPATCH";
+
+/// Applies a pending patch to the running executable, installing the result as the latest update.
+///
+/// A patch which doesn't apply is removed and otherwise ignored, leaving installed updates as they
+/// are. A read-only launcher leaves pending patches alone.
+pub fn apply_pending_patch(launcher: &LauncherConfig) {
+	let patch_path = launcher.updates_path(LATEST_PATCH_FILE);
+	let base_version_path = launcher.updates_path(PATCH_BASE_VERSION_FILE);
+	if !launcher.can_write_state() || !patch_path.is_file() || !base_version_path.is_file() {
+		return;
+	}
+
+	match install_patch(launcher, &patch_path, &base_version_path) {
+		Ok(name) => trace_main!("Installed {} from {}", name, patch_path.display()),
+		Err(e) => warn_main!("Ignoring update patch {}: {}", patch_path.display(), e),
+	}
+
+	let _ = fs::remove_file(patch_path);
+	let _ = fs::remove_file(base_version_path);
+}
+
+fn install_patch(launcher: &LauncherConfig, patch_path: &Path, base_version_path: &Path) -> Result<String, String> {
+	let base_version = read(base_version_path)
+		.and_then(|v| String::from_utf8(v).map_err(|e| e.to_string()))
+		.and_then(|v| Version::parse(v.trim()).map_err(|e| e.to_string()))?;
+	let current_version = Version::parse(env!("CARGO_PKG_VERSION")).expect("the package version is valid semver; qed");
+	if base_version != current_version {
+		return Err(format!("it applies to version {}, running {}", base_version, current_version));
+	}
+
+	let exe = launcher.current_exe.as_ref().ok_or_else(|| "couldn't determine the running executable".to_owned())?;
+	let (name, binary) = apply_patch(&read(exe)?, &read(patch_path)?)?;
+	if !name.starts_with(BINARY_PREFIX) || name.contains(|c| c == '/' || c == '\\') {
+		return Err(format!("invalid binary name {}", name));
+	}
+
+	let path = launcher.updates_path(&name);
+	write_executable(&path, &binary).map_err(|e| format!("couldn't write {}: {}", path.display(), e))?;
+
+	// replaced by a rename, so that the pointer is never seen half-written
+	let latest = launcher.updates_path(LATEST_FILE);
+	let new_latest = launcher.updates_path(&format!("{}.new", LATEST_FILE));
+	File::create(&new_latest)
+		.and_then(|mut f| f.write_all(name.as_bytes()))
+		.and_then(|_| fs::rename(&new_latest, &latest))
+		.map_err(|e| format!("couldn't update {}: {}", latest.display(), e))?;
+
+	Ok(name)
+}
+
+/// Applies `patch` to `base`, returning the name and content of the patched binary.
+///
+/// The patched binary must match the hash given by the patch.
+pub fn apply_patch(base: &[u8], patch: &[u8]) -> Result<(String, Vec<u8>), String> {
+	let mut reader = Reader { data: patch, pos: 0 };
+	if reader.take(MAGIC.len())? != MAGIC {
+		return Err("not a binary patch".into());
+	}
+
+	let hash = reader.take(32)?.to_vec();
+	let name_len = reader.u16()? as usize;
+	let name = String::from_utf8(reader.take(name_len)?.to_vec()).map_err(|_| "invalid binary name".to_owned())?;
+	let len = reader.u64()? as usize;
+
+	// every byte comes from either the base binary or the patch, so don't trust `len` any further
+	let mut output = Vec::with_capacity(len.min(base.len() + patch.len()));
+	let mut base_pos: i64 = 0;
+	while output.len() < len {
+		let diff_len = reader.u64()? as usize;
+		let extra_len = reader.u64()? as usize;
+		let seek = reader.u64()? as i64;
+		if output.len().checked_add(diff_len).and_then(|n| n.checked_add(extra_len)).map_or(true, |n| n > len) {
+			return Err("patch overflows the patched binary".into());
+		}
+
+		let diff = reader.take(diff_len)?;
+		if base_pos < 0 || (base_pos as usize).checked_add(diff_len).map_or(true, |end| end > base.len()) {
+			return Err("patch doesn't match the running executable".into());
+		}
+		let from = base_pos as usize;
+		output.extend(diff.iter().zip(&base[from..from + diff_len]).map(|(d, b)| d.wrapping_add(*b)));
+		output.extend_from_slice(reader.take(extra_len)?);
+		base_pos = base_pos.checked_add(diff_len as i64).and_then(|pos| pos.checked_add(seek))
+			.ok_or_else(|| "patch doesn't match the running executable".to_owned())?;
+	}
+
+	if keccak(&output)[..] != hash[..] {
+		return Err("patched binary doesn't match the expected hash".into());
+	}
+
+	Ok((name, output))
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+		if self.data.len() - self.pos < len {
+			return Err("truncated patch".into());
+		}
+		let data = &self.data[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(data)
+	}
+
+	fn u16(&mut self) -> Result<u16, String> {
+		let bytes = self.take(2)?;
+		Ok(u16::from(bytes[0]) | u16::from(bytes[1]) << 8)
+	}
+
+	fn u64(&mut self) -> Result<u64, String> {
+		let bytes = self.take(8)?;
+		Ok(bytes.iter().rev().fold(0, |acc, b| acc << 8 | u64::from(*b)))
+	}
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+	let mut content = Vec::new();
+	File::open(path)
+		.and_then(|mut f| f.read_to_end(&mut content))
+		.map(|_| content)
+		.map_err(|e| format!("couldn't read {}: {}", path.display(), e))
+}
+
+#[cfg(unix)]
+fn write_executable(path: &Path, content: &[u8]) -> ::std::io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+	File::create(path).and_then(|mut f| f.write_all(content))?;
+	fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn write_executable(path: &Path, content: &[u8]) -> ::std::io::Result<()> {
+	File::create(path).and_then(|mut f| f.write_all(content))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::{Read, Write};
+	use std::path::Path;
+	use hash::keccak;
+	use tempdir::TempDir;
+	use launcher::LauncherConfig;
+	use super::{apply_patch, apply_pending_patch, LATEST_PATCH_FILE, MAGIC, PATCH_BASE_VERSION_FILE};
+
+	fn u64_le(value: u64) -> Vec<u8> {
+		(0..8).map(|i| (value >> (8 * i)) as u8).collect()
+	}
+
+	/// Patch keeping the first `keep` bytes of the base binary and appending `extra`.
+	fn patch(name: &str, target: &[u8], keep: usize, extra: &[u8]) -> Vec<u8> {
+		let mut patch = MAGIC.to_vec();
+		patch.extend_from_slice(&keccak(target)[..]);
+		patch.extend_from_slice(&[name.len() as u8, 0]);
+		patch.extend_from_slice(name.as_bytes());
+		patch.extend(u64_le(target.len() as u64));
+		patch.extend(u64_le(keep as u64));
+		patch.extend(u64_le(extra.len() as u64));
+		patch.extend(u64_le(0));
+		patch.extend(vec![0; keep]);
+		patch.extend_from_slice(extra);
+		patch
+	}
+
+	fn write(path: &Path, content: &[u8]) {
+		File::create(path).unwrap().write_all(content).unwrap();
+	}
+
+	#[test]
+	fn should_apply_patch() {
+		let patch = patch("parity-1.12.1-aa", b"base binary, patched", 11, b", patched");
+		assert_eq!(apply_patch(b"base binary", &patch), Ok(("parity-1.12.1-aa".to_owned(), b"base binary, patched".to_vec())));
+	}
+
+	#[test]
+	fn should_reject_patch_not_matching_hash() {
+		let patch = patch("parity-1.12.1-aa", b"base binary, patched", 11, b", patched");
+		assert!(apply_patch(b"other binary", &patch).is_err());
+	}
+
+	#[test]
+	fn should_reject_truncated_patch() {
+		let patch = patch("parity-1.12.1-aa", b"base binary, patched", 11, b", patched");
+		assert!(apply_patch(b"base binary", &patch[..patch.len() - 1]).is_err());
+		assert!(apply_patch(b"base binary", b"PARPATCH").is_err());
+	}
+
+	#[test]
+	fn should_reject_patch_seeking_out_of_range() {
+		let mut patch = patch("parity-1.12.1-aa", b"base binary, patched", 11, b"");
+		// seek of the only block, which leaves the rest of the binary to a block that isn't there
+		let seek_pos = patch.len() - 11 - 8;
+		patch.splice(seek_pos..seek_pos + 8, u64_le(i64::max_value() as u64));
+		assert_eq!(apply_patch(b"base binary", &patch), Err("patch doesn't match the running executable".into()));
+	}
+
+	#[test]
+	fn should_install_pending_patch_as_latest() {
+		let tempdir = TempDir::new("").unwrap();
+		let exe = tempdir.path().join("parity");
+		write(&exe, b"base binary");
+		write(&tempdir.path().join(LATEST_PATCH_FILE), &patch("parity-1.12.1-aa", b"base binary, patched", 11, b", patched"));
+		write(&tempdir.path().join(PATCH_BASE_VERSION_FILE), env!("CARGO_PKG_VERSION").as_bytes());
+		let launcher = LauncherConfig::new(vec!["parity".into()], Some(exe), tempdir.path().to_owned()).unwrap();
+
+		apply_pending_patch(&launcher);
+
+		let mut latest = String::new();
+		File::open(tempdir.path().join("latest")).unwrap().read_to_string(&mut latest).unwrap();
+		assert_eq!(latest, "parity-1.12.1-aa");
+		assert!(tempdir.path().join("parity-1.12.1-aa").is_file());
+		assert!(!tempdir.path().join(LATEST_PATCH_FILE).exists());
+		assert!(!tempdir.path().join(PATCH_BASE_VERSION_FILE).exists());
+	}
+
+	#[test]
+	fn should_ignore_patch_for_other_version() {
+		let tempdir = TempDir::new("").unwrap();
+		let exe = tempdir.path().join("parity");
+		write(&exe, b"base binary");
+		write(&tempdir.path().join(LATEST_PATCH_FILE), &patch("parity-1.12.1-aa", b"base binary, patched", 11, b", patched"));
+		write(&tempdir.path().join(PATCH_BASE_VERSION_FILE), b"0.0.1");
+		let launcher = LauncherConfig::new(vec!["parity".into()], Some(exe), tempdir.path().to_owned()).unwrap();
+
+		apply_pending_patch(&launcher);
+
+		assert!(!tempdir.path().join("latest").exists());
+		assert!(!tempdir.path().join(LATEST_PATCH_FILE).exists());
+	}
+}
@@ -0,0 +1,59 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! PID files letting external tooling signal the right process across restarts.
+
+use std::fs::{remove_file, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Writes `pid` to the file at `path`, reporting a failure without giving up.
+pub fn write_pid_file(path: &Path, pid: u32) {
+	if let Err(e) = File::create(path).and_then(|mut f| writeln!(f, "{}", pid)) {
+		warn_main!("Couldn't write PID file {}: {}", path.display(), e);
+	}
+}
+
+/// Removes the PID file at `path`, if it exists.
+pub fn remove_pid_file(path: &Path) {
+	match remove_file(path) {
+		Err(ref e) if e.kind() != io::ErrorKind::NotFound => warn_main!("Couldn't remove PID file {}: {}", path.display(), e),
+		_ => {},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Read;
+	use tempdir::TempDir;
+	use super::{remove_pid_file, write_pid_file};
+
+	#[test]
+	fn should_write_and_remove_pid_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("parity.pid");
+
+		write_pid_file(&path, 4242);
+		let mut content = String::new();
+		File::open(&path).unwrap().read_to_string(&mut content).unwrap();
+		assert_eq!(content, "4242\n");
+
+		remove_pid_file(&path);
+		assert!(!path.exists());
+		// removing it again is harmless
+		remove_pid_file(&path);
+	}
+}
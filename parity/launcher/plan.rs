@@ -0,0 +1,422 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decision between running an installed update and the current binary, and the launch plan
+//! describing it for `--emit-launch-plan`.
+
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use semver::Version;
+use serde_json;
+use super::{
+	current_version, describe_update_state, is_allowed_version, is_direct_chain, is_downgrade, is_not_latest,
+	latest_binary_is_newer, latest_exe_path, peek_spec_name_override, should_run_update, staged_version, DeferReason,
+	Error, LauncherConfig, Startup,
+};
+use super::compressed::is_checksum_verified;
+use super::decision::is_expected_channel;
+use super::detect::update_settle_remaining;
+use super::slots::is_rolled_back;
+use super::updates::{has_update_apply_request, pending_sticky_args, UpdateChannel};
+use super::child::child_args;
+use super::verify::run_checks;
+use super::version::staged_channel;
+
+/// What the launcher determined about the installed update on a loop iteration.
+pub struct UpdatePlan {
+	/// Outcome of resolving the `latest` pointer.
+	pub latest: Result<PathBuf, Error>,
+	/// An update is installed.
+	pub have_update: bool,
+	/// The running binary isn't the installed update.
+	pub current_is_not_latest: bool,
+	/// The update was installed after the running binary.
+	pub update_is_newer: bool,
+	/// The update's version doesn't exceed the configured ceiling, nor go back from the current one.
+	pub update_is_allowed: bool,
+	/// Version of the installed update, if known.
+	pub staged: Option<Version>,
+	/// Version of the running binary, if known.
+	pub current: Option<Version>,
+	/// Release channel the installed update was built for, if recorded.
+	pub staged_channel: Option<String>,
+	/// Pending chain override, if any.
+	pub requested_spec: Option<String>,
+	/// Chain the node is run for.
+	pub chain: String,
+	/// Why a newer update isn't run, if it's held back.
+	pub deferred: Option<DeferReason>,
+	/// How long the update has yet to settle before it's run, if it was modified too recently.
+	pub settle_remaining: Option<Duration>,
+	/// The update is run instead of the current binary.
+	pub run_update: bool,
+}
+
+impl UpdatePlan {
+	/// Describes the state of installed updates for the decision log.
+	pub fn state(&self) -> String {
+		describe_update_state(&self.latest, self.current_is_not_latest, self.update_is_newer)
+	}
+}
+
+/// Decides whether to run the installed update instead of the running `exe`.
+///
+/// `apply_requested` tells whether an update held back by `--update-check-only` was asked to be
+/// applied. It's only called for such an update, as taking the request removes it.
+pub fn plan_update<F>(launcher: &LauncherConfig, exe: Option<&Path>, apply_requested: F) -> UpdatePlan where F: FnOnce() -> bool {
+	let latest = latest_exe_path(launcher);
+	let (have_update, current_is_not_latest, update_is_newer, staged) = {
+		let latest_exe = latest.as_ref().ok();
+		let have_update = latest_exe.map_or(false, |p| p.exists());
+		let current_is_not_latest = exe.map_or(false, |exe| latest_exe.map_or(false, |lexe| is_not_latest(&launcher.updates_dir, exe, lexe)));
+		// a binary given with --use-binary is run whatever its age and version
+		let update_is_newer = current_is_not_latest && (launcher.use_binary.is_some() || match (latest_exe, exe) {
+			(Some(latest_exe), Some(exe)) => latest_binary_is_newer(latest_exe, exe),
+			_ => false,
+		});
+		(have_update, current_is_not_latest, update_is_newer, latest_exe.and_then(|p| staged_version(launcher, p)))
+	};
+	let current = current_version();
+	let downgrade = !launcher.allow_downgrade && is_downgrade(staged.as_ref(), current.as_ref());
+	let staged_channel = staged_channel(launcher);
+	let wrong_channel = !is_expected_channel(
+		staged_channel.as_ref().map(String::as_str),
+		launcher.expected_channel.as_ref().map(String::as_str),
+	);
+	let update_is_allowed = launcher.use_binary.is_some()
+		|| (is_allowed_version(staged.as_ref(), launcher.max_update_version.as_ref()) && !downgrade && !wrong_channel);
+
+	let held_back = have_update && current_is_not_latest && update_is_newer && !update_is_allowed;
+	let mut deferred = match (held_back, downgrade, wrong_channel) {
+		(true, true, _) => Some(DeferReason::Downgrade),
+		(true, false, true) => Some(DeferReason::ChannelMismatch),
+		(true, false, false) => Some(DeferReason::VersionCeiling),
+		(false, _, _) => None,
+	};
+	let mut run_update = should_run_update(have_update, current_is_not_latest, update_is_newer, update_is_allowed);
+	let requested_spec = peek_spec_name_override(launcher).map(|spec_override| spec_override.chain);
+	let chain = requested_spec.clone().unwrap_or_else(|| launcher.chain());
+	if run_update && is_direct_chain(launcher, &chain) {
+		run_update = false;
+		deferred = Some(DeferReason::ChainPinned);
+	}
+	// a binary given with --use-binary is never written by the updater
+	let settle_remaining = match latest {
+		Ok(ref latest_exe) if run_update && launcher.use_binary.is_none() => update_settle_remaining(latest_exe, launcher.update_settle_time),
+		_ => None,
+	};
+	if settle_remaining.is_some() {
+		run_update = false;
+		deferred = Some(DeferReason::Unsettled);
+	}
+	if run_update && launcher.update_check_only && !apply_requested() {
+		run_update = false;
+		deferred = Some(DeferReason::CheckOnly);
+	}
+
+	UpdatePlan {
+		latest,
+		have_update,
+		current_is_not_latest,
+		update_is_newer,
+		update_is_allowed,
+		staged,
+		current,
+		staged_channel,
+		requested_spec,
+		chain,
+		deferred,
+		settle_remaining,
+		run_update,
+	}
+}
+
+/// Check of the installed update, as run by `--verify-only`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PlanCheck {
+	/// What was checked.
+	pub name: &'static str,
+	/// The check passed.
+	pub ok: bool,
+	/// Why the check failed, if it did.
+	pub error: Option<String>,
+}
+
+/// Everything the launcher determined before running the node, as printed by
+/// `--emit-launch-plan`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LaunchPlan {
+	/// Path of the running executable, if it could be determined.
+	pub current_exe: Option<String>,
+	/// Path of the installed update, if it could be resolved.
+	pub latest_exe: Option<String>,
+	/// Version of the running binary, if known.
+	pub current_version: Option<String>,
+	/// Version of the installed update, if known.
+	pub latest_version: Option<String>,
+	/// Checks of the installed update.
+	pub checks: Vec<PlanCheck>,
+	/// The binary run for the installed update matches the hash in `latest.keccak`.
+	pub checksums_verified: bool,
+	/// `run_latest` to run the installed update as a child process, `direct` to run the node in
+	/// the launcher's process, `rollback` to go back to the last good binary instead of an update
+	/// which failed.
+	pub decision: &'static str,
+	/// Why a newer update isn't run, if it's held back.
+	pub deferred: Option<DeferReason>,
+	/// Highest update version allowed to run, given with `--max-update-version`.
+	pub max_update_version: Option<String>,
+	/// Chain the node is run for.
+	pub chain: String,
+	/// The chain is pinned to the current binary by a `force-direct-<chain>` marker.
+	pub chain_pinned: bool,
+	/// Command line of the child process, starting with the binary run, when running the update.
+	pub argv: Option<Vec<String>>,
+}
+
+/// Determines the launch plan of `launcher` for the running `exe`, changing nothing on disk.
+///
+/// With `hypervise` unset the launcher runs the node in its own process whatever the update
+/// state. A pending request to apply a held back update is looked at without taking it.
+pub fn launch_plan(launcher: &LauncherConfig, exe: Option<&Path>, hypervise: bool) -> LaunchPlan {
+	let plan = plan_update(launcher, exe, || has_update_apply_request(launcher));
+	let latest_exe = plan.latest.as_ref().ok();
+	let run_latest = hypervise && plan.run_update;
+
+	LaunchPlan {
+		current_exe: exe.map(|exe| exe.display().to_string()),
+		latest_exe: latest_exe.map(|exe| exe.display().to_string()),
+		current_version: plan.current.as_ref().map(Version::to_string),
+		latest_version: plan.staged.as_ref().map(Version::to_string),
+		checks: run_checks(launcher).into_iter()
+			.map(|(name, result)| PlanCheck { name, ok: result.is_ok(), error: result.err() })
+			.collect(),
+		checksums_verified: latest_exe.map_or(false, |exe| is_checksum_verified(launcher, exe)),
+		decision: if hypervise && is_rollback(launcher, &plan) {
+			"rollback"
+		} else if run_latest {
+			"run_latest"
+		} else {
+			"direct"
+		},
+		deferred: plan.deferred,
+		max_update_version: launcher.max_update_version.as_ref().map(Version::to_string),
+		chain_pinned: is_direct_chain(launcher, &plan.chain),
+		chain: plan.chain.clone(),
+		argv: match latest_exe {
+			Some(exe) if run_latest => Some(iter::once(exe.display().to_string()).chain(child_args(launcher)).collect()),
+			_ => None,
+		},
+	}
+}
+
+/// Whether `plan` goes back to the last good binary: the active slot after the update on trial in
+/// the other one was demoted, or the running binary after the update was marked bad.
+fn is_rollback(launcher: &LauncherConfig, plan: &UpdatePlan) -> bool {
+	match plan.latest {
+		Err(Error::MarkedBad) => true,
+		// the same conditions as `latest_exe_path` resolving a slot
+		Ok(_) => plan.run_update && launcher.use_binary.is_none() && launcher.update_channel == UpdateChannel::Stable
+			&& is_rolled_back(&launcher.updates_dir),
+		Err(_) => false,
+	}
+}
+
+/// Whether `launcher`, started as `startup`, runs the node in the update loop, as `main` does.
+fn is_hypervised(launcher: &LauncherConfig, startup: &Startup) -> bool {
+	!launcher.no_indirect && (startup.should_hypervise() || launcher.use_binary.is_some() || launcher.supervise)
+}
+
+/// Prints the launch plan of `launcher` on stdout as a single JSON document. Returns the exit code.
+///
+/// Arguments kept across a pending restart are merged in just like before the next run.
+pub fn emit_launch_plan(launcher: &LauncherConfig) -> i32 {
+	let mut launcher = launcher.clone();
+	let sticky_args = pending_sticky_args(&launcher);
+	launcher.keep_sticky_args(&sticky_args);
+	let startup = Startup::detect(&launcher);
+	let hypervise = is_hypervised(&launcher, &startup);
+
+	match serde_json::to_string(&launch_plan(&launcher, startup.exe.as_ref().map(PathBuf::as_path), hypervise)) {
+		Ok(json) => {
+			println!("{}", json);
+			0
+		},
+		Err(e) => {
+			warn_main!("Couldn't serialize the launch plan: {}", e);
+			1
+		},
+	}
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+	use std::path::{Path, PathBuf};
+	use hash::keccak;
+	use tempdir::TempDir;
+	use launcher::{DeferReason, LauncherConfig, Startup};
+	use launcher::compressed::LATEST_CHECKSUM_FILE;
+	use launcher::slots::{demote, Slot};
+	use launcher::updates::{APPLY_UPDATE_FILE, BAD_UPDATE_FILE, LATEST_FILE};
+	use super::{is_hypervised, launch_plan};
+
+	fn write(path: &Path, content: &str) {
+		File::create(path).unwrap().write_all(content.as_bytes()).unwrap();
+	}
+
+	fn config(dir: &Path, args: &[&str]) -> LauncherConfig {
+		let args = Some("parity").into_iter().chain(args.iter().cloned()).map(String::from).collect();
+		LauncherConfig::new(args, None, dir.to_owned()).unwrap()
+	}
+
+	fn executable(dir: &Path, name: &str) -> PathBuf {
+		use std::fs::{set_permissions, Permissions};
+		use std::os::unix::fs::PermissionsExt;
+
+		let path = dir.join(name);
+		File::create(&path).unwrap();
+		set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+		path
+	}
+
+	#[test]
+	fn should_plan_direct_run_without_update() {
+		let tempdir = TempDir::new("").unwrap();
+		let plan = launch_plan(&config(tempdir.path(), &[]), Some(Path::new("/usr/bin/parity")), true);
+		assert_eq!(plan.decision, "direct");
+		assert_eq!(plan.current_exe, Some("/usr/bin/parity".into()));
+		assert_eq!(plan.latest_exe, None);
+		assert_eq!(plan.argv, None);
+		assert_eq!(plan.checks.len(), 1);
+		assert_eq!(plan.checks[0].name, "latest pointer resolved");
+		assert!(!plan.checks[0].ok);
+	}
+
+	#[test]
+	fn should_plan_running_update_with_child_argv() {
+		let tempdir = TempDir::new("").unwrap();
+		let binary = executable(tempdir.path(), "parity-1.12.0");
+		let launcher = config(tempdir.path(), &["--use-binary", binary.to_str().unwrap(), "--chain", "kovan"]);
+
+		let plan = launch_plan(&launcher, Some(Path::new("/usr/bin/parity")), true);
+		assert_eq!(plan.decision, "run_latest");
+		assert_eq!(plan.chain, "kovan");
+		let argv = plan.argv.unwrap();
+		assert_eq!(argv[0], binary.display().to_string());
+		assert!(argv.windows(2).any(|w| w == ["--chain", "kovan"]));
+
+		let plan = launch_plan(&launcher, Some(Path::new("/usr/bin/parity")), false);
+		assert_eq!(plan.decision, "direct");
+		assert_eq!(plan.argv, None);
+	}
+
+	#[test]
+	fn should_plan_running_update_with_supervise() {
+		let tempdir = TempDir::new("").unwrap();
+		let binary = executable(tempdir.path(), "parity-1.12.0");
+		write(&tempdir.path().join(LATEST_FILE), "parity-1.12.0");
+		let hypervised = |launcher: &LauncherConfig| is_hypervised(launcher, &Startup::detect(launcher));
+		// an existing binary older than the update stands in for the running one
+		let exe = Some(Path::new("/bin/sh"));
+
+		let launcher = config(tempdir.path(), &["--update-settle-time", "0"]);
+		assert!(!hypervised(&launcher));
+		assert_eq!(launch_plan(&launcher, exe, hypervised(&launcher)).decision, "direct");
+
+		let launcher = config(tempdir.path(), &["--supervise", "--update-settle-time", "0"]);
+		assert!(hypervised(&launcher));
+		let plan = launch_plan(&launcher, exe, hypervised(&launcher));
+		assert_eq!(plan.decision, "run_latest");
+		assert_eq!(plan.argv.unwrap()[0], binary.display().to_string());
+	}
+
+	#[test]
+	fn should_plan_held_back_update_without_taking_apply_request() {
+		let tempdir = TempDir::new("").unwrap();
+		let binary = executable(tempdir.path(), "parity-1.12.0");
+		let launcher = config(tempdir.path(), &["--use-binary", binary.to_str().unwrap(), "--update-check-only"]);
+		let exe = Some(Path::new("/usr/bin/parity"));
+
+		let plan = launch_plan(&launcher, exe, true);
+		assert_eq!((plan.decision, plan.deferred), ("direct", Some(DeferReason::CheckOnly)));
+
+		File::create(tempdir.path().join(APPLY_UPDATE_FILE)).unwrap();
+		assert_eq!(launch_plan(&launcher, exe, true).decision, "run_latest");
+		assert!(tempdir.path().join(APPLY_UPDATE_FILE).exists());
+
+		File::create(tempdir.path().join("force-direct-foundation")).unwrap();
+		let plan = launch_plan(&launcher, exe, true);
+		assert_eq!((plan.decision, plan.deferred), ("direct", Some(DeferReason::ChainPinned)));
+		assert!(plan.chain_pinned);
+	}
+
+	#[test]
+	fn should_plan_rollback_to_last_good_slot() {
+		let tempdir = TempDir::new("").unwrap();
+		let good = executable(tempdir.path(), "parity-1.11.0-aa");
+		executable(tempdir.path(), "parity-1.12.0-bb");
+		write(&tempdir.path().join(Slot::A.pointer_file()), "parity-1.11.0-aa");
+		write(&tempdir.path().join(Slot::B.pointer_file()), "parity-1.12.0-bb");
+		let launcher = config(tempdir.path(), &["--update-settle-time", "0"]);
+		// an existing binary older than the updates stands in for the running one
+		let exe = Some(Path::new("/bin/sh"));
+
+		let plan = launch_plan(&launcher, exe, true);
+		assert_eq!(plan.decision, "run_latest");
+		assert_eq!(plan.latest_exe, Some(tempdir.path().join("parity-1.12.0-bb").display().to_string()));
+
+		demote(tempdir.path(), Slot::B).unwrap();
+		let plan = launch_plan(&launcher, exe, true);
+		assert_eq!(plan.decision, "rollback");
+		assert_eq!(plan.latest_exe, Some(good.display().to_string()));
+		assert_eq!(plan.argv.unwrap()[0], good.display().to_string());
+	}
+
+	#[test]
+	fn should_plan_rollback_from_bad_update() {
+		let tempdir = TempDir::new("").unwrap();
+		executable(tempdir.path(), "parity-1.12.0");
+		write(&tempdir.path().join(LATEST_FILE), "parity-1.12.0");
+		write(&tempdir.path().join(BAD_UPDATE_FILE), "parity-1.12.0");
+		let launcher = config(tempdir.path(), &["--update-settle-time", "0"]);
+
+		let plan = launch_plan(&launcher, Some(Path::new("/usr/bin/parity")), true);
+		assert_eq!(plan.decision, "rollback");
+		assert_eq!(plan.latest_exe, None);
+		assert_eq!(plan.argv, None);
+		assert_eq!(launch_plan(&launcher, Some(Path::new("/usr/bin/parity")), false).decision, "direct");
+	}
+
+	#[test]
+	fn should_report_verified_checksum() {
+		let tempdir = TempDir::new("").unwrap();
+		let binary = tempdir.path().join("parity-1.12.0");
+		write(&binary, "binary");
+		write(&tempdir.path().join(LATEST_FILE), "parity-1.12.0");
+		let launcher = config(tempdir.path(), &[]);
+		let exe = Some(Path::new("/usr/bin/parity"));
+
+		assert!(!launch_plan(&launcher, exe, true).checksums_verified);
+		write(&tempdir.path().join(LATEST_CHECKSUM_FILE), &format!("{:x}", keccak("other binary")));
+		assert!(!launch_plan(&launcher, exe, true).checksums_verified);
+		write(&tempdir.path().join(LATEST_CHECKSUM_FILE), &format!("{:x}", keccak("binary")));
+		assert!(launch_plan(&launcher, exe, true).checksums_verified);
+	}
+}
@@ -0,0 +1,127 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Waiting for the ports of a node that exited to be released before starting the next one.
+
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::config::split_flag;
+
+/// Ports the node listens on by default, with the flag changing each.
+const NODE_PORTS: &[(&str, u16)] = &[("--port", 30303), ("--jsonrpc-port", 8545), ("--ws-port", 8546)];
+
+/// Flag adding to every port the node listens on.
+const PORTS_SHIFT_FLAG: &str = "--ports-shift";
+
+/// Time to wait for a connection to a port, in milliseconds.
+const CONNECT_TIMEOUT_MS: u64 = 100;
+
+/// Time between two checks of the ports still in use, in milliseconds.
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// Ports the node run with `node_args` listens on.
+///
+/// Servers listening on another interface than localhost are never seen as using their port.
+pub fn node_ports(node_args: &[String]) -> Vec<u16> {
+	let mut ports = NODE_PORTS.to_vec();
+	let mut shift = 0u16;
+	let mut args = node_args.iter();
+	while let Some(arg) = args.next() {
+		let (flag, value) = split_flag(arg);
+		if flag != PORTS_SHIFT_FLAG && !NODE_PORTS.iter().any(|&(port_flag, _)| port_flag == flag) {
+			continue;
+		}
+
+		let value = match value {
+			Some(value) => Some(value.to_owned()),
+			None => args.next().cloned(),
+		};
+		let number = match value.and_then(|value| value.parse().ok()) {
+			Some(number) => number,
+			// the node rejects it anyway
+			None => continue,
+		};
+		if flag == PORTS_SHIFT_FLAG {
+			shift = number;
+		} else {
+			for port in ports.iter_mut().filter(|&&mut (port_flag, _)| port_flag == flag) {
+				port.1 = number;
+			}
+		}
+	}
+	ports.into_iter().filter_map(|(_, port)| port.checked_add(shift)).collect()
+}
+
+/// Waits up to `timeout` for nothing to be listening on localhost `ports` anymore.
+///
+/// Returns the ports still in use once the time is up.
+pub fn wait_ports_free(ports: &[u16], timeout: Duration) -> Vec<u16> {
+	let started = Instant::now();
+	loop {
+		let busy: Vec<u16> = ports.iter().cloned().filter(|&port| is_port_busy(port)).collect();
+		if busy.is_empty() || started.elapsed() >= timeout {
+			return busy;
+		}
+		thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+	}
+}
+
+fn is_port_busy(port: u16) -> bool {
+	let addr = SocketAddr::from(([127, 0, 0, 1], port));
+	TcpStream::connect_timeout(&addr, Duration::from_millis(CONNECT_TIMEOUT_MS)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::TcpListener;
+	use std::thread;
+	use std::time::Duration;
+	use super::{node_ports, wait_ports_free};
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|s| s.to_string()).collect()
+	}
+
+	#[test]
+	fn should_find_node_ports() {
+		assert_eq!(node_ports(&[]), vec![30303, 8545, 8546]);
+		assert_eq!(node_ports(&args(&["--chain", "kovan", "--port", "30304", "--jsonrpc-port=8645"])), vec![30304, 8645, 8546]);
+		assert_eq!(node_ports(&args(&["--ports-shift", "100", "--ws-port", "9000"])), vec![30403, 8645, 9100]);
+		assert_eq!(node_ports(&args(&["--port", "many"])), vec![30303, 8545, 8546]);
+	}
+
+	#[test]
+	fn should_report_port_still_in_use() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+
+		assert_eq!(wait_ports_free(&[port], Duration::from_millis(200)), vec![port]);
+	}
+
+	#[test]
+	fn should_wait_for_port_to_be_released() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let release = thread::spawn(move || {
+			thread::sleep(Duration::from_millis(200));
+			drop(listener);
+		});
+
+		assert_eq!(wait_ports_free(&[port], Duration::from_secs(10)), Vec::<u16>::new());
+		release.join().unwrap();
+	}
+}
@@ -0,0 +1,94 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Self-test of the launcher plumbing, run against a throwaway updates directory.
+//!
+//! The running executable is installed as a fake update which, given `SELFTEST_EXIT_FLAG`,
+//! exits right away with the requested code instead of starting the node.
+
+use std::env::{self, consts::EXE_SUFFIX};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use super::{latest_exe_path, run_latest, Error, LauncherConfig};
+use super::updates::LATEST_FILE;
+
+/// Hidden flag making the launcher exit with the given code, used by the fake update.
+pub const SELFTEST_EXIT_FLAG: &str = "--launcher-selftest-exit";
+
+/// Exit codes checked to pass through the launcher unchanged.
+const EXIT_CODES: &[i32] = &[0, 42, 69];
+
+/// Runs the self-test, reporting each check on stdout. Returns the exit code.
+pub fn self_test(launcher: &LauncherConfig) -> i32 {
+	let exe = match launcher.current_exe {
+		Some(ref exe) => exe.clone(),
+		None => {
+			warn_main!("Launcher self-test failed: couldn't determine the running executable");
+			return 1;
+		},
+	};
+
+	let dir = env::temp_dir().join(format!("parity-launcher-selftest-{}", process::id()));
+	let result = run_checks(&exe, &dir);
+	let _ = fs::remove_dir_all(&dir);
+
+	match result {
+		Ok(()) => {
+			println!("Launcher self-test passed");
+			0
+		},
+		Err(e) => {
+			println!("Launcher self-test failed: {}", e);
+			1
+		},
+	}
+}
+
+fn run_checks(exe: &Path, dir: &Path) -> Result<(), String> {
+	fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+
+	check("no update installed", latest_exe_path(&config(dir, 0)) == Err(Error::NotConfigured))?;
+
+	let name = format!("parity-0.0.0-selftest{}", EXE_SUFFIX);
+	fs::copy(exe, dir.join(&name))
+		.and_then(|_| File::create(dir.join(LATEST_FILE)))
+		.and_then(|mut f| f.write_all(name.as_bytes()))
+		.map_err(|e| format!("couldn't install the fake update: {}", e))?;
+
+	check("latest pointer resolved", latest_exe_path(&config(dir, 0)) == Ok(dir.join(&name)))?;
+	for &code in EXIT_CODES {
+		check(&format!("exit code {} reported", code), run_latest(&config(dir, code)) == Ok(code))?;
+	}
+
+	Ok(())
+}
+
+fn check(name: &str, passed: bool) -> Result<(), String> {
+	if passed {
+		println!("{}: ok", name);
+		Ok(())
+	} else {
+		Err(format!("{}: FAILED", name))
+	}
+}
+
+/// Launcher configuration running the fake update in `dir`, asking it to exit with `code`.
+fn config(dir: &Path, code: i32) -> LauncherConfig {
+	let args = vec!["parity".to_owned(), format!("{}={}", SELFTEST_EXIT_FLAG, code)];
+	LauncherConfig::new(args, None, dir.to_owned()).expect("the self-test command line is valid; qed")
+}
@@ -0,0 +1,166 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Integration with the Windows service control manager.
+//!
+//! The service control manager stops a service through control codes rather than Ctrl-C, so a
+//! node run as a service would otherwise be killed without shutting down cleanly. A service runs
+//! the node in-process, as the control codes only reach the process started by the manager.
+
+#[cfg(windows)]
+use std::{io, ptr};
+#[cfg(windows)]
+use parking_lot::Mutex;
+#[cfg(windows)]
+use winapi::shared::minwindef::{DWORD, LPVOID};
+#[cfg(windows)]
+use winapi::shared::winerror::{ERROR_CALL_NOT_IMPLEMENTED, ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR};
+#[cfg(windows)]
+use winapi::um::wincon::GetConsoleWindow;
+#[cfg(windows)]
+use winapi::um::winnt::{LPWSTR, SERVICE_WIN32_OWN_PROCESS};
+#[cfg(windows)]
+use winapi::um::winsvc::{
+	RegisterServiceCtrlHandlerExW, SetServiceStatus, StartServiceCtrlDispatcherW, SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP,
+	SERVICE_CONTROL_INTERROGATE, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP, SERVICE_RUNNING, SERVICE_STATUS,
+	SERVICE_STATUS_HANDLE, SERVICE_STOPPED, SERVICE_STOP_PENDING, SERVICE_TABLE_ENTRYW,
+};
+
+/// Name the service registers its control handler under; ignored for a service in its own process.
+#[cfg(windows)]
+const SERVICE_NAME: &str = "parity";
+
+/// Time the service control manager is told stopping may take, in milliseconds.
+#[cfg(windows)]
+const STOP_WAIT_HINT_MS: DWORD = 30_000;
+
+#[cfg(windows)]
+lazy_static! {
+	static ref SERVICE_BODY: Mutex<Option<Box<FnMut() -> i32 + Send>>> = Mutex::new(None);
+	static ref STOP_HANDLER: Mutex<Option<Box<Fn() + Send>>> = Mutex::new(None);
+	// the handle is a pointer owned by the service control manager, kept as an address to be `Send`
+	static ref STATUS_HANDLE: Mutex<Option<usize>> = Mutex::new(None);
+	static ref EXIT_CODE: Mutex<Option<i32>> = Mutex::new(None);
+}
+
+/// Whether the process was most likely started by the service control manager.
+///
+/// Services have no console window, unlike interactive runs.
+#[cfg(windows)]
+pub fn is_likely_service() -> bool {
+	unsafe { GetConsoleWindow().is_null() }
+}
+
+#[cfg(not(windows))]
+pub fn is_likely_service() -> bool {
+	false
+}
+
+/// Runs `body` as the service, returning its exit code once the service stopped.
+///
+/// Returns `None` right away when the process wasn't started by the service control manager.
+#[cfg(windows)]
+pub fn run_as_service<F>(body: F) -> Option<i32> where F: FnOnce() -> i32 + Send + 'static {
+	let mut body = Some(body);
+	*SERVICE_BODY.lock() = Some(Box::new(move || body.take().map_or(1, |body| body())));
+
+	let mut name = wide(SERVICE_NAME);
+	let table = [
+		SERVICE_TABLE_ENTRYW { lpServiceName: name.as_mut_ptr(), lpServiceProc: Some(service_main) },
+		SERVICE_TABLE_ENTRYW { lpServiceName: ptr::null_mut(), lpServiceProc: None },
+	];
+	// blocks until the service stopped, running `service_main` on another thread
+	if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+		SERVICE_BODY.lock().take();
+		let e = io::Error::last_os_error();
+		if e.raw_os_error() != Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) {
+			warn_main!("Couldn't connect to the service control manager: {}", e);
+		}
+		return None;
+	}
+
+	Some(EXIT_CODE.lock().take().unwrap_or(1))
+}
+
+#[cfg(not(windows))]
+pub fn run_as_service<F>(_body: F) -> Option<i32> where F: FnOnce() -> i32 + Send + 'static {
+	None
+}
+
+/// Sets what stopping the service does, replacing the previous handler.
+#[cfg(windows)]
+pub fn set_service_stop_handler<F>(handler: F) where F: Fn() + Send + 'static {
+	*STOP_HANDLER.lock() = Some(Box::new(handler));
+}
+
+#[cfg(not(windows))]
+pub fn set_service_stop_handler<F>(_handler: F) where F: Fn() + Send + 'static {}
+
+#[cfg(windows)]
+unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut LPWSTR) {
+	let name = wide(SERVICE_NAME);
+	let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), ptr::null_mut());
+	if handle.is_null() {
+		warn_main!("Couldn't register the service control handler: {}", io::Error::last_os_error());
+		return;
+	}
+	*STATUS_HANDLE.lock() = Some(handle as usize);
+
+	report_status(SERVICE_RUNNING, 0);
+	let body = SERVICE_BODY.lock().take();
+	let code = body.map_or(1, |mut body| body());
+	*EXIT_CODE.lock() = Some(code);
+	report_status(SERVICE_STOPPED, code);
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn control_handler(control: DWORD, _event_type: DWORD, _event_data: LPVOID, _context: LPVOID) -> DWORD {
+	match control {
+		SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+			report_status(SERVICE_STOP_PENDING, 0);
+			if let Some(ref handler) = *STOP_HANDLER.lock() {
+				handler();
+			}
+			NO_ERROR
+		},
+		SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+		_ => ERROR_CALL_NOT_IMPLEMENTED,
+	}
+}
+
+#[cfg(windows)]
+fn report_status(state: DWORD, exit_code: i32) {
+	let handle = match *STATUS_HANDLE.lock() {
+		Some(handle) => handle as SERVICE_STATUS_HANDLE,
+		None => return,
+	};
+	let mut status = SERVICE_STATUS {
+		dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+		dwCurrentState: state,
+		dwControlsAccepted: if state == SERVICE_RUNNING { SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN } else { 0 },
+		dwWin32ExitCode: if exit_code == 0 { NO_ERROR } else { ERROR_SERVICE_SPECIFIC_ERROR },
+		dwServiceSpecificExitCode: exit_code as DWORD,
+		dwCheckPoint: 0,
+		dwWaitHint: if state == SERVICE_STOP_PENDING { STOP_WAIT_HINT_MS } else { 0 },
+	};
+	unsafe {
+		SetServiceStatus(handle, &mut status);
+	}
+}
+
+#[cfg(windows)]
+fn wide(s: &str) -> Vec<u16> {
+	s.encode_utf16().chain(Some(0)).collect()
+}
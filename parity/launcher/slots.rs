@@ -0,0 +1,376 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Side-by-side update slots.
+//!
+//! Instead of replacing `latest`, the updater may install a new binary into the inactive one of
+//! two slots, `slot-a` and `slot-b`, each naming a binary like `latest` does. The launcher gives
+//! the inactive slot a trial run: once it has run for long enough it's promoted to be the active
+//! one, even while it keeps running, while failing early demotes it, going back to the last good
+//! slot.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use parking_lot::{Condvar, Mutex};
+use super::LauncherConfig;
+
+/// File naming the slot which last ran successfully.
+pub const ACTIVE_SLOT_FILE: &str = "active-slot";
+
+/// Update slot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+	/// `slot-a`.
+	A,
+	/// `slot-b`.
+	B,
+}
+
+impl Slot {
+	fn name(&self) -> &'static str {
+		match *self {
+			Slot::A => "a",
+			Slot::B => "b",
+		}
+	}
+
+	fn other(&self) -> Slot {
+		match *self {
+			Slot::A => Slot::B,
+			Slot::B => Slot::A,
+		}
+	}
+
+	/// File naming the binary installed in the slot.
+	pub fn pointer_file(&self) -> String {
+		format!("slot-{}", self.name())
+	}
+
+	/// Files holding how the trial of the binary in the slot went, only meaningful along with the
+	/// pointer.
+	pub fn trial_files(&self) -> [String; 2] {
+		[self.settled_file(), self.demoted_file()]
+	}
+
+	/// File naming the binary in the slot which isn't up for a trial: the one which failed its
+	/// trial, or the one which was active before the other slot got promoted.
+	fn settled_file(&self) -> String {
+		format!("slot-{}.settled", self.name())
+	}
+
+	/// File naming the binary in the slot which failed its trial.
+	fn demoted_file(&self) -> String {
+		format!("slot-{}.demoted", self.name())
+	}
+}
+
+/// Binary to run from the update slots.
+#[derive(Debug, PartialEq)]
+pub struct SlotRun {
+	/// Slot the binary is installed in.
+	pub slot: Slot,
+	/// Name of the binary in the updates directory.
+	pub binary: String,
+	/// The binary is on trial, not having run successfully yet.
+	pub trial: bool,
+}
+
+/// Whether updates are installed into slots, rather than pointed to by `latest`.
+pub fn uses_slots(dir: &Path) -> bool {
+	dir.join(Slot::A.pointer_file()).is_file() || dir.join(Slot::B.pointer_file()).is_file()
+}
+
+/// Returns the binary to run: the inactive slot on trial, if any, otherwise the active slot.
+pub fn slot_to_run(dir: &Path) -> Option<SlotRun> {
+	let active = active_slot(dir);
+	let candidate = active.other();
+	if let Some(binary) = read(dir, &candidate.pointer_file()) {
+		if read(dir, &candidate.settled_file()).as_ref() != Some(&binary) {
+			return Some(SlotRun { slot: candidate, binary, trial: true });
+		}
+	}
+
+	read(dir, &active.pointer_file()).map(|binary| SlotRun { slot: active, binary, trial: false })
+}
+
+/// Returns the slot whose binary is about to get a trial run, if any.
+///
+/// A read-only launcher can't promote or demote the binary, so there's no trial to settle.
+pub fn trial_slot(launcher: &LauncherConfig) -> Option<Slot> {
+	if !launcher.can_write_state() {
+		return None;
+	}
+	slot_to_run(&launcher.updates_dir).and_then(|run| if run.trial { Some(run.slot) } else { None })
+}
+
+/// Trial run of a slot, promoting it once the node has run from it for long enough.
+pub struct Trial {
+	dir: PathBuf,
+	slot: Slot,
+	stop: Arc<(Mutex<bool>, Condvar)>,
+	thread: Option<thread::JoinHandle<bool>>,
+}
+
+impl Trial {
+	/// Starts the trial of `slot` as the node is launched from it.
+	///
+	/// The slot is promoted after `--slot-promote-after`, unless the trial is finished before.
+	pub fn start(launcher: &LauncherConfig, slot: Slot) -> Self {
+		let dir = launcher.updates_dir.clone();
+		let promote_after = launcher.slot_promote_after;
+		let stop = Arc::new((Mutex::new(false), Condvar::new()));
+		let thread_stop = stop.clone();
+		let thread_dir = dir.clone();
+
+		let thread = thread::Builder::new()
+			.name("slot-trial".into())
+			.spawn(move || {
+				// promoting with the lock held, so a finished trial is never promoted afterwards
+				let mut stopped = thread_stop.0.lock();
+				if !*stopped {
+					let _ = thread_stop.1.wait_for(&mut stopped, promote_after);
+				}
+				if *stopped {
+					return false;
+				}
+				trace_main!("Promoting {} after running successfully for {}s", slot.pointer_file(), promote_after.as_secs());
+				report_settling(&thread_dir, promote(&thread_dir, slot));
+				true
+			})
+			.expect("Error creating slot trial thread");
+
+		Trial {
+			dir,
+			slot,
+			stop,
+			thread: Some(thread),
+		}
+	}
+
+	/// Ends the trial as the node exited, demoting the slot if it `failed` before being promoted.
+	///
+	/// Otherwise a slot which wasn't promoted yet stays on trial, e.g. when the node asked for a
+	/// restart early.
+	pub fn finish(mut self, failed: bool) {
+		let promoted = self.stop_thread();
+		if failed && !promoted {
+			warn_main!("Update in {} failed early, going back to the last good version.", self.slot.pointer_file());
+			report_settling(&self.dir, demote(&self.dir, self.slot));
+		}
+	}
+
+	/// Stops the timer, returning whether the slot was promoted.
+	fn stop_thread(&mut self) -> bool {
+		*self.stop.0.lock() = true;
+		self.stop.1.notify_all();
+		self.thread.take().map_or(false, |thread| thread.join().unwrap_or(false))
+	}
+}
+
+impl Drop for Trial {
+	fn drop(&mut self) {
+		self.stop_thread();
+	}
+}
+
+fn report_settling(dir: &Path, result: io::Result<()>) {
+	if let Err(e) = result {
+		warn_main!("Couldn't update {} in {}: {}", ACTIVE_SLOT_FILE, dir.display(), e);
+	}
+}
+
+/// Makes `slot` the active one, keeping the previously active binary only as a fallback.
+pub fn promote(dir: &Path, slot: Slot) -> io::Result<()> {
+	let _ = fs::remove_file(dir.join(slot.settled_file()));
+	settle(dir, slot.other())?;
+	write(dir, ACTIVE_SLOT_FILE, slot.name())
+}
+
+/// Gives up the binary on trial in `slot`, until another one is installed there.
+pub fn demote(dir: &Path, slot: Slot) -> io::Result<()> {
+	settle(dir, slot)?;
+	match read(dir, &slot.pointer_file()) {
+		Some(binary) => write(dir, &slot.demoted_file(), &binary),
+		None => Ok(()),
+	}
+}
+
+/// Whether the active slot is run because the binary in the other one failed its trial.
+pub fn is_rolled_back(dir: &Path) -> bool {
+	let inactive = active_slot(dir).other();
+	match read(dir, &inactive.pointer_file()) {
+		Some(binary) => read(dir, &inactive.demoted_file()) == Some(binary),
+		None => false,
+	}
+}
+
+fn settle(dir: &Path, slot: Slot) -> io::Result<()> {
+	match read(dir, &slot.pointer_file()) {
+		Some(binary) => write(dir, &slot.settled_file(), &binary),
+		None => Ok(()),
+	}
+}
+
+fn active_slot(dir: &Path) -> Slot {
+	match read(dir, ACTIVE_SLOT_FILE).as_ref().map(|s| s.trim()) {
+		Some("b") => Slot::B,
+		_ => Slot::A,
+	}
+}
+
+/// Replaces the file `name` with one holding `content`, by a rename so it's never seen
+/// half-written, e.g. after a crash.
+fn write(dir: &Path, name: &str, content: &str) -> io::Result<()> {
+	let path = dir.join(name);
+	let partial = dir.join(format!("{}.new", name));
+	File::create(&partial)
+		.and_then(|mut f| f.write_all(content.as_bytes()))
+		.and_then(|_| fs::rename(&partial, &path))
+}
+
+fn read(dir: &Path, name: &str) -> Option<String> {
+	let mut content = String::new();
+	File::open(dir.join(name)).and_then(|mut f| f.read_to_string(&mut content)).ok().map(|_| content)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+	use std::path::Path;
+	use std::thread;
+	use std::time::{Duration, Instant};
+	use tempdir::TempDir;
+	use launcher::LauncherConfig;
+	use super::{demote, is_rolled_back, promote, slot_to_run, uses_slots, Slot, SlotRun, Trial};
+
+	fn install(dir: &Path, slot: Slot, binary: &str) {
+		File::create(dir.join(slot.pointer_file())).unwrap().write_all(binary.as_bytes()).unwrap();
+	}
+
+	fn run(slot: Slot, binary: &str, trial: bool) -> Option<SlotRun> {
+		Some(SlotRun { slot, binary: binary.into(), trial })
+	}
+
+	fn trial_config(dir: &Path, promote_after: Duration) -> LauncherConfig {
+		let mut launcher = LauncherConfig::new(vec!["parity".into()], None, dir.to_owned()).unwrap();
+		launcher.slot_promote_after = promote_after;
+		launcher
+	}
+
+	#[test]
+	fn should_promote_candidate_while_it_runs() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		install(tempdir.path(), Slot::B, "parity-1.12.0-bb");
+		let trial = Trial::start(&trial_config(tempdir.path(), Duration::from_millis(10)), Slot::B);
+
+		let started = Instant::now();
+		while slot_to_run(tempdir.path()) != run(Slot::B, "parity-1.12.0-bb", false) {
+			assert!(started.elapsed() < Duration::from_secs(5), "slot-b wasn't promoted");
+			thread::sleep(Duration::from_millis(5));
+		}
+		// the node being killed later on says nothing about the update any more
+		trial.finish(true);
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.0-bb", false));
+	}
+
+	#[test]
+	fn should_settle_trial_finished_early() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		install(tempdir.path(), Slot::B, "parity-1.12.0-bb");
+		let launcher = trial_config(tempdir.path(), Duration::from_secs(3600));
+
+		Trial::start(&launcher, Slot::B).finish(false);
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.0-bb", true));
+
+		Trial::start(&launcher, Slot::B).finish(true);
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::A, "parity-1.11.0-aa", false));
+	}
+
+	#[test]
+	fn should_run_active_slot_without_candidate() {
+		let tempdir = TempDir::new("").unwrap();
+		assert!(!uses_slots(tempdir.path()));
+		assert_eq!(slot_to_run(tempdir.path()), None);
+
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		assert!(uses_slots(tempdir.path()));
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::A, "parity-1.11.0-aa", false));
+	}
+
+	#[test]
+	fn should_promote_candidate_on_success() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		install(tempdir.path(), Slot::B, "parity-1.12.0-bb");
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.0-bb", true));
+
+		promote(tempdir.path(), Slot::B).unwrap();
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.0-bb", false));
+
+		// the updater installs the next version into the now inactive slot
+		install(tempdir.path(), Slot::A, "parity-1.12.1-cc");
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::A, "parity-1.12.1-cc", true));
+	}
+
+	#[test]
+	fn should_demote_candidate_on_early_failure() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		install(tempdir.path(), Slot::B, "parity-1.12.0-bb");
+
+		assert!(!is_rolled_back(tempdir.path()));
+		demote(tempdir.path(), Slot::B).unwrap();
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::A, "parity-1.11.0-aa", false));
+		assert!(is_rolled_back(tempdir.path()));
+
+		// a fixed version gets its own trial
+		install(tempdir.path(), Slot::B, "parity-1.12.1-cc");
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.1-cc", true));
+		assert!(!is_rolled_back(tempdir.path()));
+	}
+
+	#[test]
+	fn should_not_roll_back_after_promotion() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		install(tempdir.path(), Slot::B, "parity-1.12.0-bb");
+
+		promote(tempdir.path(), Slot::B).unwrap();
+		assert!(!is_rolled_back(tempdir.path()));
+	}
+
+	#[test]
+	fn should_keep_promotion_over_partial_write() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), Slot::A, "parity-1.11.0-aa");
+		install(tempdir.path(), Slot::B, "parity-1.12.0-bb");
+		promote(tempdir.path(), Slot::B).unwrap();
+
+		// a write cut short, e.g. by a crash, only leaves the partial file behind
+		File::create(tempdir.path().join("active-slot.new")).unwrap();
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.0-bb", false));
+
+		promote(tempdir.path(), Slot::B).unwrap();
+		assert!(!tempdir.path().join("active-slot.new").exists());
+		assert_eq!(slot_to_run(tempdir.path()), run(Slot::B, "parity-1.12.0-bb", false));
+	}
+}
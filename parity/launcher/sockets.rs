@@ -0,0 +1,160 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Process-wide setup of the sockets library, balanced across nested and repeated runs of the node.
+//!
+//! Windows sockets have to be started before the node uses them, and cleaned up before another
+//! Parity process is spawned. Each run of the node holds a `SocketsGuard` for as long as it needs
+//! sockets: the first guard starts them, and dropping the last one cleans them up.
+
+use parking_lot::Mutex;
+
+lazy_static! {
+	static ref SOCKETS: InitCount = InitCount::default();
+}
+
+/// Number of users of a process-wide resource, set up for the first one and torn down after the
+/// last one.
+#[derive(Default)]
+struct InitCount {
+	users: Mutex<usize>,
+}
+
+impl InitCount {
+	/// Counts a user in, running `init` first if there was none.
+	fn enter<F: FnOnce()>(&self, init: F) {
+		let mut users = self.users.lock();
+		if *users == 0 {
+			init();
+		}
+		*users += 1;
+	}
+
+	/// Counts a user out, running `cleanup` if it was the last one. Leaving without a user in does
+	/// nothing.
+	fn leave<F: FnOnce()>(&self, cleanup: F) {
+		let mut users = self.users.lock();
+		match *users {
+			0 => {},
+			1 => {
+				*users = 0;
+				cleanup();
+			},
+			_ => *users -= 1,
+		}
+	}
+}
+
+/// Keeps the sockets library set up while it's alive.
+pub struct SocketsGuard(());
+
+impl Drop for SocketsGuard {
+	fn drop(&mut self) {
+		SOCKETS.leave(global_cleanup);
+	}
+}
+
+/// Sets up the sockets library unless it already is, until the returned guard is dropped.
+pub fn init_sockets() -> SocketsGuard {
+	SOCKETS.enter(global_init);
+	SocketsGuard(())
+}
+
+#[cfg(windows)]
+fn global_init() {
+	// When restarting in the same process this reinits windows sockets.
+	unsafe {
+		const WS_VERSION: u16 = 0x202;
+		let mut wsdata: ::winapi::um::winsock2::WSADATA = ::std::mem::zeroed();
+		::winapi::um::winsock2::WSAStartup(WS_VERSION, &mut wsdata);
+	}
+}
+
+#[cfg(not(windows))]
+fn global_init() {}
+
+#[cfg(windows)]
+fn global_cleanup() {
+	// We need to cleanup all sockets before spawning another Parity process. This makes sure everything is cleaned up.
+	// The loop is required because of internal reference counter for winsock dll. We don't know how many crates we use do
+	// initialize it. There's at least 2 now.
+	for _ in 0.. 10 {
+		unsafe { ::winapi::um::winsock2::WSACleanup(); }
+	}
+}
+
+#[cfg(not(windows))]
+fn global_cleanup() {}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use super::{init_sockets, InitCount, SOCKETS};
+
+	#[test]
+	fn should_init_once_and_clean_up_after_last_user() {
+		let count = InitCount::default();
+		let (inits, cleanups) = (Cell::new(0), Cell::new(0));
+		let init = || inits.set(inits.get() + 1);
+		let cleanup = || cleanups.set(cleanups.get() + 1);
+
+		count.enter(init);
+		count.enter(init);
+		assert_eq!((inits.get(), cleanups.get()), (1, 0));
+		count.leave(cleanup);
+		assert_eq!((inits.get(), cleanups.get()), (1, 0));
+		count.leave(cleanup);
+		assert_eq!((inits.get(), cleanups.get()), (1, 1));
+	}
+
+	#[test]
+	fn should_init_again_after_cleanup() {
+		let count = InitCount::default();
+		let (inits, cleanups) = (Cell::new(0), Cell::new(0));
+
+		for _ in 0..3 {
+			count.enter(|| inits.set(inits.get() + 1));
+			count.leave(|| cleanups.set(cleanups.get() + 1));
+		}
+		assert_eq!((inits.get(), cleanups.get()), (3, 3));
+	}
+
+	#[test]
+	fn should_ignore_unbalanced_leave() {
+		let count = InitCount::default();
+		let cleanups = Cell::new(0);
+
+		count.leave(|| cleanups.set(cleanups.get() + 1));
+		assert_eq!(cleanups.get(), 0);
+		count.enter(|| {});
+		count.leave(|| cleanups.set(cleanups.get() + 1));
+		count.leave(|| cleanups.set(cleanups.get() + 1));
+		assert_eq!(cleanups.get(), 1);
+		assert_eq!(*count.users.lock(), 0);
+	}
+
+	#[test]
+	fn should_count_nested_guards() {
+		let outer = init_sockets();
+		{
+			let _inner = init_sockets();
+			assert_eq!(*SOCKETS.users.lock(), 2);
+		}
+		assert_eq!(*SOCKETS.users.lock(), 1);
+		drop(outer);
+		assert_eq!(*SOCKETS.users.lock(), 0);
+	}
+}
@@ -0,0 +1,291 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Local HTTP endpoint serving the launcher's state as JSON, for monitoring.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use parking_lot::Mutex;
+use serde_json;
+
+/// Time a client has to send its request and read the response.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest request read, headers included.
+const MAX_REQUEST_LEN: usize = 8192;
+
+/// Path the status is served on as Prometheus metrics rather than JSON.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// State of the launcher, as served by the status endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LauncherStatus {
+	/// Path of the launcher's own executable.
+	pub current_exe: Option<String>,
+	/// Path of the binary running the node, while one is running.
+	pub target: Option<String>,
+	/// Number of times the node was restarted since the launcher started.
+	pub restarts: u32,
+	/// Exit code of the last node run.
+	pub last_exit_code: Option<i32>,
+	/// A newer update is installed but isn't run.
+	pub update_pending: bool,
+}
+
+/// Background thread answering status requests until stopped.
+pub struct StatusServer {
+	addr: SocketAddr,
+	stopping: Arc<AtomicBool>,
+	thread: Option<thread::JoinHandle<()>>,
+}
+
+impl StatusServer {
+	/// Starts serving `status` on `addr`, which mustn't be one of the `node_ports`.
+	pub fn start(addr: &str, node_ports: &[u16], status: Arc<Mutex<LauncherStatus>>) -> io::Result<Self> {
+		let addr = addr.to_socket_addrs()?.next()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} doesn't resolve", addr)))?;
+		if node_ports.contains(&addr.port()) {
+			return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("port {} is used by the node", addr.port())));
+		}
+		let listener = TcpListener::bind(addr)?;
+		let addr = listener.local_addr()?;
+		let stopping = Arc::new(AtomicBool::new(false));
+		let thread_stopping = stopping.clone();
+
+		let thread = thread::Builder::new()
+			.name("launcher-status".into())
+			.spawn(move || {
+				for stream in listener.incoming() {
+					if thread_stopping.load(Ordering::SeqCst) {
+						return;
+					}
+					let answered = stream.and_then(|stream| {
+						let status = status.lock().clone();
+						answer(stream, &status)
+					});
+					if let Err(e) = answered {
+						trace_main!("Couldn't answer a status request: {}", e);
+					}
+				}
+			})?;
+
+		Ok(StatusServer {
+			addr,
+			stopping,
+			thread: Some(thread),
+		})
+	}
+
+	/// Address the status is served on.
+	pub fn addr(&self) -> SocketAddr {
+		self.addr
+	}
+
+	/// Stops serving and waits for the server thread to finish.
+	pub fn stop(mut self) {
+		self.stop_thread();
+	}
+
+	fn stop_thread(&mut self) {
+		self.stopping.store(true, Ordering::SeqCst);
+		// the thread only sees it's stopping once it accepts a connection
+		let wake_addr = match self.addr.ip() {
+			IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), self.addr.port()),
+			IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), self.addr.port()),
+			_ => self.addr,
+		};
+		let _ = TcpStream::connect_timeout(&wake_addr, CLIENT_TIMEOUT);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+impl Drop for StatusServer {
+	fn drop(&mut self) {
+		self.stop_thread();
+	}
+}
+
+/// Answers the request on `stream` with `status` if it's a `GET`, as metrics on `METRICS_PATH`.
+fn answer(mut stream: TcpStream, status: &LauncherStatus) -> io::Result<()> {
+	stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+	stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+	let mut request = Vec::new();
+	let mut chunk = [0u8; 1024];
+	while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+		let len = stream.read(&mut chunk)?;
+		if len == 0 {
+			break;
+		}
+		request.extend_from_slice(&chunk[..len]);
+	}
+
+	let response = if request.starts_with(b"GET ") {
+		let (content_type, body) = if request_path(&request) == METRICS_PATH {
+			("text/plain; version=0.0.4", metrics(status))
+		} else {
+			("application/json", serde_json::to_string(status).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?)
+		};
+		format!(
+			"HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+			content_type, body.len(), body
+		)
+	} else {
+		"HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+	};
+	stream.write_all(response.as_bytes())
+}
+
+/// Path of the HTTP `request`, without its query.
+fn request_path(request: &[u8]) -> &str {
+	let line = request.split(|&b| b == b'\r' || b == b'\n').next().unwrap_or(&[]);
+	let target = line.split(|&b| b == b' ').nth(1).unwrap_or(&[]);
+	let path = target.split(|&b| b == b'?').next().unwrap_or(&[]);
+	::std::str::from_utf8(path).unwrap_or("")
+}
+
+/// `status` in the Prometheus text exposition format.
+fn metrics(status: &LauncherStatus) -> String {
+	let mut metrics = String::new();
+	{
+		let mut metric = |name: &str, kind: &str, help: &str, value: i64| {
+			metrics.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value));
+		};
+		metric("parity_launcher_restarts_total", "counter",
+			"Number of times the node was restarted since the launcher started.", status.restarts as i64);
+		metric("parity_launcher_node_running", "gauge",
+			"Whether the node is running.", status.target.is_some() as i64);
+		metric("parity_launcher_update_available", "gauge",
+			"Whether a newer update is installed but isn't run.", status.update_pending as i64);
+		if let Some(code) = status.last_exit_code {
+			metric("parity_launcher_last_exit_code", "gauge", "Exit code of the last node run.", code as i64);
+		}
+	}
+	metrics
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Read, Write};
+	use std::net::TcpStream;
+	use std::sync::Arc;
+	use parking_lot::Mutex;
+	use serde_json::{self, Value};
+	use super::{request_path, LauncherStatus, StatusServer};
+
+	fn request(server: &StatusServer, request: &str) -> String {
+		let mut stream = TcpStream::connect(server.addr()).unwrap();
+		stream.write_all(request.as_bytes()).unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}
+
+	#[test]
+	fn should_serve_status_as_json() {
+		let status = Arc::new(Mutex::new(LauncherStatus {
+			current_exe: Some("/usr/bin/parity".into()),
+			..Default::default()
+		}));
+		let server = StatusServer::start("127.0.0.1:0", &[8545], status.clone()).unwrap();
+		{
+			let mut status = status.lock();
+			status.target = Some("/updates/parity-1.12.0".into());
+			status.restarts = 2;
+			status.last_exit_code = Some(69);
+		}
+
+		let response = request(&server, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+		assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+		let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+		let json: Value = serde_json::from_str(body).unwrap();
+		assert_eq!(json["current_exe"], "/usr/bin/parity");
+		assert_eq!(json["target"], "/updates/parity-1.12.0");
+		assert_eq!(json["restarts"], 2);
+		assert_eq!(json["last_exit_code"], 69);
+		assert_eq!(json["update_pending"], false);
+		server.stop();
+	}
+
+	#[test]
+	fn should_serve_metrics() {
+		let status = Arc::new(Mutex::new(LauncherStatus {
+			target: Some("/updates/parity-1.12.0".into()),
+			update_pending: true,
+			..Default::default()
+		}));
+		let server = StatusServer::start("127.0.0.1:0", &[], status.clone()).unwrap();
+		let scrape = || {
+			let response = request(&server, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+			assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+			assert!(response.contains("\r\nContent-Type: text/plain; version=0.0.4\r\n"));
+			response[response.find("\r\n\r\n").unwrap() + 4..].to_owned()
+		};
+
+		let body = scrape();
+		assert!(body.contains("# TYPE parity_launcher_restarts_total counter\n"));
+		assert!(body.contains("\nparity_launcher_restarts_total 0\n"));
+		assert!(body.contains("\nparity_launcher_node_running 1\n"));
+		assert!(body.contains("\nparity_launcher_update_available 1\n"));
+		assert!(!body.contains("parity_launcher_last_exit_code"));
+
+		// as the main loop records a restart
+		{
+			let mut status = status.lock();
+			status.target = None;
+			status.last_exit_code = Some(69);
+			status.restarts += 1;
+		}
+		let body = scrape();
+		assert!(body.contains("\nparity_launcher_restarts_total 1\n"));
+		assert!(body.contains("\nparity_launcher_node_running 0\n"));
+		assert!(body.contains("# TYPE parity_launcher_last_exit_code gauge\nparity_launcher_last_exit_code 69\n"));
+		server.stop();
+	}
+
+	#[test]
+	fn should_find_request_path() {
+		assert_eq!(request_path(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n"), "/metrics");
+		assert_eq!(request_path(b"GET /metrics?name[]=x HTTP/1.1\r\n\r\n"), "/metrics");
+		assert_eq!(request_path(b"GET / HTTP/1.1\r\n\r\n"), "/");
+		assert_eq!(request_path(b"GET"), "");
+	}
+
+	#[test]
+	fn should_only_answer_get() {
+		let server = StatusServer::start("127.0.0.1:0", &[], Arc::new(Mutex::new(LauncherStatus::default()))).unwrap();
+		assert!(request(&server, "POST / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 "));
+	}
+
+	#[test]
+	fn should_not_serve_on_node_port() {
+		let status = Arc::new(Mutex::new(LauncherStatus::default()));
+		assert!(StatusServer::start("127.0.0.1:8545", &[30303, 8545], status).is_err());
+	}
+
+	#[test]
+	fn should_stop_serving() {
+		let server = StatusServer::start("127.0.0.1:0", &[], Arc::new(Mutex::new(LauncherStatus::default()))).unwrap();
+		let addr = server.addr();
+		server.stop();
+		assert!(TcpStream::connect(addr).is_err());
+	}
+}
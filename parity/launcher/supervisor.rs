@@ -0,0 +1,385 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Supervision of the node with `--supervise`.
+//!
+//! The launcher then stays around for as long as the node runs as its child, so that termination
+//! signals it gets are forwarded to the node, and a node which crashed is relaunched. A node which
+//! doesn't stop after a forwarded signal is sent `SIGTERM`, and then killed. `SIGHUP` is forwarded
+//! too, for the node to reload its log levels without being stopped.
+
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::LauncherConfig;
+
+/// Time between two checks of whether the supervised child exited.
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// `SIGTERM`, the signal a child which doesn't stop after a forwarded one is sent next.
+const SIGTERM: i32 = 15;
+
+/// PID of the child termination signals are forwarded to, or 0 while there's none.
+static CHILD_PID: AtomicUsize = AtomicUsize::new(0);
+
+/// Termination signal the launcher got last, or 0 if none.
+static STOP_SIGNAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes `SIGINT`, `SIGTERM`, `SIGQUIT` and `SIGHUP` go to the child instead of stopping the launcher.
+///
+/// After a termination signal the launcher stops once the child exited, without relaunching it.
+/// `SIGHUP` only asks the child to reload its log levels, so the launcher goes on as before.
+#[cfg(unix)]
+pub fn forward_signals() {
+	use libc;
+
+	for &signal in &[libc::SIGINT, libc::SIGTERM, libc::SIGQUIT] {
+		unsafe { libc::signal(signal, on_termination as libc::sighandler_t); }
+	}
+	unsafe { libc::signal(libc::SIGHUP, on_hangup as libc::sighandler_t); }
+}
+
+#[cfg(unix)]
+extern "C" fn on_termination(signal: ::libc::c_int) {
+	// atomics and `kill` are all that's async-signal-safe
+	STOP_SIGNAL.store(signal as usize, Ordering::SeqCst);
+	let pid = CHILD_PID.load(Ordering::SeqCst);
+	if pid != 0 {
+		unsafe { ::libc::kill(pid as ::libc::pid_t, signal); }
+	}
+}
+
+#[cfg(unix)]
+extern "C" fn on_hangup(signal: ::libc::c_int) {
+	// not a stop, so it's only passed on; one arriving while no child runs is dropped
+	let pid = CHILD_PID.load(Ordering::SeqCst);
+	if pid != 0 {
+		unsafe { ::libc::kill(pid as ::libc::pid_t, signal); }
+	}
+}
+
+#[cfg(not(unix))]
+pub fn forward_signals() {}
+
+/// Forwards termination signals to the child `pid` until `untrack_child` is called.
+///
+/// A signal which arrived while no child was running is forwarded right away, so that a child
+/// started meanwhile doesn't outlive the launcher asked to stop.
+#[cfg(unix)]
+pub fn track_child(pid: u32) {
+	CHILD_PID.store(pid as usize, Ordering::SeqCst);
+	if let Some(signal) = stop_signal() {
+		unsafe { ::libc::kill(pid as ::libc::pid_t, signal); }
+	}
+}
+
+#[cfg(not(unix))]
+pub fn track_child(_pid: u32) {}
+
+/// Stops forwarding termination signals to the child, which exited.
+pub fn untrack_child() {
+	CHILD_PID.store(0, Ordering::SeqCst);
+}
+
+/// Termination signal forwarded to the child, if the launcher got one.
+pub fn stop_signal() -> Option<i32> {
+	match STOP_SIGNAL.load(Ordering::SeqCst) {
+		0 => None,
+		signal => Some(signal as i32),
+	}
+}
+
+/// Waits for the supervised `child` to exit, making sure it does once the launcher got a
+/// termination signal.
+///
+/// The signal is forwarded as it arrives. A child still running `timeout` after it is sent
+/// `SIGTERM`, unless that's what it was forwarded, and is killed if still running `timeout` after
+/// that.
+pub fn wait_supervised(child: &mut Child, timeout: Duration) -> io::Result<ExitStatus> {
+	wait_stopping(child, timeout, stop_signal)
+}
+
+/// Waits for `child` to exit, escalating once `stop` tells the launcher got a termination signal.
+fn wait_stopping<F>(child: &mut Child, timeout: Duration, stop: F) -> io::Result<ExitStatus> where F: Fn() -> Option<i32> {
+	// when the child was last sent a signal, and whether it was `SIGTERM`
+	let mut signaled: Option<(Instant, bool)> = None;
+	loop {
+		match child.try_wait() {
+			Ok(Some(status)) => return Ok(status),
+			Ok(None) => {},
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		}
+
+		signaled = match (signaled, stop()) {
+			(None, Some(signal)) => Some((Instant::now(), signal == SIGTERM)),
+			(Some((sent, false)), _) if sent.elapsed() >= timeout => {
+				warn_main!("The node didn't stop within {}s of the forwarded signal, sending it SIGTERM.", timeout.as_secs());
+				terminate(child);
+				Some((Instant::now(), true))
+			},
+			(Some((sent, true)), _) if sent.elapsed() >= timeout => {
+				warn_main!("The node didn't stop within {}s of SIGTERM, killing it.", timeout.as_secs());
+				let _ = child.kill();
+				return child.wait();
+			},
+			(signaled, _) => signaled,
+		};
+		thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+	}
+}
+
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+	unsafe { ::libc::kill(child.id() as ::libc::pid_t, SIGTERM); }
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+	let _ = child.kill();
+}
+
+/// Whether the supervised node exiting with `exit_code` crashed, whatever the time it ran for.
+///
+/// A node stopped by a forwarded `stop_signal` exits the way it was asked to, so it didn't crash.
+pub fn is_supervised_crash(launcher: &LauncherConfig, exit_code: i32, stop_signal: Option<i32>) -> bool {
+	launcher.supervise && stop_signal.is_none() && exit_code != 0 && exit_code != launcher.restart_exit_code
+}
+
+/// Exit code the launcher loop goes on with once the supervised node exited with `exit_code`.
+///
+/// A node which `crashed` is relaunched as if it asked for a restart, leaving it to the crash loop
+/// limit to give up on it. A node stopped by a forwarded `stop_signal` isn't relaunched, even if it
+/// asks for a restart.
+pub fn supervised_exit_code(launcher: &LauncherConfig, exit_code: i32, crashed: bool, stop_signal: Option<i32>) -> i32 {
+	match stop_signal {
+		Some(signal) if exit_code == launcher.restart_exit_code => {
+			trace_main!("Not restarting the node stopped by signal {}", signal);
+			0
+		},
+		Some(_) => exit_code,
+		None if crashed && exit_code != launcher.restart_exit_code => {
+			trace_main!("Relaunching the node after it crashed with {}", exit_code);
+			launcher.restart_exit_code
+		},
+		None => exit_code,
+	}
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::os::unix::process::ExitStatusExt;
+	use std::path::{Path, PathBuf};
+	use std::process::Command;
+	use std::sync::atomic::Ordering;
+	use std::thread;
+	use std::time::{Duration, Instant};
+	use libc;
+	use launcher::{Error, LauncherConfig, PanicMarker, PanicTracker};
+	use launcher::child::run_current;
+	use parking_lot::Mutex;
+	use tempdir::TempDir;
+	use super::{
+		is_supervised_crash, on_hangup, on_termination, stop_signal, supervised_exit_code, wait_stopping, CHILD_PID,
+		STOP_SIGNAL,
+	};
+
+	lazy_static! {
+		/// Held by tests running supervised children, so that no other test's child gets the
+		/// signal a test forwards.
+		static ref SUPERVISING: Mutex<()> = Mutex::new(());
+	}
+
+	/// Clears the signal state shared by the supervising tests, again once dropped, so that a
+	/// failing test doesn't leave a stop signal behind for the next one.
+	struct SignalState;
+
+	impl SignalState {
+		fn clear() -> Self {
+			clear_signal_state();
+			SignalState
+		}
+	}
+
+	impl Drop for SignalState {
+		fn drop(&mut self) {
+			clear_signal_state();
+		}
+	}
+
+	fn clear_signal_state() {
+		STOP_SIGNAL.store(0, Ordering::SeqCst);
+		CHILD_PID.store(0, Ordering::SeqCst);
+	}
+
+	/// Runs the shell standing in for the node as a supervised child with `script`, calling
+	/// `handler` as if the launcher got `signal` once the child is tracked.
+	///
+	/// The handler is called directly rather than the test process signalled, which would reach
+	/// every other test running alongside.
+	fn run_signaled(script: &str, handler: extern "C" fn(libc::c_int), signal: libc::c_int) -> Result<i32, Error> {
+		let launcher = config(&["parity", "-c", script, "--supervise"]);
+		let runner = thread::spawn(move || run_current(&launcher, Path::new("/bin/sh")).unwrap());
+		let started = Instant::now();
+		while CHILD_PID.load(Ordering::SeqCst) == 0 {
+			assert!(started.elapsed() < Duration::from_secs(5), "the child wasn't tracked");
+			thread::sleep(Duration::from_millis(10));
+		}
+		// time for the shell to set its trap, though the outcome also allows for one not set yet
+		thread::sleep(Duration::from_millis(200));
+		handler(signal);
+		runner.join().unwrap()
+	}
+
+	fn config(args: &[&str]) -> LauncherConfig {
+		LauncherConfig::new(args.iter().map(|s| s.to_string()).collect(), None, PathBuf::from("/updates")).unwrap()
+	}
+
+	/// Runs the shell standing in for the node the way the launcher loop does, until it isn't
+	/// relaunched anymore. Returns the final exit code and the number of runs.
+	fn supervise_stub(launcher: &LauncherConfig) -> (i32, u32) {
+		let _supervising = SUPERVISING.lock();
+		let mut panics = PanicTracker::default();
+		let mut runs = 0;
+		loop {
+			runs += 1;
+			let exit_code = run_current(launcher, Path::new("/bin/sh")).expect("the shell can be started; qed")
+				.unwrap_or_else(|e| e.exit_code());
+			let crash = if is_supervised_crash(launcher, exit_code, None) {
+				Some(PanicMarker::quick_exit(exit_code, Duration::from_millis(0)))
+			} else {
+				None
+			};
+			let identical_panics = panics.record(crash);
+			let exit_code = supervised_exit_code(launcher, exit_code, panics.last().is_some(), None);
+			if exit_code != launcher.restart_exit_code {
+				return (exit_code, runs);
+			}
+			if identical_panics >= launcher.max_identical_panics {
+				return (1, runs);
+			}
+		}
+	}
+
+	#[test]
+	fn should_relaunch_crashed_child_until_it_succeeds() {
+		let tempdir = TempDir::new("").unwrap();
+		// crashes on the first two runs, counting them in a file
+		let script = format!(
+			"n=$(cat {0} 2>/dev/null || echo 0); echo $((n + 1)) > {0}; test $n -ge 2",
+			tempdir.path().join("runs").display(),
+		);
+		let launcher = config(&["parity", "-c", &script, "--supervise"]);
+
+		assert_eq!(supervise_stub(&launcher), (0, 3));
+	}
+
+	#[test]
+	fn should_give_up_on_child_crashing_the_same_way() {
+		let launcher = config(&["parity", "-c", "exit 3", "--supervise", "--max-identical-panics", "2"]);
+		assert_eq!(supervise_stub(&launcher), (1, 2));
+	}
+
+	#[test]
+	fn should_relaunch_only_when_supervising() {
+		let launcher = config(&["parity", "-c", "exit 3"]);
+		assert!(!is_supervised_crash(&launcher, 3, None));
+		assert_eq!(supervise_stub(&launcher), (3, 1));
+	}
+
+	#[test]
+	fn should_not_relaunch_child_stopped_by_signal() {
+		let launcher = config(&["parity", "--supervise"]);
+		let restart = launcher.restart_exit_code;
+
+		assert!(!is_supervised_crash(&launcher, 143, Some(libc::SIGTERM)));
+		assert!(!is_supervised_crash(&launcher, restart, None));
+		assert!(is_supervised_crash(&launcher, 143, None));
+		assert_eq!(supervised_exit_code(&launcher, 143, false, Some(libc::SIGTERM)), 143);
+		assert_eq!(supervised_exit_code(&launcher, restart, false, Some(libc::SIGTERM)), 0);
+		assert_eq!(supervised_exit_code(&launcher, 143, true, None), restart);
+		assert_eq!(supervised_exit_code(&launcher, 0, false, None), 0);
+	}
+
+	#[test]
+	fn should_forward_termination_signal_to_child() {
+		let _supervising = SUPERVISING.lock();
+		let _state = SignalState::clear();
+		// the shell stops its `sleep` and exits with 7 once it gets the forwarded signal
+		let result = run_signaled("trap 'kill $!; exit 7' TERM; sleep 30 & wait", on_termination, libc::SIGTERM);
+
+		// the signal may be forwarded before the shell gets to set its trap
+		assert!(result == Ok(7) || result == Err(Error::KilledBySignal(libc::SIGTERM)), "{:?}", result);
+		assert_eq!(stop_signal(), Some(libc::SIGTERM));
+	}
+
+	#[test]
+	fn should_forward_hangup_without_stopping() {
+		let _supervising = SUPERVISING.lock();
+		let _state = SignalState::clear();
+		// the shell stands in for a node reloading its log levels, telling it got the signal
+		let result = run_signaled("trap 'kill $!; exit 9' HUP; sleep 30 & wait", on_hangup, libc::SIGHUP);
+
+		assert!(result == Ok(9) || result == Err(Error::KilledBySignal(libc::SIGHUP)), "{:?}", result);
+		assert_eq!(stop_signal(), None);
+	}
+
+	#[test]
+	fn should_let_cooperative_child_shut_down_on_forwarded_interrupt() {
+		let _supervising = SUPERVISING.lock();
+		let _state = SignalState::clear();
+		// the shell shuts down cleanly once it gets the forwarded Ctrl-C
+		let result = run_signaled("trap 'kill $!; exit 0' INT; sleep 30 & wait", on_termination, libc::SIGINT);
+
+		assert!(result == Ok(0) || result == Err(Error::KilledBySignal(libc::SIGINT)), "{:?}", result);
+		assert_eq!(stop_signal(), Some(libc::SIGINT));
+	}
+
+	#[test]
+	fn should_drop_hangup_without_child() {
+		let _supervising = SUPERVISING.lock();
+		let _state = SignalState::clear();
+		on_hangup(libc::SIGHUP);
+		assert_eq!(stop_signal(), None);
+		on_termination(libc::SIGTERM);
+		assert_eq!(stop_signal(), Some(libc::SIGTERM));
+	}
+
+	#[test]
+	fn should_escalate_to_sigterm_then_kill() {
+		// ignores SIGINT but stops on SIGTERM
+		let mut child = Command::new("/bin/sh").args(&["-c", "trap '' INT; trap 'exit 5' TERM; while :; do sleep 0.1; done"]).spawn().unwrap();
+		thread::sleep(Duration::from_millis(200));
+		let status = wait_stopping(&mut child, Duration::from_millis(300), || Some(libc::SIGINT)).unwrap();
+		assert_eq!(status.code(), Some(5));
+
+		// ignores both, so it's killed
+		let mut child = Command::new("/bin/sh").args(&["-c", "trap '' INT TERM; while :; do sleep 0.1; done"]).spawn().unwrap();
+		thread::sleep(Duration::from_millis(200));
+		let status = wait_stopping(&mut child, Duration::from_millis(300), || Some(libc::SIGTERM)).unwrap();
+		assert_eq!(status.signal(), Some(libc::SIGKILL));
+	}
+
+	#[test]
+	fn should_wait_for_child_without_stop_signal() {
+		let mut child = Command::new("/bin/sh").args(&["-c", "sleep 0.3; exit 4"]).spawn().unwrap();
+		let status = wait_stopping(&mut child, Duration::from_millis(0), || None).unwrap();
+		assert_eq!(status.code(), Some(4));
+	}
+}
@@ -0,0 +1,293 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Filesystem and clock access of the launcher, abstracted for tests.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Operations on the outside world the update logic depends on.
+pub trait LauncherEnv {
+	/// Reads the content of a pointer file, like `latest`.
+	fn read_pointer(&self, path: &Path) -> io::Result<String>;
+
+	/// Whether `path` is a regular file. Fails if it doesn't exist.
+	fn is_file(&self, path: &Path) -> io::Result<bool>;
+
+	/// Whether `path` exists.
+	fn exists(&self, path: &Path) -> bool;
+
+	/// Last modification time of the file at `path`.
+	fn file_mtime(&self, path: &Path) -> io::Result<SystemTime>;
+
+	/// Removes the file at `path`.
+	fn remove(&self, path: &Path) -> io::Result<()>;
+
+	/// Target of the symlink at `path`. Fails if it isn't one.
+	fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+	/// Current time.
+	fn now(&self) -> SystemTime;
+
+	/// Blocks the current thread for `duration`.
+	fn sleep(&self, duration: Duration);
+}
+
+/// Runs `f` until it isn't interrupted by a signal arriving at the launcher.
+///
+/// A signal such as `SIGCHLD` or `SIGWINCH` interrupting the wait for the child says nothing about
+/// the child, so it mustn't be mistaken for a failure to run it. Slow or networked filesystems may
+/// be interrupted the same way.
+pub fn retry_on_interrupt<T, F>(mut f: F) -> io::Result<T> where F: FnMut() -> io::Result<T> {
+	loop {
+		match f() {
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => trace_main!("Interrupted by a signal, retrying"),
+			result => return result,
+		}
+	}
+}
+
+/// Most symlinks followed by `resolve_symlinks`, so that a loop of them is given up on.
+pub const MAX_SYMLINK_DEPTH: usize = 8;
+
+/// Path of the file `path` ends at, following up to `MAX_SYMLINK_DEPTH` symlinks.
+///
+/// A relative target is relative to the directory of the link pointing to it. Needing more
+/// symlinks, e.g. because they form a loop, is an error.
+pub fn resolve_symlinks<E: LauncherEnv>(env: &E, path: &Path) -> io::Result<PathBuf> {
+	let mut path = path.to_owned();
+	let mut followed = 0;
+	while let Ok(target) = env.read_link(&path) {
+		if followed == MAX_SYMLINK_DEPTH {
+			return Err(io::Error::new(io::ErrorKind::Other, format!("more than {} symlinks to follow", MAX_SYMLINK_DEPTH)));
+		}
+		followed += 1;
+		path = path.parent().map_or_else(|| target.clone(), |dir| dir.join(&target));
+	}
+	Ok(path)
+}
+
+/// The actual filesystem and clock.
+pub struct RealEnv;
+
+impl LauncherEnv for RealEnv {
+	fn read_pointer(&self, path: &Path) -> io::Result<String> {
+		let mut content = String::new();
+		File::open(path).and_then(|mut f| f.read_to_string(&mut content)).map(|_| content)
+	}
+
+	fn is_file(&self, path: &Path) -> io::Result<bool> {
+		fs::metadata(path).map(|m| m.is_file())
+	}
+
+	fn exists(&self, path: &Path) -> bool {
+		path.exists()
+	}
+
+	fn file_mtime(&self, path: &Path) -> io::Result<SystemTime> {
+		fs::metadata(path)?.modified()
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		fs::remove_file(path)
+	}
+
+	fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+		fs::read_link(path)
+	}
+
+	fn now(&self) -> SystemTime {
+		SystemTime::now()
+	}
+
+	fn sleep(&self, duration: Duration) {
+		thread::sleep(duration)
+	}
+}
+
+#[cfg(test)]
+pub mod test_env {
+	//! In-memory environment for tests.
+
+	use std::cell::{Cell, RefCell};
+	use std::collections::HashMap;
+	use std::io;
+	use std::path::{Path, PathBuf};
+	use std::time::{Duration, SystemTime, UNIX_EPOCH};
+	use super::LauncherEnv;
+
+	/// Entry of the in-memory filesystem.
+	pub enum Entry {
+		/// Regular file with the given content.
+		File(String),
+		/// Directory.
+		Dir,
+	}
+
+	/// In-memory filesystem, where files may show up only after a number of sleeps.
+	///
+	/// The clock starts at the Unix epoch and only moves forward by sleeping.
+	#[derive(Default)]
+	pub struct TestEnv {
+		entries: RefCell<HashMap<PathBuf, (Entry, usize)>>,
+		mtimes: RefCell<HashMap<PathBuf, SystemTime>>,
+		interrupts: Cell<usize>,
+		/// Durations slept so far.
+		pub slept: RefCell<Vec<Duration>>,
+	}
+
+	impl TestEnv {
+		/// Adds an entry, visible once `after_sleeps` sleeps happened.
+		pub fn add<P: Into<PathBuf>>(&self, path: P, entry: Entry, after_sleeps: usize) {
+			self.entries.borrow_mut().insert(path.into(), (entry, after_sleeps));
+		}
+
+		/// Adds a file with the given content, last modified `secs` seconds after the epoch.
+		pub fn add_file_at<P: Into<PathBuf>>(&self, path: P, content: &str, secs: u64) {
+			let path = path.into();
+			self.mtimes.borrow_mut().insert(path.clone(), UNIX_EPOCH + Duration::from_secs(secs));
+			self.add(path, Entry::File(content.into()), 0);
+		}
+
+		/// Makes the next `times` reads and removals fail as interrupted by a signal.
+		pub fn interrupt(&self, times: usize) {
+			self.interrupts.set(times);
+		}
+
+		fn interrupted(&self) -> io::Result<()> {
+			match self.interrupts.get() {
+				0 => Ok(()),
+				left => {
+					self.interrupts.set(left - 1);
+					Err(io::ErrorKind::Interrupted.into())
+				},
+			}
+		}
+
+		fn with_entry<T, F: FnOnce(&Entry) -> T>(&self, path: &Path, f: F) -> io::Result<T> {
+			let sleeps = self.slept.borrow().len();
+			match self.entries.borrow().get(path) {
+				Some(&(ref entry, after_sleeps)) if sleeps >= after_sleeps => Ok(f(entry)),
+				_ => Err(io::ErrorKind::NotFound.into()),
+			}
+		}
+	}
+
+	impl LauncherEnv for TestEnv {
+		fn read_pointer(&self, path: &Path) -> io::Result<String> {
+			self.interrupted()?;
+			self.with_entry(path, |entry| match *entry {
+				Entry::File(ref content) => Ok(content.clone()),
+				Entry::Dir => Err(io::Error::new(io::ErrorKind::Other, "is a directory")),
+			}).and_then(|r| r)
+		}
+
+		fn is_file(&self, path: &Path) -> io::Result<bool> {
+			self.with_entry(path, |entry| match *entry {
+				Entry::File(_) => true,
+				Entry::Dir => false,
+			})
+		}
+
+		fn exists(&self, path: &Path) -> bool {
+			self.with_entry(path, |_| ()).is_ok()
+		}
+
+		fn file_mtime(&self, path: &Path) -> io::Result<SystemTime> {
+			self.with_entry(path, |_| self.mtimes.borrow().get(path).cloned().unwrap_or(UNIX_EPOCH))
+		}
+
+		fn remove(&self, path: &Path) -> io::Result<()> {
+			self.interrupted()?;
+			self.with_entry(path, |_| ())?;
+			self.entries.borrow_mut().remove(path);
+			Ok(())
+		}
+
+		fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+			self.with_entry(path, |_| ())?;
+			Err(io::Error::new(io::ErrorKind::InvalidInput, "not a symlink"))
+		}
+
+		fn now(&self) -> SystemTime {
+			self.slept.borrow().iter().fold(UNIX_EPOCH, |now, slept| now + *slept)
+		}
+
+		fn sleep(&self, duration: Duration) {
+			self.slept.borrow_mut().push(duration);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+	use tempdir::TempDir;
+	use super::{resolve_symlinks, retry_on_interrupt, RealEnv};
+
+	#[test]
+	fn should_retry_interrupted_wait() {
+		let mut attempts = 0;
+		let result = retry_on_interrupt(|| {
+			attempts += 1;
+			if attempts == 1 { Err(io::Error::from(io::ErrorKind::Interrupted)) } else { Ok(42) }
+		});
+		assert_eq!(result.unwrap(), 42);
+		assert_eq!(attempts, 2);
+	}
+
+	#[test]
+	fn should_not_retry_other_errors() {
+		let mut attempts = 0;
+		let result: io::Result<()> = retry_on_interrupt(|| {
+			attempts += 1;
+			Err(io::Error::from(io::ErrorKind::PermissionDenied))
+		});
+		assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+		assert_eq!(attempts, 1);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_resolve_chained_symlinks() {
+		use std::fs::File;
+		use std::os::unix::fs::symlink;
+
+		let tempdir = TempDir::new("").unwrap();
+		let binary = tempdir.path().join("parity-1.12.0");
+		File::create(&binary).unwrap();
+		symlink("parity-1.12.0", tempdir.path().join("parity-stable")).unwrap();
+		symlink(tempdir.path().join("parity-stable"), tempdir.path().join("parity")).unwrap();
+
+		assert_eq!(resolve_symlinks(&RealEnv, &tempdir.path().join("parity")).unwrap(), binary);
+		assert_eq!(resolve_symlinks(&RealEnv, &binary).unwrap(), binary);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_give_up_on_symlink_loop() {
+		use std::os::unix::fs::symlink;
+
+		let tempdir = TempDir::new("").unwrap();
+		symlink("b", tempdir.path().join("a")).unwrap();
+		symlink("a", tempdir.path().join("b")).unwrap();
+
+		assert!(resolve_symlinks(&RealEnv, &tempdir.path().join("a")).is_err());
+	}
+}
@@ -0,0 +1,109 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Opt-in reporting of update decisions to a local Unix socket, for fleet monitoring.
+
+#[cfg(unix)]
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use std::time::Duration;
+#[cfg(unix)]
+use serde_json;
+use super::{LaunchDecision, LauncherConfig};
+
+/// Time a listener may take to accept a decision before it's dropped.
+#[cfg(unix)]
+const SOCKET_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Sends `decision` as a JSON line to the socket given by `--decision-socket`, if any.
+///
+/// Monitoring is best-effort: a missing or failing listener is only traced.
+#[cfg(unix)]
+pub fn report_decision(launcher: &LauncherConfig, decision: &LaunchDecision) {
+	if let Some(ref path) = launcher.decision_socket {
+		if let Err(e) = send_line(path, decision) {
+			trace_main!("Couldn't report the update decision to {}: {}", path.display(), e);
+		}
+	}
+}
+
+#[cfg(not(unix))]
+pub fn report_decision(_launcher: &LauncherConfig, _decision: &LaunchDecision) {}
+
+#[cfg(unix)]
+fn send_line(path: &Path, decision: &LaunchDecision) -> io::Result<()> {
+	use std::os::unix::net::UnixStream;
+
+	let mut line = serde_json::to_string(decision).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+	line.push('\n');
+	let mut stream = UnixStream::connect(path)?;
+	stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+	stream.write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::io::{BufRead, BufReader};
+	use std::os::unix::net::UnixListener;
+	use std::path::PathBuf;
+	use tempdir::TempDir;
+	use launcher::{LaunchDecision, LauncherConfig};
+	use super::report_decision;
+
+	fn config(socket: &str) -> LauncherConfig {
+		let args = vec!["parity".into(), "--decision-socket".into(), socket.into()];
+		LauncherConfig::new(args, None, PathBuf::from("/updates")).unwrap()
+	}
+
+	fn decision() -> LaunchDecision {
+		LaunchDecision {
+			launch_id: Some("0123456789abcdef".into()),
+			latest: Some("/updates/parity-1.12.0".into()),
+			state: "newer update available".into(),
+			have_update: true,
+			current_is_not_latest: true,
+			update_is_newer: true,
+			update_is_allowed: true,
+			run_update: true,
+		}
+	}
+
+	#[test]
+	fn should_send_one_decision_line() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("decisions.sock");
+		let listener = UnixListener::bind(&path).unwrap();
+
+		report_decision(&config(path.to_str().unwrap()), &decision());
+
+		let (stream, _) = listener.accept().unwrap();
+		let mut line = String::new();
+		BufReader::new(stream).read_line(&mut line).unwrap();
+		assert_eq!(line, concat!(
+			r#"{"launch_id":"0123456789abcdef","latest":"/updates/parity-1.12.0","state":"newer update available","#,
+			r#""have_update":true,"current_is_not_latest":true,"update_is_newer":true,"update_is_allowed":true,"run_update":true}"#,
+			"\n",
+		));
+	}
+
+	#[test]
+	fn should_skip_missing_socket() {
+		let tempdir = TempDir::new("").unwrap();
+		report_decision(&config(tempdir.path().join("missing.sock").to_str().unwrap()), &decision());
+	}
+}
@@ -0,0 +1,938 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Access to the updates directory.
+
+use std::env;
+use std::fs::{create_dir_all, remove_file, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use rand::{self, Rng};
+use serde_json::{self, Value};
+use super::{Error, LauncherConfig};
+use super::slots::{slot_to_run, uses_slots};
+use super::system::{resolve_symlinks, retry_on_interrupt, LauncherEnv, RealEnv};
+
+/// Number of times reading a missing `latest` pointer is retried, in case it's being replaced.
+const LATEST_READ_RETRIES: u32 = 3;
+
+/// Base delay between attempts at reading the `latest` pointer, in milliseconds.
+///
+/// Each attempt waits up to twice as long, using random jitter.
+const LATEST_READ_RETRY_DELAY_MS: u64 = 20;
+
+/// File naming the most recently installed binary.
+pub const LATEST_FILE: &str = "latest";
+
+/// File naming the most recently installed pre-release binary, run on the candidate channel.
+pub const CANDIDATE_FILE: &str = "candidate";
+
+/// File holding the chain the node should switch to on its next start, as a JSON `SpecOverride`
+/// or a bare chain name.
+pub const SPEC_NAME_OVERRIDE_FILE: &str = "spec_name_override";
+
+/// File in which the node acknowledges switching to the chain override it holds a copy of.
+pub const SPEC_NAME_OVERRIDE_ACK_FILE: &str = "spec_name_override.ack";
+
+/// File in which the node records the chain it actually runs.
+pub const RUNNING_SPEC_FILE: &str = "running_spec";
+
+/// Marker opting a chain override into `${VAR}` expansion against the environment.
+const SPEC_NAME_EXPAND_MARKER: &str = "env:";
+
+/// File holding the arguments the node wants to keep across a restart, one per line.
+pub const STICKY_ARGS_FILE: &str = "sticky_args";
+
+/// File asking a launcher running with `--update-check-only` to switch to the staged update.
+pub const APPLY_UPDATE_FILE: &str = "apply_update";
+
+/// File naming the update binary which didn't become healthy, not to be run again.
+pub const BAD_UPDATE_FILE: &str = "bad_update";
+
+/// Prefix of the marker files pinning a chain to the current binary, followed by the chain name.
+const FORCE_DIRECT_PREFIX: &str = "force-direct-";
+
+/// Makes sure the updates directory exists and can be written to.
+pub fn ensure_updates_dir(dir: &Path) -> io::Result<()> {
+	create_dir_all(dir)?;
+	let probe = dir.join(".probe");
+	File::create(&probe).and_then(|mut f| f.write_all(b"probe"))?;
+	remove_file(probe)
+}
+
+/// Returns the path of the binary the `latest` pointer refers to.
+///
+/// A binary given with `--use-binary` is returned as it is. With update slots in use, the slot
+/// to run is resolved instead. A missing pointer is reported as `Error::NotConfigured`, and one
+/// which isn't a regular file as `Error::LatestFileUnreadable`. The binary must be a regular
+/// file; anything else is reported as `Error::BinaryNotFound`.
+pub fn latest_exe_path(launcher: &LauncherConfig) -> Result<PathBuf, Error> {
+	latest_exe_path_in(launcher, &RealEnv)
+}
+
+/// Returns the path of the binary the `latest` pointer refers to, using the given environment.
+///
+/// On the candidate channel, the `candidate` pointer is used instead, and A/B slots are ignored.
+/// The updater replaces the pointer while the launcher may be reading it, so a missing pointer
+/// is retried a few times before giving up.
+pub fn latest_exe_path_in<E: LauncherEnv>(launcher: &LauncherConfig, env: &E) -> Result<PathBuf, Error> {
+	if let Some(ref binary) = launcher.use_binary {
+		return Ok(binary.clone());
+	}
+	if let Some(ref e) = launcher.updates_dir_error {
+		return Err(Error::NoUpdatesDir(e.clone()));
+	}
+	if launcher.updates_disabled {
+		return Err(Error::NotConfigured);
+	}
+
+	let exe = if launcher.update_channel == UpdateChannel::Stable && uses_slots(&launcher.updates_dir) {
+		slot_to_run(&launcher.updates_dir).map(|run| run.binary).ok_or(Error::NotConfigured)?
+	} else {
+		read_latest_pointer(launcher, env)?
+	};
+
+	if env.read_pointer(&launcher.updates_path(BAD_UPDATE_FILE)).ok().as_ref() == Some(&exe) {
+		trace_main!("Latest update {} didn't become healthy before, ignoring it", exe);
+		return Err(Error::MarkedBad);
+	}
+
+	// an empty pointer names no binary at all rather than one elsewhere
+	if !exe.is_empty() && !is_plain_file_name(&exe) {
+		warn_main!(
+			"Refusing to run {:?} named by the latest pointer in {}: updates must be binaries in that directory. \
+			The pointer may have been tampered with.",
+			exe, launcher.updates_dir.display()
+		);
+		return Err(Error::UnsafeUpdatePath(exe));
+	}
+
+	let path = launcher.updates_path(&exe);
+	// a symlinked update is checked for the binary it ends at, but still run through the link
+	match resolve_symlinks(env, &path).and_then(|target| env.is_file(&target)) {
+		Ok(true) => Ok(path),
+		Ok(false) => {
+			trace_main!("Latest update {} is not a regular file, ignoring it", path.display());
+			Err(Error::BinaryNotFound)
+		},
+		Err(_) => Err(Error::BinaryNotFound),
+	}
+}
+
+/// Channel of updates the launcher runs, selected with `--update-channel`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateChannel {
+	/// Released binaries, installed behind the `latest` pointer.
+	Stable,
+	/// Pre-release binaries, installed behind the `candidate` pointer.
+	Candidate,
+}
+
+impl UpdateChannel {
+	/// Name of the pointer file naming the binary of the channel.
+	pub fn pointer_file(&self) -> &'static str {
+		match *self {
+			UpdateChannel::Stable => LATEST_FILE,
+			UpdateChannel::Candidate => CANDIDATE_FILE,
+		}
+	}
+}
+
+impl Default for UpdateChannel {
+	fn default() -> Self {
+		UpdateChannel::Stable
+	}
+}
+
+impl FromStr for UpdateChannel {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, ()> {
+		match s {
+			"stable" => Ok(UpdateChannel::Stable),
+			"candidate" => Ok(UpdateChannel::Candidate),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Whether `name` is the name of a file in a directory, rather than a path which could lead out of it.
+fn is_plain_file_name(name: &str) -> bool {
+	let mut components = Path::new(name).components();
+	match (components.next(), components.next()) {
+		(Some(Component::Normal(_)), None) => !name.contains('/') && !name.contains('\\'),
+		_ => false,
+	}
+}
+
+fn read_latest_pointer<E: LauncherEnv>(launcher: &LauncherConfig, env: &E) -> Result<String, Error> {
+	let path = launcher.updates_path(launcher.update_channel.pointer_file());
+	let mut retries = 0;
+	loop {
+		// reading a directory fails differently from one platform to the next, if it fails at all
+		if let Ok(false) = env.is_file(&path) {
+			return Err(Error::LatestFileUnreadable(path.display().to_string()));
+		}
+		match env.read_pointer(&path) {
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound && retries < LATEST_READ_RETRIES && env.exists(&launcher.updates_dir) => {
+				retries += 1;
+				let delay = LATEST_READ_RETRY_DELAY_MS + rand::thread_rng().gen_range(0, LATEST_READ_RETRY_DELAY_MS + 1);
+				trace_main!("Latest pointer not found, retrying in {}ms ({}/{})", delay, retries, LATEST_READ_RETRIES);
+				env.sleep(Duration::from_millis(delay));
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Err(Error::NotConfigured),
+			result => return result.map_err(|e| Error::PointerUnreadable(e.to_string())),
+		}
+	}
+}
+
+/// Records update binary `exe` as bad, so that it isn't run again.
+pub fn mark_bad_update(launcher: &LauncherConfig, exe: &Path) {
+	let name = match exe.file_name() {
+		Some(name) if launcher.can_write_state() => name.to_string_lossy().into_owned(),
+		_ => return,
+	};
+	let path = launcher.updates_path(BAD_UPDATE_FILE);
+	if let Err(e) = File::create(&path).and_then(|mut f| f.write_all(name.as_bytes())) {
+		warn!("Couldn't record the bad update: {} at {:?}", e, path);
+	}
+}
+
+/// Chain the node should switch to on its next start, with the arguments the switch needs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpecOverride {
+	/// Name of the chain, or path of its spec.
+	pub chain: String,
+	/// Arguments given to the node on top of the user's, e.g. for the chain's default ports.
+	pub extra_args: Vec<String>,
+}
+
+impl<'a> From<&'a str> for SpecOverride {
+	fn from(chain: &'a str) -> Self {
+		SpecOverride { chain: chain.into(), extra_args: Vec::new() }
+	}
+}
+
+impl SpecOverride {
+	/// Parses an override stored as JSON, or as the bare chain name older launchers store.
+	fn parse(content: &str) -> Result<Self, String> {
+		if !content.trim_left().starts_with('{') {
+			return Ok(content.into());
+		}
+
+		let value: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+		let chain = value["chain"].as_str().ok_or_else(|| "no chain given".to_owned())?;
+		let extra_args = match value["extra_args"] {
+			Value::Null => Vec::new(),
+			Value::Array(ref args) => args.iter()
+				.map(|arg| arg.as_str().map(Into::into).ok_or_else(|| "extra arguments must be strings".to_owned()))
+				.collect::<Result<_, _>>()?,
+			_ => return Err("extra arguments must be a list".into()),
+		};
+		Ok(SpecOverride { chain: chain.into(), extra_args })
+	}
+}
+
+/// Stores the chain the node should switch to on its next start.
+pub fn set_spec_name_override(launcher: &LauncherConfig, spec_name: SpecOverride) {
+	if !launcher.can_write_state() {
+		return;
+	}
+	set_spec_name_override_in(&launcher.updates_dir, spec_name)
+}
+
+/// Takes the chain the node should switch to.
+///
+/// The override stays on disk until the node acknowledges switching with
+/// `ack_spec_name_override`, so a crash before that applies it again on the next start. A
+/// read-only launcher never removes an override, so it applies to every run.
+pub fn take_spec_name_override(launcher: &LauncherConfig) -> Option<SpecOverride> {
+	if launcher.updates_disabled {
+		return None;
+	}
+	if launcher.readonly {
+		return peek_spec_name_override_from(&launcher.updates_dir, &RealEnv);
+	}
+	take_spec_name_override_from(&launcher.updates_dir, &RealEnv)
+}
+
+/// Stores the chain override in the given directory.
+pub fn set_spec_name_override_in(dir: &Path, spec_name: SpecOverride) {
+	let path = dir.join(SPEC_NAME_OVERRIDE_FILE);
+	let content = serde_json::to_string(&spec_name).expect("a chain override is made of strings only; qed");
+	if let Err(e) = create_dir_all(dir)
+		.and_then(|_| retry_on_interrupt(|| File::create(&path)))
+		.and_then(|mut f| f.write_all(content.as_bytes()))
+	{
+		warn!("Couldn't override chain spec: {} at {:?}", e, path);
+	}
+}
+
+/// Takes the chain override from the given directory.
+///
+/// An override starting with `env:` has its `${VAR}` references expanded; an override referring
+/// to an undefined variable is rejected. An override the node already acknowledged, but which
+/// wasn't removed because the node stopped right after, is removed instead of applied again.
+pub fn take_spec_name_override_from<E: LauncherEnv>(dir: &Path, env: &E) -> Option<SpecOverride> {
+	if clear_acked_spec_name_override_from(dir, env) {
+		trace_main!("Already switched to the chain override, not applying it again");
+		return None;
+	}
+	peek_spec_name_override_from(dir, env)
+}
+
+/// Acknowledges that the node switched to the chain override, removing it from disk.
+pub fn ack_spec_name_override(launcher: &LauncherConfig) {
+	if !launcher.can_write_state() {
+		return;
+	}
+	ack_spec_name_override_in(&launcher.updates_dir)
+}
+
+/// Acknowledges the chain override in the given directory.
+///
+/// The acknowledgement holds a copy of the override, so the override is known to be applied
+/// whenever the node stops before both files are removed. An override which can't be removed is
+/// logged, as it would apply again on the next start.
+pub fn ack_spec_name_override_in(dir: &Path) {
+	let content = match retry_on_interrupt(|| RealEnv.read_pointer(&dir.join(SPEC_NAME_OVERRIDE_FILE))) {
+		Ok(content) => content,
+		Err(_) => return,
+	};
+	let path = dir.join(SPEC_NAME_OVERRIDE_ACK_FILE);
+	if let Err(e) = retry_on_interrupt(|| File::create(&path)).and_then(|mut f| f.write_all(content.as_bytes())) {
+		warn!("Couldn't acknowledge the chain override: {} at {:?}", e, path);
+		return;
+	}
+	clear_acked_spec_name_override_from(dir, &RealEnv);
+}
+
+/// Removes the acknowledgement and the override it acknowledges, if any.
+///
+/// An acknowledgement of an earlier override is stale and only removed itself. Returns whether
+/// an acknowledged override was found, even if it couldn't be removed.
+fn clear_acked_spec_name_override_from<E: LauncherEnv>(dir: &Path, env: &E) -> bool {
+	let ack_path = dir.join(SPEC_NAME_OVERRIDE_ACK_FILE);
+	let ack = match retry_on_interrupt(|| env.read_pointer(&ack_path)) {
+		Ok(ack) => ack,
+		Err(_) => return false,
+	};
+
+	// the override goes first, so that it's never left without its acknowledgement
+	let path = dir.join(SPEC_NAME_OVERRIDE_FILE);
+	let acked = retry_on_interrupt(|| env.read_pointer(&path)).ok().map_or(false, |content| content == ack);
+	if acked {
+		if let Err(e) = retry_on_interrupt(|| env.remove(&path)) {
+			warn!("Couldn't remove chain override: {} at {:?}", e, path);
+			return true;
+		}
+	}
+	match retry_on_interrupt(|| env.remove(&ack_path)) {
+		Err(ref e) if e.kind() != io::ErrorKind::NotFound => warn!("Couldn't remove chain override acknowledgement: {} at {:?}", e, ack_path),
+		_ => {},
+	}
+	acked
+}
+
+/// Returns the chain the node should switch to, leaving it on disk.
+pub fn peek_spec_name_override(launcher: &LauncherConfig) -> Option<SpecOverride> {
+	if launcher.updates_disabled {
+		return None;
+	}
+	peek_spec_name_override_from(&launcher.updates_dir, &RealEnv)
+}
+
+fn peek_spec_name_override_from<E: LauncherEnv>(dir: &Path, env: &E) -> Option<SpecOverride> {
+	let content = retry_on_interrupt(|| env.read_pointer(&dir.join(SPEC_NAME_OVERRIDE_FILE))).ok()?;
+	let parsed = SpecOverride::parse(&content).and_then(|spec_override| {
+		let chain = expand_spec_name(&spec_override.chain, |name| env::var(name).ok())?;
+		Ok(SpecOverride { chain, ..spec_override })
+	});
+	match parsed {
+		Ok(spec_override) => Some(spec_override),
+		Err(e) => {
+			warn_main!("Ignoring chain override {}: {}", content, e);
+			None
+		},
+	}
+}
+
+/// Expands `${VAR}` references in a chain override carrying the expansion marker.
+///
+/// Overrides without the marker are returned as they are.
+fn expand_spec_name<F>(spec_name: &str, var: F) -> Result<String, String> where F: Fn(&str) -> Option<String> {
+	if !spec_name.starts_with(SPEC_NAME_EXPAND_MARKER) {
+		return Ok(spec_name.to_owned());
+	}
+
+	let mut rest = &spec_name[SPEC_NAME_EXPAND_MARKER.len()..];
+	let mut expanded = String::with_capacity(rest.len());
+	while let Some(start) = rest.find("${") {
+		expanded.push_str(&rest[..start]);
+		let end = rest[start..].find('}').ok_or_else(|| "unterminated variable reference".to_owned())?;
+		let name = &rest[start + 2..start + end];
+		expanded.push_str(&var(name).ok_or_else(|| format!("variable {} is not defined", name))?);
+		rest = &rest[start + end + 1..];
+	}
+	expanded.push_str(rest);
+
+	Ok(expanded)
+}
+
+/// Stores the arguments the node wants to keep across the upcoming restart.
+pub fn set_sticky_args(launcher: &LauncherConfig, args: &[String]) {
+	if !launcher.can_write_state() {
+		return;
+	}
+	let path = launcher.updates_path(STICKY_ARGS_FILE);
+	if let Err(e) = create_dir_all(&launcher.updates_dir)
+		.and_then(|_| File::create(&path))
+		.and_then(|mut f| f.write_all(args.join("\n").as_bytes()))
+	{
+		warn!("Couldn't keep arguments across restart: {} at {:?}", e, path);
+	}
+}
+
+/// Takes the arguments stored by the node before its restart, removing them from disk.
+pub fn take_sticky_args(launcher: &LauncherConfig) -> Vec<String> {
+	let args = pending_sticky_args(launcher);
+	if launcher.can_write_state() {
+		let _ = remove_file(launcher.updates_path(STICKY_ARGS_FILE));
+	}
+	args
+}
+
+/// Returns the arguments stored by the node before its restart, leaving them on disk.
+pub fn pending_sticky_args(launcher: &LauncherConfig) -> Vec<String> {
+	if launcher.updates_disabled {
+		return Vec::new();
+	}
+	let mut content = String::new();
+	if File::open(launcher.updates_path(STICKY_ARGS_FILE)).and_then(|mut f| f.read_to_string(&mut content)).is_err() {
+		return Vec::new();
+	}
+
+	content.lines().filter(|line| !line.is_empty()).map(Into::into).collect()
+}
+
+/// Records the chain the node actually runs, for the launcher to check overrides took effect.
+pub fn set_running_spec(launcher: &LauncherConfig, spec_name: &str) {
+	if !launcher.can_write_state() {
+		return;
+	}
+	let path = launcher.updates_path(RUNNING_SPEC_FILE);
+	if let Err(e) = File::create(&path).and_then(|mut f| f.write_all(spec_name.as_bytes())) {
+		warn!("Couldn't record the running chain: {} at {:?}", e, path);
+	}
+}
+
+/// Takes the chain the node recorded running, removing it from disk.
+pub fn take_running_spec(launcher: &LauncherConfig) -> Option<String> {
+	if !launcher.can_write_state() {
+		return None;
+	}
+	let path = launcher.updates_path(RUNNING_SPEC_FILE);
+	let mut spec_name = String::new();
+	File::open(&path).and_then(|mut f| f.read_to_string(&mut spec_name)).ok()?;
+	let _ = remove_file(&path);
+	Some(spec_name)
+}
+
+/// Whether `chain` is pinned to the current binary by a `force-direct-<chain>` marker.
+///
+/// The marker only keeps updates from being run for that chain; `--force-direct` applies to all
+/// chains regardless.
+pub fn is_direct_chain(launcher: &LauncherConfig, chain: &str) -> bool {
+	!launcher.updates_disabled && launcher.updates_path(&format!("{}{}", FORCE_DIRECT_PREFIX, chain)).exists()
+}
+
+/// Asks the launcher to switch to the staged update on the node's next restart.
+pub fn request_update_apply(dir: &Path) -> io::Result<()> {
+	create_dir_all(dir)?;
+	File::create(dir.join(APPLY_UPDATE_FILE)).map(|_| ())
+}
+
+/// Takes the request to switch to the staged update, removing it from disk.
+///
+/// A read-only launcher leaves the request on disk, so the staged update is always run.
+pub fn take_update_apply_request(launcher: &LauncherConfig) -> bool {
+	if launcher.updates_disabled {
+		return false;
+	}
+	if launcher.readonly {
+		return has_update_apply_request(launcher);
+	}
+	remove_file(launcher.updates_path(APPLY_UPDATE_FILE)).is_ok()
+}
+
+/// Whether the switch to the staged update was asked for, leaving the request on disk.
+pub fn has_update_apply_request(launcher: &LauncherConfig) -> bool {
+	!launcher.updates_disabled && launcher.updates_path(APPLY_UPDATE_FILE).exists()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::{create_dir, File};
+	use std::io::Write;
+	use std::path::Path;
+	use std::time::Duration;
+	use tempdir::TempDir;
+	use launcher::{Error, LauncherConfig};
+	use launcher::system::{LauncherEnv, RealEnv};
+	use launcher::system::test_env::{Entry, TestEnv};
+	use super::{
+		ack_spec_name_override, ack_spec_name_override_in, ensure_updates_dir, expand_spec_name, is_direct_chain,
+		latest_exe_path, latest_exe_path_in, mark_bad_update, peek_spec_name_override, peek_spec_name_override_from,
+		request_update_apply, set_running_spec, set_spec_name_override, set_spec_name_override_in, set_sticky_args,
+		take_running_spec, take_spec_name_override, take_spec_name_override_from, take_sticky_args,
+		take_update_apply_request, SpecOverride, UpdateChannel, SPEC_NAME_OVERRIDE_ACK_FILE, SPEC_NAME_OVERRIDE_FILE,
+	};
+
+	fn config(updates_dir: &Path) -> LauncherConfig {
+		LauncherConfig::new(vec!["parity".into()], None, updates_dir.to_owned()).unwrap()
+	}
+
+	fn write_latest(updates_dir: &Path, name: &str) {
+		File::create(updates_dir.join("latest")).unwrap().write_all(name.as_bytes()).unwrap();
+	}
+
+	#[test]
+	fn should_resolve_latest_binary() {
+		let tempdir = TempDir::new("").unwrap();
+		File::create(tempdir.path().join("parity-1.12.0")).unwrap();
+		write_latest(tempdir.path(), "parity-1.12.0");
+
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Ok(tempdir.path().join("parity-1.12.0")));
+	}
+
+	#[test]
+	fn should_resolve_candidate_binary_on_candidate_channel() {
+		let tempdir = TempDir::new("").unwrap();
+		File::create(tempdir.path().join("parity-1.12.0")).unwrap();
+		File::create(tempdir.path().join("parity-1.13.0-beta")).unwrap();
+		write_latest(tempdir.path(), "parity-1.12.0");
+		File::create(tempdir.path().join("candidate")).unwrap().write_all(b"parity-1.13.0-beta").unwrap();
+		let mut launcher = config(tempdir.path());
+
+		assert_eq!(launcher.update_channel, UpdateChannel::Stable);
+		assert_eq!(latest_exe_path(&launcher), Ok(tempdir.path().join("parity-1.12.0")));
+		launcher.update_channel = UpdateChannel::Candidate;
+		assert_eq!(latest_exe_path(&launcher), Ok(tempdir.path().join("parity-1.13.0-beta")));
+	}
+
+	#[test]
+	fn should_not_fall_back_to_latest_on_candidate_channel() {
+		let tempdir = TempDir::new("").unwrap();
+		File::create(tempdir.path().join("parity-1.12.0")).unwrap();
+		write_latest(tempdir.path(), "parity-1.12.0");
+		let mut launcher = config(tempdir.path());
+		launcher.update_channel = UpdateChannel::Candidate;
+
+		assert_eq!(latest_exe_path_in(&launcher, &RealEnv), Err(Error::NotConfigured));
+	}
+
+	#[test]
+	fn should_not_find_binary_without_latest_pointer() {
+		let tempdir = TempDir::new("").unwrap();
+
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Err(Error::NotConfigured));
+	}
+
+	#[test]
+	fn should_not_find_missing_binary() {
+		let tempdir = TempDir::new("").unwrap();
+		write_latest(tempdir.path(), "parity-1.12.0");
+
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Err(Error::BinaryNotFound));
+	}
+
+	#[test]
+	fn should_reject_latest_pointing_to_a_directory() {
+		let tempdir = TempDir::new("").unwrap();
+		create_dir(tempdir.path().join("parity-1.12.0")).unwrap();
+		write_latest(tempdir.path(), "parity-1.12.0");
+
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Err(Error::BinaryNotFound));
+	}
+
+	#[test]
+	fn should_reject_latest_pointer_which_is_a_directory() {
+		let tempdir = TempDir::new("").unwrap();
+		create_dir(tempdir.path().join("latest")).unwrap();
+
+		let expected = tempdir.path().join("latest").display().to_string();
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Err(Error::LatestFileUnreadable(expected)));
+	}
+
+	#[test]
+	fn should_not_retry_latest_pointer_which_is_a_directory() {
+		let launcher = config(Path::new("/updates"));
+		let env = TestEnv::default();
+		env.add("/updates", Entry::Dir, 0);
+		env.add("/updates/latest", Entry::Dir, 0);
+
+		assert_eq!(
+			latest_exe_path_in(&launcher, &env),
+			Err(Error::LatestFileUnreadable("/updates/latest".into()))
+		);
+		assert!(env.slept.borrow().is_empty());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn should_check_symlinked_binary_through_its_target() {
+		use std::os::unix::fs::symlink;
+
+		let tempdir = TempDir::new("").unwrap();
+		File::create(tempdir.path().join("parity-1.12.0")).unwrap();
+		symlink("parity-1.12.0", tempdir.path().join("parity-stable")).unwrap();
+		write_latest(tempdir.path(), "parity-stable");
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Ok(tempdir.path().join("parity-stable")));
+
+		symlink("parity-loop", tempdir.path().join("parity-loop")).unwrap();
+		write_latest(tempdir.path(), "parity-loop");
+		assert_eq!(latest_exe_path(&config(tempdir.path())), Err(Error::BinaryNotFound));
+	}
+
+	#[test]
+	fn should_retry_reading_late_latest_pointer() {
+		let launcher = config(Path::new("/updates"));
+		let env = TestEnv::default();
+		env.add("/updates", Entry::Dir, 0);
+		env.add("/updates/parity-1.12.0", Entry::File("binary".into()), 0);
+		env.add("/updates/latest", Entry::File("parity-1.12.0".into()), 2);
+
+		assert_eq!(latest_exe_path_in(&launcher, &env), Ok("/updates/parity-1.12.0".into()));
+		assert_eq!(env.slept.borrow().len(), 2);
+		assert!(env.slept.borrow().iter().all(|d| *d >= Duration::from_millis(20) && *d <= Duration::from_millis(40)));
+	}
+
+	#[test]
+	fn should_give_up_on_missing_latest_pointer() {
+		let launcher = config(Path::new("/updates"));
+		let env = TestEnv::default();
+		env.add("/updates", Entry::Dir, 0);
+		env.add("/updates/latest", Entry::File("parity-1.12.0".into()), 10);
+
+		assert_eq!(latest_exe_path_in(&launcher, &env), Err(Error::NotConfigured));
+		assert_eq!(env.slept.borrow().len(), 3);
+	}
+
+	#[test]
+	fn should_not_retry_without_updates_dir() {
+		let launcher = config(Path::new("/updates"));
+		let env = TestEnv::default();
+
+		assert_eq!(latest_exe_path_in(&launcher, &env), Err(Error::NotConfigured));
+		assert!(env.slept.borrow().is_empty());
+	}
+
+	#[test]
+	fn should_report_unknown_updates_dir() {
+		let mut launcher = config(Path::new("$HOME/.parity-hypervisor"));
+		launcher.updates_dir_error = Some("no home directory".into());
+		launcher.disable_updates();
+
+		assert_eq!(latest_exe_path(&launcher), Err(Error::NoUpdatesDir("no home directory".into())));
+	}
+
+	#[test]
+	fn should_refuse_latest_pointing_outside_updates_dir() {
+		let launcher = config(Path::new("/updates"));
+		let env = TestEnv::default();
+		env.add("/updates", Entry::Dir, 0);
+		env.add("/bin/sh", Entry::File("binary".into()), 0);
+		env.add("/updates/parity-1.12.0", Entry::File("binary".into()), 0);
+
+		for name in &["../bin/sh", "/bin/sh", "sub/../parity-1.12.0", "./parity-1.12.0", "..", "sub\\parity-1.12.0"] {
+			env.add("/updates/latest", Entry::File(name.to_string()), 0);
+			assert_eq!(latest_exe_path_in(&launcher, &env), Err(Error::UnsafeUpdatePath(name.to_string())), "{:?}", name);
+		}
+
+		env.add("/updates/latest", Entry::File("parity-1.12.0".into()), 0);
+		assert_eq!(latest_exe_path_in(&launcher, &env), Ok("/updates/parity-1.12.0".into()));
+	}
+
+	#[test]
+	fn should_skip_update_marked_bad() {
+		let launcher = config(Path::new("/updates"));
+		let env = TestEnv::default();
+		env.add("/updates", Entry::Dir, 0);
+		env.add("/updates/parity-1.12.0", Entry::File("binary".into()), 0);
+		env.add("/updates/latest", Entry::File("parity-1.12.0".into()), 0);
+		env.add("/updates/bad_update", Entry::File("parity-1.11.0".into()), 0);
+		assert_eq!(latest_exe_path_in(&launcher, &env), Ok("/updates/parity-1.12.0".into()));
+
+		env.add("/updates/bad_update", Entry::File("parity-1.12.0".into()), 0);
+		assert_eq!(latest_exe_path_in(&launcher, &env), Err(Error::MarkedBad));
+	}
+
+	#[test]
+	fn should_mark_bad_update_by_name() {
+		let tempdir = TempDir::new("").unwrap();
+		create_dir(tempdir.path().join("parity-1.12.0")).unwrap();
+		let launcher = config(tempdir.path());
+		write_latest(tempdir.path(), "parity-1.12.0");
+
+		mark_bad_update(&launcher, &tempdir.path().join("parity-1.12.0"));
+		assert_eq!(latest_exe_path(&launcher), Err(Error::MarkedBad));
+	}
+
+	#[test]
+	fn should_take_sticky_args_once() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = config(tempdir.path());
+		let args = vec!["--port=30304".to_owned(), "--chain=kovan".to_owned()];
+
+		set_sticky_args(&launcher, &args);
+		assert_eq!(take_sticky_args(&launcher), args);
+		assert!(take_sticky_args(&launcher).is_empty());
+	}
+
+	#[test]
+	fn should_keep_spec_name_override_until_acknowledged() {
+		let tempdir = TempDir::new("").unwrap();
+
+		assert_eq!(take_spec_name_override_from(tempdir.path(), &RealEnv), None);
+
+		set_spec_name_override_in(tempdir.path(), "kovan".into());
+		assert!(tempdir.path().join(SPEC_NAME_OVERRIDE_FILE).exists());
+
+		// the node stopping before it switched chains leaves the override for the next start
+		assert_eq!(take_spec_name_override_from(tempdir.path(), &RealEnv), Some("kovan".into()));
+		assert!(tempdir.path().join(SPEC_NAME_OVERRIDE_FILE).exists());
+		assert_eq!(take_spec_name_override_from(tempdir.path(), &RealEnv), Some("kovan".into()));
+
+		ack_spec_name_override_in(tempdir.path());
+		assert!(!tempdir.path().join(SPEC_NAME_OVERRIDE_FILE).exists());
+		assert!(!tempdir.path().join(SPEC_NAME_OVERRIDE_ACK_FILE).exists());
+		assert_eq!(take_spec_name_override_from(tempdir.path(), &RealEnv), None);
+	}
+
+	#[test]
+	fn should_round_trip_spec_override_with_extra_args() {
+		let tempdir = TempDir::new("").unwrap();
+		let spec_override = SpecOverride { chain: "kovan".into(), extra_args: vec!["--port=30304".into(), "--jsonrpc-port=8546".into()] };
+
+		set_spec_name_override_in(tempdir.path(), spec_override.clone());
+		assert_eq!(take_spec_name_override_from(tempdir.path(), &RealEnv), Some(spec_override));
+	}
+
+	#[test]
+	fn should_read_bare_chain_name_override() {
+		let env = TestEnv::default();
+		let dir = Path::new("/updates");
+		env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File("kovan".into()), 0);
+
+		assert_eq!(peek_spec_name_override_from(dir, &env), Some(SpecOverride { chain: "kovan".into(), extra_args: vec![] }));
+	}
+
+	#[test]
+	fn should_reject_malformed_spec_override() {
+		let env = TestEnv::default();
+		let dir = Path::new("/updates");
+		for content in &["{\"extra_args\": []}", "{\"chain\": \"kovan\", \"extra_args\": [30304]}", "{\"chain\": \"kovan\""] {
+			env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File(content.to_string()), 0);
+			assert_eq!(peek_spec_name_override_from(dir, &env), None, "{}", content);
+		}
+
+		env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File("{\"chain\": \"kovan\"}".into()), 0);
+		assert_eq!(peek_spec_name_override_from(dir, &env), Some("kovan".into()));
+	}
+
+	#[test]
+	fn should_take_spec_name_override_from_env() {
+		let env = TestEnv::default();
+		let dir = Path::new("/updates");
+		env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File("kovan".into()), 0);
+
+		assert_eq!(take_spec_name_override_from(dir, &env), Some("kovan".into()));
+		assert_eq!(take_spec_name_override_from(dir, &env), Some("kovan".into()));
+	}
+
+	#[test]
+	fn should_drop_override_acknowledged_before_stopping() {
+		let env = TestEnv::default();
+		let dir = Path::new("/updates");
+		env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File("kovan".into()), 0);
+		env.add(dir.join(SPEC_NAME_OVERRIDE_ACK_FILE), Entry::File("kovan".into()), 0);
+
+		assert_eq!(take_spec_name_override_from(dir, &env), None);
+		assert!(!env.exists(&dir.join(SPEC_NAME_OVERRIDE_FILE)));
+		assert!(!env.exists(&dir.join(SPEC_NAME_OVERRIDE_ACK_FILE)));
+	}
+
+	#[test]
+	fn should_apply_override_newer_than_acknowledgement() {
+		let env = TestEnv::default();
+		let dir = Path::new("/updates");
+		env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File("ropsten".into()), 0);
+		env.add(dir.join(SPEC_NAME_OVERRIDE_ACK_FILE), Entry::File("kovan".into()), 0);
+
+		assert_eq!(take_spec_name_override_from(dir, &env), Some("ropsten".into()));
+		assert!(!env.exists(&dir.join(SPEC_NAME_OVERRIDE_ACK_FILE)));
+	}
+
+	#[test]
+	fn should_retry_interrupted_spec_name_override_io() {
+		let env = TestEnv::default();
+		let dir = Path::new("/updates");
+		env.add(dir.join(SPEC_NAME_OVERRIDE_FILE), Entry::File("kovan".into()), 0);
+
+		env.interrupt(1);
+		assert_eq!(peek_spec_name_override_from(dir, &env), Some("kovan".into()));
+
+		// reading the acknowledgement is interrupted once
+		env.add(dir.join(SPEC_NAME_OVERRIDE_ACK_FILE), Entry::File("kovan".into()), 0);
+		env.interrupt(1);
+		assert_eq!(take_spec_name_override_from(dir, &env), None);
+		assert!(!env.exists(&dir.join(SPEC_NAME_OVERRIDE_FILE)));
+	}
+
+	#[test]
+	fn should_create_missing_directory_for_spec_name_override() {
+		let tempdir = TempDir::new("").unwrap();
+		let dir = tempdir.path().join("updates");
+
+		set_spec_name_override_in(&dir, "kovan".into());
+		assert_eq!(take_spec_name_override_from(&dir, &RealEnv), Some("kovan".into()));
+	}
+
+	#[test]
+	fn should_expand_defined_variables_in_spec_name_override() {
+		let var = |name: &str| if name == "PARITY_CHAIN" { Some("kovan".to_owned()) } else { None };
+
+		assert_eq!(expand_spec_name("env:${PARITY_CHAIN}", &var), Ok("kovan".into()));
+		assert_eq!(expand_spec_name("env:/chains/${PARITY_CHAIN}.json", &var), Ok("/chains/kovan.json".into()));
+	}
+
+	#[test]
+	fn should_reject_undefined_variables_in_spec_name_override() {
+		let var = |_: &str| None;
+
+		assert!(expand_spec_name("env:${PARITY_CHAIN}", &var).is_err());
+		assert!(expand_spec_name("env:${PARITY_CHAIN", &var).is_err());
+	}
+
+	#[test]
+	fn should_not_expand_spec_name_override_without_marker() {
+		let var = |_: &str| Some("kovan".to_owned());
+
+		assert_eq!(expand_spec_name("${PARITY_CHAIN}", &var), Ok("${PARITY_CHAIN}".into()));
+		assert_eq!(expand_spec_name("foundation", &var), Ok("foundation".into()));
+	}
+
+	#[test]
+	fn should_create_usable_updates_dir() {
+		let tempdir = TempDir::new("").unwrap();
+		let dir = tempdir.path().join("updates");
+
+		ensure_updates_dir(&dir).unwrap();
+		assert!(dir.is_dir());
+		assert_eq!(dir.read_dir().unwrap().count(), 0);
+	}
+
+	#[test]
+	fn should_reject_unusable_updates_dir() {
+		let tempdir = TempDir::new("").unwrap();
+		let file = tempdir.path().join("updates");
+		File::create(&file).unwrap();
+
+		assert!(ensure_updates_dir(&file).is_err());
+	}
+
+	#[test]
+	fn should_ignore_launcher_state_with_updates_disabled() {
+		let tempdir = TempDir::new("").unwrap();
+		File::create(tempdir.path().join("parity-1.12.0")).unwrap();
+		write_latest(tempdir.path(), "parity-1.12.0");
+		set_spec_name_override_in(tempdir.path(), "kovan".into());
+		let mut launcher = config(tempdir.path());
+		launcher.disable_updates();
+
+		assert_eq!(latest_exe_path(&launcher), Err(Error::NotConfigured));
+		assert_eq!(take_spec_name_override(&launcher), None);
+		set_spec_name_override(&launcher, "ropsten".into());
+		assert_eq!(take_spec_name_override_from(tempdir.path(), &RealEnv), Some("kovan".into()));
+	}
+
+	#[test]
+	fn should_take_update_apply_request_once() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = config(tempdir.path());
+		assert!(!take_update_apply_request(&launcher));
+
+		request_update_apply(tempdir.path()).unwrap();
+		assert!(take_update_apply_request(&launcher));
+		assert!(!take_update_apply_request(&launcher));
+	}
+
+	#[test]
+	fn should_pin_chain_with_marker() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = config(tempdir.path());
+		assert!(!is_direct_chain(&launcher, "kovan"));
+
+		File::create(tempdir.path().join("force-direct-kovan")).unwrap();
+		assert!(is_direct_chain(&launcher, "kovan"));
+		assert!(!is_direct_chain(&launcher, "foundation"));
+	}
+
+	#[test]
+	fn should_peek_spec_name_override_without_taking_it() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = config(tempdir.path());
+		set_spec_name_override(&launcher, "kovan".into());
+
+		assert_eq!(peek_spec_name_override(&launcher), Some("kovan".into()));
+		assert_eq!(take_spec_name_override(&launcher), Some("kovan".into()));
+		ack_spec_name_override(&launcher);
+		assert_eq!(peek_spec_name_override(&launcher), None);
+	}
+
+	#[test]
+	fn should_leave_state_alone_when_readonly() {
+		let tempdir = TempDir::new("").unwrap();
+		set_spec_name_override_in(tempdir.path(), "kovan".into());
+		File::create(tempdir.path().join("apply_update")).unwrap();
+		let mut launcher = config(tempdir.path());
+		launcher.readonly = true;
+
+		assert_eq!(take_spec_name_override(&launcher), Some("kovan".into()));
+		assert_eq!(take_spec_name_override(&launcher), Some("kovan".into()));
+		assert!(take_update_apply_request(&launcher));
+		assert!(take_update_apply_request(&launcher));
+
+		set_sticky_args(&launcher, &["--jsonrpc-port=8546".into()]);
+		set_running_spec(&launcher, "kovan");
+		ack_spec_name_override(&launcher);
+		mark_bad_update(&launcher, &tempdir.path().join("parity-1.12.0"));
+		assert_eq!(tempdir.path().read_dir().unwrap().count(), 2);
+	}
+
+	#[test]
+	fn should_take_running_spec_once() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = config(tempdir.path());
+		assert_eq!(take_running_spec(&launcher), None);
+
+		set_running_spec(&launcher, "kovan");
+		assert_eq!(take_running_spec(&launcher), Some("kovan".into()));
+		assert_eq!(take_running_spec(&launcher), None);
+	}
+}
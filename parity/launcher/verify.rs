@@ -0,0 +1,259 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+//! Read-only integrity check of the updates directory.
+
+use std::env::consts::{ARCH, OS};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use super::{is_allowed_version, latest_exe_path, staged_version, LauncherConfig};
+use super::decision::is_expected_channel;
+use super::version::staged_channel;
+
+/// ELF `e_machine` values of the architectures binaries are released for.
+const ELF_MACHINES: &[(&str, u16)] = &[("x86", 3), ("arm", 40), ("x86_64", 62), ("aarch64", 183)];
+
+/// Checks the installed update, reporting each check on stdout. Returns the exit code.
+pub fn verify(launcher: &LauncherConfig) -> i32 {
+	report(&run_checks(launcher), &format!("Updates directory {}", launcher.updates_dir.display()))
+}
+
+/// Checks the update bundle in `dir` as if it were installed, reporting each check on stdout.
+/// Returns the exit code.
+///
+/// The bundle goes through the same checks as an installed update, with the settings of
+/// `launcher` such as `--max-update-version`. Nothing is run or changed.
+pub fn verify_bundle(launcher: &LauncherConfig, dir: &Path) -> i32 {
+	report(&run_checks(&bundle_config(launcher, dir)), &format!("Update bundle {}", dir.display()))
+}
+
+fn bundle_config(launcher: &LauncherConfig, dir: &Path) -> LauncherConfig {
+	let mut bundle = launcher.clone();
+	bundle.updates_dir = dir.to_owned();
+	bundle.updates_dir_error = None;
+	bundle.use_binary = None;
+	bundle
+}
+
+fn report(checks: &[(&'static str, Result<(), String>)], subject: &str) -> i32 {
+	for &(name, ref result) in checks {
+		match *result {
+			Ok(()) => println!("{}: ok", name),
+			Err(ref e) => println!("{}: FAILED ({})", name, e),
+		}
+	}
+
+	let failed = checks.iter().filter(|&&(_, ref result)| result.is_err()).count();
+	if failed == 0 {
+		println!("{} verified", subject);
+		0
+	} else {
+		println!("{} failed {} of {} checks", subject, failed, checks.len());
+		1
+	}
+}
+
+/// Runs the checks in order, stopping at the first one the others depend on.
+pub fn run_checks(launcher: &LauncherConfig) -> Vec<(&'static str, Result<(), String>)> {
+	let mut checks = Vec::new();
+	let exe = match latest_exe_path(launcher) {
+		Ok(exe) => {
+			checks.push(("latest pointer resolved", Ok(())));
+			exe
+		},
+		Err(e) => {
+			checks.push(("latest pointer resolved", Err(e.to_string())));
+			return checks;
+		},
+	};
+
+	checks.push(("binary executable", check_executable(&exe)));
+	checks.push(("binary built for this platform", check_platform(&exe)));
+
+	let staged = staged_version(launcher, &exe);
+	checks.push(("version allowed", if is_allowed_version(staged.as_ref(), launcher.max_update_version.as_ref()) {
+		Ok(())
+	} else {
+		Err(format!("{} is above --max-update-version", staged.map_or("unknown version".to_owned(), |v| v.to_string())))
+	}));
+
+	checks.push(("channel expected", match (staged_channel(launcher), launcher.expected_channel.as_ref()) {
+		(Some(channel), Some(expected)) if !is_expected_channel(Some(&channel), Some(expected)) => {
+			Err(format!("built for channel {}, not {}", channel, expected))
+		},
+		_ => Ok(()),
+	}));
+
+	checks
+}
+
+/// Checks `exe` can be executed, explaining why not otherwise.
+#[cfg(unix)]
+pub fn check_executable(exe: &Path) -> Result<(), String> {
+	use std::os::unix::fs::PermissionsExt;
+
+	let mode = exe.metadata().map_err(|e| e.to_string())?.permissions().mode();
+	if mode & 0o111 != 0 { Ok(()) } else { Err(format!("mode is {:o}", mode & 0o777)) }
+}
+
+/// Checks `exe` can be executed, explaining why not otherwise.
+#[cfg(not(unix))]
+pub fn check_executable(exe: &Path) -> Result<(), String> {
+	match exe.extension() {
+		Some(ext) if ext == "exe" => Ok(()),
+		_ => Err("not an .exe".into()),
+	}
+}
+
+fn check_platform(exe: &Path) -> Result<(), String> {
+	let mut header = [0u8; 20];
+	File::open(exe).and_then(|mut f| f.read_exact(&mut header)).map_err(|e| format!("couldn't read the header: {}", e))?;
+	platform_error(&header, OS, ARCH).map_or(Ok(()), Err)
+}
+
+/// Why a binary starting with `header` can't run on `os`/`arch`, if it can't.
+fn platform_error(header: &[u8; 20], os: &str, arch: &str) -> Option<String> {
+	match os {
+		"windows" if &header[..2] != b"MZ" => Some("not a Windows executable".into()),
+		"macos" if header[..4] != [0xcf, 0xfa, 0xed, 0xfe] => Some("not a 64-bit Mach-O executable".into()),
+		"windows" | "macos" => None,
+		_ if &header[..4] != b"\x7fELF" => Some("not an ELF executable".into()),
+		_ => {
+			let machine = u16::from(header[18]) | u16::from(header[19]) << 8;
+			match ELF_MACHINES.iter().find(|&&(name, _)| name == arch) {
+				Some(&(_, expected)) if machine != expected => Some(format!("built for ELF machine {}, not {}", machine, arch)),
+				_ => None,
+			}
+		},
+	}
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use std::env;
+	use std::fs::{self, File};
+	use std::io::Write;
+	use std::os::unix::fs::PermissionsExt;
+	use std::path::Path;
+	use tempdir::TempDir;
+	use launcher::LauncherConfig;
+	use launcher::updates::LATEST_FILE;
+	use launcher::version::LATEST_CHANNEL_FILE;
+	use super::{bundle_config, platform_error, run_checks};
+
+	fn config(dir: &Path, args: &[&str]) -> LauncherConfig {
+		let args = Some("parity").into_iter().chain(args.iter().cloned()).map(String::from).collect();
+		LauncherConfig::new(args, None, dir.to_owned()).unwrap()
+	}
+
+	/// Installs the test binary itself as update `name`, which is executable and built for this platform.
+	fn install(dir: &Path, name: &str) {
+		fs::copy(env::current_exe().unwrap(), dir.join(name)).unwrap();
+		File::create(dir.join(LATEST_FILE)).unwrap().write_all(name.as_bytes()).unwrap();
+	}
+
+	fn failed(launcher: &LauncherConfig) -> Vec<&'static str> {
+		run_checks(launcher).into_iter().filter(|&(_, ref result)| result.is_err()).map(|(name, _)| name).collect()
+	}
+
+	#[test]
+	fn should_pass_valid_update() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), "parity-1.12.0");
+		let launcher = config(tempdir.path(), &[]);
+		assert_eq!(run_checks(&launcher).len(), 5);
+		assert!(failed(&launcher).is_empty());
+	}
+
+	#[test]
+	fn should_fail_missing_pointer() {
+		let tempdir = TempDir::new("").unwrap();
+		assert_eq!(failed(&config(tempdir.path(), &[])), ["latest pointer resolved"]);
+	}
+
+	#[test]
+	fn should_fail_non_executable_binary() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), "parity-1.12.0");
+		fs::set_permissions(tempdir.path().join("parity-1.12.0"), fs::Permissions::from_mode(0o644)).unwrap();
+		assert_eq!(failed(&config(tempdir.path(), &[])), ["binary executable"]);
+	}
+
+	#[test]
+	fn should_fail_foreign_binary() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("parity-1.12.0");
+		File::create(&path).unwrap().write_all(b"#!/bin/sh\nexit 0\n# padding the header\n").unwrap();
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+		File::create(tempdir.path().join(LATEST_FILE)).unwrap().write_all(b"parity-1.12.0").unwrap();
+		assert_eq!(failed(&config(tempdir.path(), &[])), ["binary built for this platform"]);
+	}
+
+	#[test]
+	fn should_fail_version_above_ceiling() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), "parity-2.0.0");
+		assert_eq!(failed(&config(tempdir.path(), &["--max-update-version", "1.12.99"])), ["version allowed"]);
+	}
+
+	#[test]
+	fn should_fail_binary_of_other_channel() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), "parity-1.12.0");
+		let launcher = config(tempdir.path(), &["--expected-channel", "stable"]);
+		assert!(failed(&launcher).is_empty());
+
+		File::create(tempdir.path().join(LATEST_CHANNEL_FILE)).unwrap().write_all(b"stable\n").unwrap();
+		assert!(failed(&launcher).is_empty());
+		File::create(tempdir.path().join(LATEST_CHANNEL_FILE)).unwrap().write_all(b"beta\n").unwrap();
+		assert_eq!(failed(&launcher), ["channel expected"]);
+		assert!(failed(&config(tempdir.path(), &[])).is_empty());
+	}
+
+	#[test]
+	fn should_check_elf_machine() {
+		let mut header = [0u8; 20];
+		header[..4].copy_from_slice(b"\x7fELF");
+		header[18] = 62;
+		assert_eq!(platform_error(&header, "linux", "x86_64"), None);
+		assert!(platform_error(&header, "linux", "aarch64").is_some());
+		assert!(platform_error(&header, "windows", "x86_64").is_some());
+		assert_eq!(platform_error(&header, "linux", "mips"), None);
+	}
+
+	#[test]
+	fn should_check_bundle_instead_of_updates_dir() {
+		let updates = TempDir::new("").unwrap();
+		install(updates.path(), "parity-1.12.0");
+		let bundle = TempDir::new("").unwrap();
+		let launcher = config(updates.path(), &["--max-update-version", "1.12.99"]);
+		assert_eq!(failed(&bundle_config(&launcher, bundle.path())), ["latest pointer resolved"]);
+
+		install(bundle.path(), "parity-2.0.0");
+		assert_eq!(failed(&bundle_config(&launcher, bundle.path())), ["version allowed"]);
+		assert_eq!(updates.path().read_dir().unwrap().count(), 2);
+	}
+
+	#[test]
+	fn should_not_touch_launcher_state() {
+		let tempdir = TempDir::new("").unwrap();
+		install(tempdir.path(), "parity-1.12.0");
+		File::create(tempdir.path().join("spec_name_override")).unwrap();
+		run_checks(&config(tempdir.path(), &[]));
+		assert!(tempdir.path().join("spec_name_override").exists());
+	}
+}
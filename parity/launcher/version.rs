@@ -0,0 +1,129 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Versions of installed updates.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use semver::Version;
+use super::LauncherConfig;
+use super::slots::uses_slots;
+use super::updates::UpdateChannel;
+
+/// File holding the version of the binary `latest` points to, `candidate.version` on the candidate channel.
+pub const LATEST_VERSION_FILE: &str = "latest.version";
+
+/// File holding the release channel the binary `latest` points to was built for, e.g. `stable`,
+/// `candidate.channel` on the candidate channel.
+pub const LATEST_CHANNEL_FILE: &str = "latest.channel";
+
+/// Prefix of the binaries installed by the updater, followed by `X.Y.Z-<hash>`.
+pub const BINARY_PREFIX: &str = "parity-";
+
+/// Returns the version of the running executable.
+pub fn current_version() -> Option<Version> {
+	Version::parse(env!("CARGO_PKG_VERSION")).ok()
+}
+
+/// Returns the version of the installed update `exe`.
+///
+/// The version is read from the `latest.version` file of the update channel, falling back to
+/// parsing the binary name. With update slots in use, `latest.version` describes the binary the
+/// updater staged last rather than the slot being run, so only the binary name is used.
+pub fn staged_version(launcher: &LauncherConfig, exe: &Path) -> Option<Version> {
+	let from_name = || exe.file_name().and_then(|name| name.to_str()).and_then(version_from_binary_name);
+	if launcher.update_channel == UpdateChannel::Stable && uses_slots(&launcher.updates_dir) {
+		return from_name();
+	}
+
+	let mut content = String::new();
+	match File::open(launcher.channel_path(LATEST_VERSION_FILE)).and_then(|mut f| f.read_to_string(&mut content)) {
+		Ok(_) => Version::parse(content.trim()).ok(),
+		Err(_) => from_name(),
+	}
+}
+
+/// Returns the release channel the installed update was built for, if the updater recorded it.
+pub fn staged_channel(launcher: &LauncherConfig) -> Option<String> {
+	let mut content = String::new();
+	File::open(launcher.channel_path(LATEST_CHANNEL_FILE)).and_then(|mut f| f.read_to_string(&mut content)).ok()?;
+	let channel = content.trim();
+	if channel.is_empty() { None } else { Some(channel.to_owned()) }
+}
+
+/// Parses the version out of a binary name given by the updater, e.g. `parity-1.12.0-4f2b1a3c`.
+pub fn version_from_binary_name(name: &str) -> Option<Version> {
+	if !name.starts_with(BINARY_PREFIX) {
+		return None;
+	}
+
+	let version = &name[BINARY_PREFIX.len()..];
+	let version = version.find('-').map_or(version, |pos| &version[..pos]);
+	Version::parse(version).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+	use std::path::Path;
+	use semver::Version;
+	use tempdir::TempDir;
+	use launcher::LauncherConfig;
+	use launcher::slots::Slot;
+	use super::{staged_channel, staged_version, version_from_binary_name, LATEST_CHANNEL_FILE, LATEST_VERSION_FILE};
+
+	#[test]
+	fn should_parse_version_from_binary_name() {
+		assert_eq!(version_from_binary_name("parity-1.12.0-4f2b1a3c"), Some(Version::new(1, 12, 0)));
+		assert_eq!(version_from_binary_name("parity-2.0.1"), Some(Version::new(2, 0, 1)));
+		assert_eq!(version_from_binary_name("parity"), None);
+		assert_eq!(version_from_binary_name("geth-1.8.0"), None);
+	}
+
+	#[test]
+	fn should_prefer_latest_version_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = LauncherConfig::new(vec!["parity".into()], None, tempdir.path().to_owned()).unwrap();
+		let exe = Path::new("parity-1.12.0-4f2b1a3c");
+		assert_eq!(staged_version(&launcher, exe), Some(Version::new(1, 12, 0)));
+
+		File::create(tempdir.path().join(LATEST_VERSION_FILE)).unwrap().write_all(b"2.0.0\n").unwrap();
+		assert_eq!(staged_version(&launcher, exe), Some(Version::new(2, 0, 0)));
+	}
+
+	#[test]
+	fn should_read_slot_version_from_binary_name() {
+		let tempdir = TempDir::new("").unwrap();
+		File::create(tempdir.path().join(LATEST_VERSION_FILE)).unwrap().write_all(b"2.0.0\n").unwrap();
+		File::create(tempdir.path().join(Slot::A.pointer_file())).unwrap().write_all(b"parity-1.12.0-4f2b1a3c").unwrap();
+		let launcher = LauncherConfig::new(vec!["parity".into()], None, tempdir.path().to_owned()).unwrap();
+		assert_eq!(staged_version(&launcher, Path::new("parity-1.12.0-4f2b1a3c")), Some(Version::new(1, 12, 0)));
+	}
+
+	#[test]
+	fn should_read_staged_channel_if_recorded() {
+		let tempdir = TempDir::new("").unwrap();
+		let launcher = LauncherConfig::new(vec!["parity".into()], None, tempdir.path().to_owned()).unwrap();
+		assert_eq!(staged_channel(&launcher), None);
+
+		File::create(tempdir.path().join(LATEST_CHANNEL_FILE)).unwrap().write_all(b"  \n").unwrap();
+		assert_eq!(staged_channel(&launcher), None);
+		File::create(tempdir.path().join(LATEST_CHANNEL_FILE)).unwrap().write_all(b"beta\n").unwrap();
+		assert_eq!(staged_channel(&launcher), Some("beta".into()));
+	}
+}
@@ -0,0 +1,170 @@
+// Copyright 2015-2018 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Watchdog restarting an unresponsive node.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use parity::LivenessProbe;
+use parking_lot::{Condvar, Mutex};
+
+/// Something that can be asked whether it still responds.
+pub trait Probe: Send + Sync + 'static {
+	/// Returns whether the probed service responded.
+	fn is_responsive(&self) -> bool;
+}
+
+impl Probe for LivenessProbe {
+	fn is_responsive(&self) -> bool {
+		LivenessProbe::is_responsive(self)
+	}
+}
+
+/// Background thread probing the node and reporting when it stops responding.
+pub struct Watchdog {
+	stop: Arc<(Mutex<bool>, Condvar)>,
+	thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Watchdog {
+	/// Starts probing every `interval`.
+	///
+	/// `on_unresponsive` is called once, after `max_misses` consecutive probes failed or didn't
+	/// answer within `interval`.
+	pub fn start<P, F>(probe: P, interval: Duration, max_misses: u32, on_unresponsive: F) -> Self where
+		P: Probe,
+		F: FnOnce() + Send + 'static,
+	{
+		let stop = Arc::new((Mutex::new(false), Condvar::new()));
+		let thread_stop = stop.clone();
+		let probe = Arc::new(probe);
+
+		let thread = thread::Builder::new()
+			.name("watchdog".into())
+			.spawn(move || {
+				let pending = Arc::new(AtomicBool::new(false));
+				let mut misses = 0;
+				loop {
+					{
+						let mut stopped = thread_stop.0.lock();
+						if !*stopped {
+							let _ = thread_stop.1.wait_for(&mut stopped, interval);
+						}
+						if *stopped {
+							return;
+						}
+					}
+
+					if probe_within(&probe, &pending, interval) {
+						misses = 0;
+						continue;
+					}
+
+					misses += 1;
+					trace_main!("Watchdog: node didn't respond ({}/{})", misses, max_misses);
+					if misses >= max_misses {
+						warn_main!("Node unresponsive for {} consecutive checks, restarting.", misses);
+						on_unresponsive();
+						return;
+					}
+				}
+			})
+			.expect("Error creating watchdog thread");
+
+		Watchdog {
+			stop,
+			thread: Some(thread),
+		}
+	}
+
+	/// Stops probing and waits for the watchdog thread to finish.
+	pub fn stop(mut self) {
+		self.stop_thread();
+	}
+
+	fn stop_thread(&mut self) {
+		*self.stop.0.lock() = true;
+		self.stop.1.notify_all();
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+impl Drop for Watchdog {
+	fn drop(&mut self) {
+		self.stop_thread();
+	}
+}
+
+/// Probes on a helper thread, so a hung node counts as a miss instead of stalling the watchdog.
+///
+/// While a previous probe is still hanging no new one is started.
+fn probe_within<P: Probe>(probe: &Arc<P>, pending: &Arc<AtomicBool>, timeout: Duration) -> bool {
+	if pending.swap(true, Ordering::SeqCst) {
+		return false;
+	}
+
+	let (tx, rx) = mpsc::channel();
+	let probe = probe.clone();
+	let done = pending.clone();
+	thread::spawn(move || {
+		let _ = tx.send(probe.is_responsive());
+		done.store(false, Ordering::SeqCst);
+	});
+
+	rx.recv_timeout(timeout).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::mpsc;
+	use std::time::Duration;
+	use super::{Probe, Watchdog};
+
+	struct MockProbe(Arc<AtomicBool>);
+
+	impl Probe for MockProbe {
+		fn is_responsive(&self) -> bool {
+			self.0.load(Ordering::SeqCst)
+		}
+	}
+
+	#[test]
+	fn should_report_node_becoming_unresponsive() {
+		let responsive = Arc::new(AtomicBool::new(true));
+		let (tx, rx) = mpsc::channel();
+		let _watchdog = Watchdog::start(MockProbe(responsive.clone()), Duration::from_millis(10), 3, move || tx.send(()).unwrap());
+
+		assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+		responsive.store(false, Ordering::SeqCst);
+		assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+	}
+
+	#[test]
+	fn should_not_report_after_stop() {
+		let (tx, rx) = mpsc::channel::<()>();
+		let watchdog = Watchdog::start(MockProbe(Arc::new(AtomicBool::new(false))), Duration::from_secs(60), 1, move || tx.send(()).unwrap());
+
+		watchdog.stop();
+		assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+	}
+}
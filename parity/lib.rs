@@ -129,7 +129,8 @@ use deprecated::find_deprecated;
 use ethcore_logger::{Config as LogConfig, setup_log};
 
 pub use self::configuration::Configuration;
-pub use self::run::RunningClient;
+pub use self::run::{inherit_listeners, LivenessProbe, RunningClient};
+pub use ethcore_logger::LogReloader;
 
 fn print_hash_of(maybe_file: Option<String>) -> Result<String, String> {
 	if let Some(file) = maybe_file {
@@ -176,6 +177,10 @@ pub enum ExecutionAction {
 	/// Contains the string to print on stdout, if any.
 	Instant(Option<String>),
 
+	/// Like `Instant`, also carrying a structured form of the output, for when machine-readable
+	/// output is asked for.
+	InstantStructured(String, serde_json::Value),
+
 	/// The client has started running and must be shut down manually by calling `shutdown`.
 	///
 	/// If you don't call `shutdown()`, execution will continue in the background.
@@ -222,6 +227,8 @@ fn execute<Cr, Rr>(command: Execute, on_client_rq: Cr, on_updater_rq: Rr) -> Res
 		},
 		Cmd::Version => Ok(ExecutionAction::Instant(Some(Args::print_version()))),
 		Cmd::Hash(maybe_file) => print_hash_of(maybe_file).map(|s| ExecutionAction::Instant(Some(s))),
+		Cmd::Account(account::AccountCmd::List(list_cmd)) => account::list(list_cmd)
+			.map(|accounts| ExecutionAction::InstantStructured(accounts.join("\n"), accounts.into())),
 		Cmd::Account(account_cmd) => account::execute(account_cmd).map(|s| ExecutionAction::Instant(Some(s))),
 		Cmd::ImportPresaleWallet(presale_cmd) => presale::execute(presale_cmd).map(|s| ExecutionAction::Instant(Some(s))),
 		Cmd::Blockchain(blockchain_cmd) => blockchain::execute(blockchain_cmd).map(|_| ExecutionAction::Instant(None)),
@@ -23,123 +23,170 @@ extern crate parity;
 extern crate ctrlc;
 extern crate dir;
 extern crate fdlimit;
+extern crate flate2;
+extern crate keccak_hash as hash;
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate panic_hook;
 extern crate parking_lot;
+extern crate rand;
+extern crate semver;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 
 #[cfg(windows)] extern crate winapi;
+#[cfg(feature = "zstd")] extern crate zstd;
+#[cfg(unix)] extern crate libc;
+
+#[cfg(test)]
+extern crate tempdir;
 
 use std::{process, env};
-use std::io::{self as stdio, Read, Write};
-use std::fs::{remove_file, metadata, File, create_dir_all};
-use std::path::PathBuf;
+use std::io::{self as stdio, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use ctrlc::CtrlC;
-use dir::default_hypervisor_path;
-use fdlimit::raise_fd_limit;
+use dir::default_data_path;
+use dir::helpers::replace_home;
+use launcher::{
+	ack_spec_name_override, apply_fd_limit, apply_pending_patch, describe_fallback_failure,
+	describe_spec_mismatch, effective_chain, ensure_updates_dir, forward_signals, init_sockets,
+	is_likely_crash, is_likely_service, is_supervised_crash, is_update_restart, log_launcher_to,
+	merge_sticky_args, node_ports, notify_deferred_update, plan_update, record_panics, reload_log_if_asked,
+	reload_log_on_hangup, remove_pid_file, render_instant_output, report_decision, run_as_service,
+	run_current, run_fallback_binary, run_latest, run_post_exit_cmd, set_launch_mode, set_quiet,
+	set_running_spec, set_service_stop_handler, set_spec_name_override, set_sticky_args, stop_signal,
+	supervised_exit_code, take_handoff, take_inherited_listeners, take_panic_marker, take_running_spec,
+	take_spec_name_override, take_sticky_args, take_update_apply_request, trial_slot, wait_ports_free,
+	wait_restart_jitter, write_effective_chain, write_pid_file, CONFIG_CHANGE_DEBOUNCE, ConfigWatch,
+	DeferReason, Error, ExitReason, ExitSignal, Housekeeping, LaunchDecision, LauncherConfig, LauncherStatus,
+	METRICS_PATH, PanicMarker, PanicTracker, Startup, StatusServer, Trial, UpdatePlan, Watchdog,
+};
 use parity::{start, ExecutionAction};
-use parking_lot::{Condvar, Mutex};
+use parking_lot::Mutex;
 
-fn updates_path(name: &str) -> PathBuf {
-	let mut dest = PathBuf::from(default_hypervisor_path());
-	dest.push(name);
-	dest
+fn println_trace_main(s: String) {
+	let to_stdout = launcher::traces_to_stdout(env::var("RUST_LOG").ok().as_ref().map(String::as_str));
+	launcher::write_trace_line(&s, to_stdout);
 }
 
-fn latest_exe_path() -> Option<PathBuf> {
-	File::open(updates_path("latest")).ok()
-		.and_then(|mut f| { let mut exe = String::new(); f.read_to_string(&mut exe).ok().map(|_| updates_path(&exe)) })
+#[macro_export]
+macro_rules! trace_main {
+	($arg:expr) => ($crate::println_trace_main($arg.into()));
+	($($arg:tt)*) => ($crate::println_trace_main(format!("{}", format_args!($($arg)*))));
 }
 
-fn set_spec_name_override(spec_name: String) {
-	if let Err(e) = create_dir_all(default_hypervisor_path())
-		.and_then(|_| File::create(updates_path("spec_name_override"))
-		.and_then(|mut f| f.write_all(spec_name.as_bytes())))
-	{
-		warn!("Couldn't override chain spec: {} at {:?}", e, updates_path("spec_name_override"));
-	}
+fn eprintln_warn_main(s: String) {
+	launcher::write_warn_line(&s);
 }
 
-fn take_spec_name_override() -> Option<String> {
-	let p = updates_path("spec_name_override");
-	let r = File::open(p.clone()).ok()
-		.and_then(|mut f| { let mut spec_name = String::new(); f.read_to_string(&mut spec_name).ok().map(|_| spec_name) });
-	let _ = remove_file(p);
-	r
+#[macro_export]
+macro_rules! warn_main {
+	($arg:expr) => ($crate::eprintln_warn_main($arg.into()));
+	($($arg:tt)*) => ($crate::eprintln_warn_main(format!("{}", format_args!($($arg)*))));
 }
 
-#[cfg(windows)]
-fn global_cleanup() {
-	// We need to cleanup all sockets before spawning another Parity process. This makes sure everything is cleaned up.
-	// The loop is required because of internal reference counter for winsock dll. We don't know how many crates we use do
-	// initialize it. There's at least 2 now.
-	for _ in 0.. 10 {
-		unsafe { ::winapi::um::winsock2::WSACleanup(); }
-	}
-}
+mod launcher;
 
-#[cfg(not(windows))]
-fn global_init() {}
+// Starts ~/.parity-updates/parity and returns the code it exits with.
+fn run_parity(launcher: &LauncherConfig) -> Result<i32, Error> {
+	let _sockets = init_sockets();
+	run_latest(launcher)
+}
 
-#[cfg(windows)]
-fn global_init() {
-	// When restarting in the same process this reinits windows sockets.
-	unsafe {
-		const WS_VERSION: u16 = 0x202;
-		let mut wsdata: ::winapi::um::winsock2::WSADATA = ::std::mem::zeroed();
-		::winapi::um::winsock2::WSAStartup(WS_VERSION, &mut wsdata);
+// Runs the current binary: as a child with `--supervise`, and in this process otherwise.
+fn run_current_binary(launcher: &LauncherConfig, exe: Option<&Path>) -> i32 {
+	if launcher.supervise {
+		match exe.and_then(|exe| run_current(launcher, exe)) {
+			Some(result) => return result.unwrap_or_else(|e| e.exit_code()),
+			None => warn_main!("Couldn't run the current binary as a child, running the node in this process instead."),
+		}
+		// the node's Ctrl-C handler replaces forwarding, which the next child needs back
+		let exit_code = main_direct(launcher, true);
+		forward_signals();
+		return exit_code;
 	}
+	main_direct(launcher, true)
 }
 
-#[cfg(not(windows))]
-fn global_cleanup() {}
-
-// Starts ~/.parity-updates/parity and returns the code it exits with.
-fn run_parity() -> Option<i32> {
-	global_init();
-	use ::std::ffi::OsString;
-	let prefix = vec![OsString::from("--can-restart"), OsString::from("--force-direct")];
-	let res = latest_exe_path().and_then(|exe| process::Command::new(exe)
-		.args(&(env::args_os().skip(1).chain(prefix.into_iter()).collect::<Vec<_>>()))
-		.status()
-		.map(|es| es.code().unwrap_or(128))
-		.ok()
-	);
-	global_cleanup();
-	res
+// Why the node exited, going by its exit code.
+fn exit_reason(launcher: &LauncherConfig, exit_code: i32) -> ExitReason {
+	if exit_code == launcher.restart_exit_code { ExitReason::Restart } else { ExitReason::Exit }
 }
 
-const PLEASE_RESTART_EXIT_CODE: i32 = 69;
+// How often the main thread wakes up for housekeeping while the node is running.
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
 
 // Run our version of parity.
 // Returns the exit error code.
-fn main_direct(force_can_restart: bool) -> i32 {
-	global_init();
-
-	let mut conf = {
-		let args = std::env::args().collect::<Vec<_>>();
-		parity::Configuration::parse_cli(&args).unwrap_or_else(|e| e.exit())
+fn main_direct(launcher: &LauncherConfig, force_can_restart: bool) -> i32 {
+	let _sockets = init_sockets();
+	// a node replacing one on a warm restart listens on the sockets that one handed over
+	parity::inherit_listeners(take_inherited_listeners());
+	let handoff = take_handoff();
+
+	let mode = set_launch_mode(force_can_restart);
+	trace_main!("Running the node in {} launch mode", mode.as_str());
+
+	// a chain switch may come with arguments of its own, taking precedence over the user's
+	let spec_override = take_spec_name_override(launcher);
+	let args = match spec_override {
+		Some(ref spec_override) if !spec_override.extra_args.is_empty() && !launcher.args.is_empty() => {
+			trace_main!("Switching to chain {} with {:?}", spec_override.chain, spec_override.extra_args);
+			let mut args = vec![launcher.args[0].clone()];
+			args.extend(merge_sticky_args(launcher.node_args(), &spec_override.extra_args));
+			args
+		},
+		_ => launcher.args.clone(),
 	};
-
-	if let Some(spec_override) = take_spec_name_override() {
+	// nothing is spawned, so the arguments and working directory are all there is to record
+	let cwd = env::current_dir().unwrap_or_default();
+	trace_main!("Running the node in this process with argv {:?} in {}", args, cwd.display());
+	let mut conf = parity::Configuration::parse_cli(&args).unwrap_or_else(|e| e.exit());
+
+	// a node started on a chain it switched to is within the switch cooldown from the start
+	let chain = effective_chain(spec_override.as_ref(), conf.args.flag_testnet, &conf.args.arg_chain);
+	let switching_chain = spec_override.is_some();
+	let mut last_switch = None;
+	if let Some(spec_override) = spec_override {
 		conf.args.flag_testnet = false;
-		conf.args.arg_chain = spec_override;
+		conf.args.arg_chain = spec_override.chain;
+		last_switch = Some(Instant::now());
 	}
 
-	let can_restart = force_can_restart || conf.args.flag_can_restart;
+	set_running_spec(launcher, &chain);
+	if let Some(ref path) = launcher.effective_chain_file {
+		write_effective_chain(path, &chain);
+	}
+
+	let can_restart = !launcher.no_indirect && (force_can_restart || conf.args.flag_can_restart);
+
+	// the config file as the node finds it, which the node is watched for if it's loaded at all
+	let config_watch = if launcher.restart_on_config_change && !conf.args.flag_no_config {
+		let path = replace_home(&default_data_path(), &conf.args.arg_config);
+		Some(ConfigWatch::new(path.into(), CONFIG_CHANGE_DEBOUNCE))
+	} else {
+		None
+	};
 
-	// increase max number of open files
-	raise_fd_limit();
+	// increase max number of open files, as configured with `--fd-limit`
+	apply_fd_limit(launcher.fd_limit);
 
-	let exit = Arc::new((Mutex::new((false, None)), Condvar::new()));
+	let exit = Arc::new(ExitSignal::with_switch_cooldown(launcher.spec_switch_cooldown, last_switch));
 
 	let exec = if can_restart {
 		let e1 = exit.clone();
 		let e2 = exit.clone();
 		start(conf,
-			move |new_chain: String| { *e1.0.lock() = (true, Some(new_chain)); e1.1.notify_all(); },
-			move || { *e2.0.lock() = (true, None); e2.1.notify_all(); })
+			move |new_chain: String| { e1.request_spec_switch(new_chain); },
+			move || e2.request_restart(None))
 	} else {
 		trace!(target: "mode", "Not hypervised: not setting exit handlers.");
 		start(conf, move |_| {}, move || {})
@@ -147,26 +194,104 @@ fn main_direct(force_can_restart: bool) -> i32 {
 
 	let res = match exec {
 		Ok(result) => match result {
-			ExecutionAction::Instant(Some(s)) => { println!("{}", s); 0 },
+			ExecutionAction::Instant(Some(s)) => { println!("{}", render_instant_output(&s, None, launcher.output_format)); 0 },
+			ExecutionAction::InstantStructured(s, structured) => {
+				println!("{}", render_instant_output(&s, Some(&structured), launcher.output_format));
+				0
+			},
 			ExecutionAction::Instant(None) => 0,
 			ExecutionAction::Running(client) => {
+				// only a node which got this far runs on the chain it switched to
+				if switching_chain {
+					ack_spec_name_override(launcher);
+				}
+
 				CtrlC::set_handler({
 					let e = exit.clone();
-					move || { e.1.notify_all(); }
+					move || e.interrupt()
+				});
+				// a service is stopped by the service control manager instead
+				set_service_stop_handler({
+					let e = exit.clone();
+					move || e.interrupt()
+				});
+
+				let watchdog = match launcher.watchdog_interval {
+					Some(interval) if can_restart => {
+						let e = exit.clone();
+						Some(Watchdog::start(client.liveness_probe(), interval, launcher.watchdog_misses, move || e.request_restart(None)))
+					},
+					Some(_) => {
+						warn_main!("Not hypervised: the watchdog can't restart the node and is disabled.");
+						None
+					},
+					None => None,
+				};
+
+				let mut housekeeping = Housekeeping::new(HOUSEKEEPING_INTERVAL);
+				housekeeping.register({
+					let started = Instant::now();
+					move || trace_main!("Node running for {}s", started.elapsed().as_secs())
 				});
+				match config_watch {
+					Some(mut watch) if can_restart => {
+						let e = exit.clone();
+						housekeeping.register(move || if watch.poll() {
+							warn_main!("Config file changed, restarting the node to apply it.");
+							e.request_restart(None);
+						});
+					},
+					Some(_) => warn_main!("Not hypervised: config file changes can't restart the node."),
+					None => {},
+				}
+				// the handler only sets a flag, the levels are reloaded on the next wakeup
+				if let Some(reloader) = client.log_reloader() {
+					reload_log_on_hangup();
+					let file = launcher.log_reload_file.clone();
+					housekeeping.register(move || reload_log_if_asked(&reloader, file.as_ref().map(PathBuf::as_path)));
+				}
 
 				// Wait for signal
-				let mut lock = exit.0.lock();
-				let _ = exit.1.wait(&mut lock);
+				let state = exit.wait(&mut housekeeping, launcher.exit_wait_timeout)
+					.unwrap_or_else(|state| {
+						warn_main!("No exit request within {}s, shutting down.", launcher.exit_wait_timeout.map_or(0, |t| t.as_secs()));
+						state
+					});
+
+				// the watchdog may be waiting for the lock, so only stop it once it's released
+				if let Some(watchdog) = watchdog {
+					watchdog.stop();
+				}
+
+				if state.0 {
+					if let Some(ref handoff) = handoff {
+						handoff.hand_over(&client.listeners());
+					}
+					let sticky_args = client.sticky_args();
+					if !sticky_args.is_empty() && launcher.readonly {
+						trace_main!("Not keeping arguments {:?} across the restart with --launcher-readonly", sticky_args);
+					} else if !sticky_args.is_empty() {
+						set_sticky_args(launcher, &sticky_args);
+					}
+				}
 
 				client.shutdown();
 
-				match &*lock {
-					&(true, ref spec_name_override) => {
-						if let &Some(ref spec_name) = spec_name_override {
-							set_spec_name_override(spec_name.clone());
+				match state {
+					(true, ref spec_name_override) => {
+						match *spec_name_override {
+							Some(ref spec_name) if launcher.readonly => warn_main!(
+								"Can't switch to chain {} across the restart with --launcher-readonly; restarting on the current chain.",
+								spec_name
+							),
+							Some(ref spec_name) => set_spec_name_override(launcher, spec_name.as_str().into()),
+							None => {},
+						}
+						if launcher.restart_grace > Duration::from_millis(0) {
+							trace_main!("Waiting {:?} for resources to be released before restarting", launcher.restart_grace);
+							thread::sleep(launcher.restart_grace);
 						}
-						PLEASE_RESTART_EXIT_CODE
+						launcher.restart_exit_code
 					},
 					_ => 0,
 				}
@@ -178,20 +303,15 @@ fn main_direct(force_can_restart: bool) -> i32 {
 		},
 	};
 
-	global_cleanup();
 	res
 }
 
-fn println_trace_main(s: String) {
-	if env::var("RUST_LOG").ok().and_then(|s| s.find("main=trace")).is_some() {
-		println!("{}", s);
+// Exits the launcher, removing its PID file first.
+fn quit(launcher: &LauncherConfig, exit_code: i32) -> ! {
+	if let Some(ref path) = launcher.launcher_pidfile {
+		remove_pid_file(path);
 	}
-}
-
-#[macro_export]
-macro_rules! trace_main {
-	($arg:expr) => (println_trace_main($arg.into()));
-	($($arg:tt)*) => (println_trace_main(format!("{}", format_args!($($arg)*))));
+	process::exit(exit_code)
 }
 
 fn main() {
@@ -199,48 +319,277 @@ fn main() {
 
 	// assuming the user is not running with `--force-direct`, then:
 	// if argv[0] == "parity" and this executable != ~/.parity-updates/parity, run that instead.
-	let force_direct = std::env::args().any(|arg| arg == "--force-direct");
-	let exe = std::env::current_exe().ok();
-	let development = exe.as_ref().and_then(|p| p.parent().and_then(|p| p.parent()).and_then(|p| p.file_name()).map(|n| n == "target")).unwrap_or(false);
-	let same_name = exe.as_ref().map(|p| p.file_stem().map_or(false, |s| s == "parity") && p.extension().map_or(true, |x| x == "exe")).unwrap_or(false);
-	trace_main!("Starting up {} (force-direct: {}, development: {}, same-name: {})", std::env::current_exe().map(|x| format!("{}", x.display())).unwrap_or("<unknown>".to_owned()), force_direct, development, same_name);
-	if !force_direct && !development && same_name {
+	let mut launcher = LauncherConfig::from_env().unwrap_or_else(|e| {
+		warn_main!("{}", e);
+		process::exit(1);
+	});
+	set_quiet(launcher.quiet);
+	if let Some(ref path) = launcher.log_launcher_to {
+		log_launcher_to(path);
+	}
+	if let Some(ref command) = launcher.command {
+		process::exit(launcher::execute(&launcher, command));
+	}
+	if let Some(ref path) = launcher.launcher_pidfile {
+		write_pid_file(path, process::id());
+	}
+	if launcher.no_indirect {
+		trace_main!("Running the node in this process, without updates or restarts");
+		launcher.disable_updates();
+		let exit_code = main_direct(&launcher, false);
+		quit(&launcher, exit_code);
+	}
+	if let Some(e) = launcher.updates_dir_error.clone() {
+		warn_main!("Couldn't determine the updates directory ({}); updates and chain overrides are disabled.", e);
+		launcher.disable_updates();
+	} else if launcher.readonly {
+		trace_main!("Read-only launcher, not writing to {}", launcher.updates_dir.display());
+	} else if let Err(e) = ensure_updates_dir(&launcher.updates_dir) {
+		warn_main!("Updates directory {} is unusable ({}); updates and chain overrides are disabled.", launcher.updates_dir.display(), e);
+		launcher.disable_updates();
+	}
+	record_panics(&launcher);
+
+	if launcher.windows_service || is_likely_service() {
+		let service_launcher = launcher.clone();
+		let service_code = run_as_service(move || {
+			trace_main!("Running as a service");
+			let exit_code = main_direct(&service_launcher, false);
+			run_post_exit_cmd(&service_launcher, exit_reason(&service_launcher, exit_code), exit_code);
+			exit_code
+		});
+		match service_code {
+			Some(exit_code) => quit(&launcher, exit_code),
+			None if launcher.windows_service => warn_main!("Not started by the service control manager, running interactively."),
+			None => {},
+		}
+	}
+
+	if launcher.launch_id.is_none() {
+		launcher.renew_launch_id();
+	}
+	let startup = Startup::detect(&launcher);
+	trace_main!("Starting up {} (launch {}), updates in {}",
+		startup,
+		launcher.launch_id.as_ref().expect("set above; qed"),
+		launcher.updates_dir.display());
+	let exe = startup.exe.clone();
+	let status = Arc::new(Mutex::new(LauncherStatus {
+		current_exe: exe.as_ref().map(|exe| exe.display().to_string()),
+		..Default::default()
+	}));
+	let status_server = launcher.launcher_status_addr.as_ref().and_then(|addr| {
+		match StatusServer::start(addr, &node_ports(launcher.node_args()), status.clone()) {
+			Ok(server) => {
+				trace_main!("Serving the launcher status on http://{0}, and as metrics on http://{0}{1}", server.addr(), METRICS_PATH);
+				Some(server)
+			},
+			Err(e) => {
+				warn_main!("Couldn't serve the launcher status on {}: {}", addr, e);
+				None
+			},
+		}
+	});
+	let exit_code = if startup.should_hypervise() || launcher.use_binary.is_some() || launcher.supervise {
 		// looks like we're not running ~/.parity-updates/parity when the user is expecting otherwise.
 		// Everything run inside a loop, so we'll be able to restart from the child into a new version seamlessly.
+		let started = Instant::now();
+		let mut panics = PanicTracker::default();
+		let mut restarting = false;
+		let mut previous_exe: Option<PathBuf> = None;
+		if launcher.supervise {
+			trace_main!("Supervising the node, forwarding termination signals to it");
+			forward_signals();
+		}
 		loop {
+			if let Some(max_lifetime) = launcher.max_lifetime {
+				if started.elapsed() >= max_lifetime {
+					trace_main!("Maximum lifetime of {}s reached, not restarting", max_lifetime.as_secs());
+					break 0;
+				}
+			}
+
+			let sticky_args = take_sticky_args(&launcher);
+			if !sticky_args.is_empty() {
+				trace_main!("Keeping arguments across restart: {:?}", sticky_args);
+				launcher.keep_sticky_args(&sticky_args);
+			}
+
+			apply_pending_patch(&launcher);
+
 			// If we fail to run the updated parity then fallback to local version.
-			let latest_exe = latest_exe_path();
-			let have_update = latest_exe.as_ref().map_or(false, |p| p.exists());
-			let is_non_updated_current = exe.as_ref().map_or(false, |exe| latest_exe.as_ref().map_or(false, |lexe| exe.canonicalize().ok() != lexe.canonicalize().ok()));
-			let update_is_newer = match (
-				latest_exe.as_ref()
-					.and_then(|p| metadata(p.as_path()).ok())
-					.and_then(|m| m.modified().ok()),
-				exe.as_ref()
-					.and_then(|p| metadata(p.as_path()).ok())
-					.and_then(|m| m.modified().ok())
-			) {
-				(Some(latest_exe_time), Some(this_exe_time)) if latest_exe_time > this_exe_time => true,
-				_ => false,
-			};
-			trace_main!("Starting... (have-update: {}, non-updated-current: {}, update-is-newer: {})", have_update, is_non_updated_current, update_is_newer);
-			let exit_code = if have_update && is_non_updated_current && update_is_newer {
-				trace_main!("Attempting to run latest update ({})...", latest_exe.as_ref().expect("guarded by have_update; latest_exe must exist for have_update; qed").display());
-				run_parity().unwrap_or_else(|| { trace_main!("Falling back to local..."); main_direct(true) })
+			let plan = plan_update(&launcher, exe.as_ref().map(PathBuf::as_path), || take_update_apply_request(&launcher));
+			let update_state = plan.state();
+			let UpdatePlan {
+				latest, have_update, current_is_not_latest: is_non_updated_current, update_is_newer, update_is_allowed, staged,
+				current, staged_channel, requested_spec, chain, deferred, settle_remaining, run_update,
+			} = plan;
+			let latest_exe = latest.as_ref().ok();
+			trace_main!("Starting... ({}; have-update: {}, non-updated-current: {}, update-is-newer: {}, update-is-allowed: {})", update_state, have_update, is_non_updated_current, update_is_newer, update_is_allowed);
+			match deferred {
+				Some(DeferReason::Downgrade) => warn_main!(
+					"Not running update {} older than the current version {}; use --allow-downgrade to run it anyway.",
+					staged.as_ref().expect("only a known version is a downgrade; qed"),
+					current.as_ref().expect("only a known version is a downgrade; qed"),
+				),
+				Some(DeferReason::VersionCeiling) => warn_main!(
+					"Not running update {} above the maximum allowed version {}; staying on the current binary.",
+					staged.as_ref().map_or("of unknown version".to_owned(), |v| v.to_string()),
+					launcher.max_update_version.as_ref().expect("update_is_allowed is only false with a ceiling; qed"),
+				),
+				Some(DeferReason::ChannelMismatch) => warn_main!(
+					"Not running update {} built for channel {} instead of {}; staying on the current binary.",
+					latest_exe.expect("only a runnable update is held back; qed").display(),
+					staged_channel.as_ref().expect("only an update of known channel mismatches; qed"),
+					launcher.expected_channel.as_ref().expect("only an expected channel can be mismatched; qed"),
+				),
+				Some(DeferReason::ChainPinned) => trace_main!("Chain {} is pinned to the current binary, not running the update", chain),
+				Some(DeferReason::CheckOnly) => warn_main!(
+					"Update {} is staged and ready; use --apply-update-now and restart the node to switch to it.",
+					latest_exe.expect("only a runnable update is held back; qed").display()
+				),
+				Some(DeferReason::Unsettled) => {
+					// the update is likely still being written, so it's looked at again once it should be complete
+					let remaining = settle_remaining.expect("only an unsettled update has time left to settle; qed");
+					trace_main!(
+						"Update {} was modified less than {}s ago, waiting for the file to settle",
+						latest_exe.expect("only a runnable update is held back; qed").display(),
+						launcher.update_settle_time.as_secs(),
+					);
+					thread::sleep(remaining);
+					continue;
+				},
+				None => {},
+			}
+			if let Some(reason) = deferred {
+				notify_deferred_update(&launcher, staged.as_ref(), reason);
+			}
+			report_decision(&launcher, &LaunchDecision {
+				launch_id: launcher.launch_id.clone(),
+				latest: latest_exe.map(|p| p.display().to_string()),
+				state: update_state,
+				have_update,
+				current_is_not_latest: is_non_updated_current,
+				update_is_newer,
+				update_is_allowed,
+				run_update,
+			});
+			if restarting && launcher.restart_port_timeout > Duration::from_secs(0) {
+				let busy = wait_ports_free(&node_ports(launcher.node_args()), launcher.restart_port_timeout);
+				if !busy.is_empty() {
+					warn_main!("Ports {:?} are still in use after {}s, starting the node anyway.", busy, launcher.restart_port_timeout.as_secs());
+				}
+			}
+			let next_exe = if run_update { latest_exe.cloned() } else { None };
+			if is_update_restart(restarting, previous_exe.as_ref().map(PathBuf::as_path), next_exe.as_ref().map(PathBuf::as_path), panics.last().is_some()) {
+				wait_restart_jitter(launcher.restart_jitter);
+			}
+			previous_exe = next_exe;
+			{
+				let mut status = status.lock();
+				let target = if run_update { latest_exe } else { exe.as_ref() };
+				status.target = target.map(|exe| exe.display().to_string());
+				status.update_pending = have_update && is_non_updated_current && update_is_newer && !run_update;
+			}
+			let run_started = Instant::now();
+			let exit_code = if run_update {
+				trace_main!("Attempting to run latest update ({})...", latest_exe.expect("guarded by have_update; latest_exe must exist for have_update; qed").display());
+				let trial = trial_slot(&launcher).map(|slot| Trial::start(&launcher, slot));
+				let result = run_parity(&launcher);
+				if let Some(trial) = trial {
+					trial.finish(result.as_ref().map_or(true, |&code| code != 0 && code != launcher.restart_exit_code));
+				}
+				match result {
+					Ok(code) => code,
+					Err(ref e) if e.should_fall_back() => {
+						match run_fallback_binary(&launcher) {
+							Some((fallback, result)) => {
+								trace_main!("Ran fallback binary {} after the latest update failed ({})", fallback.display(), e);
+								result.unwrap_or_else(|e| e.exit_code())
+							},
+							None => {
+								trace_main!("Falling back to local ({})...", e);
+								let exit_code = run_current_binary(&launcher, exe.as_ref().map(PathBuf::as_path));
+								if let Some(summary) = describe_fallback_failure(e, exit_code, launcher.restart_exit_code) {
+									warn_main!("{}", summary);
+								}
+								exit_code
+							},
+						}
+					},
+					Err(e) => {
+						if e.is_likely_oom() {
+							warn_main!("Latest update was killed, most likely for running out of memory.");
+						}
+						trace_main!("Not falling back to local ({})", e);
+						e.exit_code()
+					},
+				}
 			} else {
-				trace_main!("No latest update. Attempting to direct...");
-				main_direct(true)
+				match latest {
+					Err(Error::NotConfigured) => trace_main!("No latest update in {}. Attempting to direct...", launcher.updates_dir.display()),
+					Err(ref e) => trace_main!("No usable latest update ({}). Attempting to direct...", e),
+					Ok(_) => trace_main!("Not running the latest update. Attempting to direct..."),
+				}
+				run_current_binary(&launcher, exe.as_ref().map(PathBuf::as_path))
 			};
 			trace_main!("Latest exited with {}", exit_code);
-			if exit_code != PLEASE_RESTART_EXIT_CODE {
+			let running_spec = take_running_spec(&launcher);
+			let spec_mismatch = requested_spec
+				.and_then(|spec| describe_spec_mismatch(&spec, running_spec.as_ref().map(String::as_str)));
+			if let Some(mismatch) = spec_mismatch {
+				warn_main!("{}", mismatch);
+			}
+			let ran_for = run_started.elapsed();
+			let stop = if launcher.supervise { stop_signal() } else { None };
+			let crashed = is_likely_crash(exit_code, ran_for, launcher.crash_grace) || is_supervised_crash(&launcher, exit_code, stop);
+			let crash = take_panic_marker(&launcher).or_else(|| if crashed {
+				Some(PanicMarker::quick_exit(exit_code, ran_for))
+			} else {
+				None
+			});
+			let identical_panics = panics.record(crash);
+			if let Some(panic) = panics.last() {
+				warn_main!("The node crashed ({} time(s) in a row): {}", identical_panics, panic.report);
+			}
+			let exit_code = if launcher.supervise {
+				supervised_exit_code(&launcher, exit_code, panics.last().is_some(), stop)
+			} else {
+				exit_code
+			};
+			let exit_code = if exit_code == launcher.restart_exit_code && identical_panics >= launcher.max_identical_panics {
+				warn_main!("The node crashed the same way {} times in a row, not relaunching it.", identical_panics);
+				1
+			} else {
+				exit_code
+			};
+			{
+				let mut status = status.lock();
+				status.target = None;
+				status.last_exit_code = Some(exit_code);
+			}
+			run_post_exit_cmd(&launcher, exit_reason(&launcher, exit_code), exit_code);
+			if exit_code != launcher.restart_exit_code {
 				trace_main!("Quitting...");
-				process::exit(exit_code);
+				break exit_code;
 			}
-			trace_main!("Rerunning...");
+			restarting = true;
+			status.lock().restarts += 1;
+			let previous_launch_id = launcher.renew_launch_id();
+			trace_main!("Rerunning as launch {} after {}...",
+				launcher.launch_id.as_ref().expect("just renewed; qed"),
+				previous_launch_id.as_ref().expect("set before the loop; qed"));
 		}
 	} else {
 		trace_main!("Running direct");
+		status.lock().target = exe.as_ref().map(|exe| exe.display().to_string());
 		// Otherwise, we're presumably running the version we want. Just run and fall-through.
-		process::exit(main_direct(false));
+		let exit_code = main_direct(&launcher, false);
+		run_post_exit_cmd(&launcher, exit_reason(&launcher, exit_code), exit_code);
+		exit_code
+	};
+	if let Some(server) = status_server {
+		server.stop();
 	}
+	quit(&launcher, exit_code);
 }
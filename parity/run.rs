@@ -16,6 +16,7 @@
 
 use std::any::Any;
 use std::fmt;
+use std::net::{SocketAddr, TcpListener};
 use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant};
 use std::thread;
@@ -28,7 +29,7 @@ use ethcore::miner::{stratum, Miner, MinerService, MinerOptions};
 use ethcore::snapshot;
 use ethcore::spec::{SpecParams, OptimizeFor};
 use ethcore::verification::queue::VerifierSettings;
-use ethcore_logger::{Config as LogConfig, RotatingLogger};
+use ethcore_logger::{self, Config as LogConfig, LogReloader, RotatingLogger};
 use ethcore_service::ClientService;
 use sync::{self, SyncConfig};
 use miner::work_notify::WorkPoster;
@@ -75,6 +76,9 @@ const SNAPSHOT_HISTORY: u64 = 100;
 const GAS_CORPUS_EXPIRATION_MINUTES: u64 = 60 * 6;
 
 // Pops along with error messages when a password is missing or invalid.
+// Name the p2p listener is handed over by on a warm restart.
+const P2P_LISTENER: &'static str = "p2p";
+
 const VERIFY_PASSWORD_HINT: &'static str = "Make sure valid password is present in files passed using `--password` or in the configuration file.";
 
 #[derive(Debug, PartialEq)]
@@ -395,6 +399,7 @@ fn execute_light_impl(cmd: RunCmd, logger: Arc<RotatingLogger>) -> Result<Runnin
 			rpc: rpc_direct,
 			informant,
 			client,
+			net: Arc::downgrade(&(light_sync as Arc<sync::ManageNetwork>)),
 			keep_alive: Box::new((event_loop, service, ws_server, http_server, ipc_server, ui_server)),
 		}
 	})
@@ -886,6 +891,7 @@ fn execute_impl<Cr, Rr>(cmd: RunCmd, logger: Arc<RotatingLogger>, on_client_rq:
 			informant,
 			client,
 			client_service: Arc::new(service),
+			net: Arc::downgrade(&manage_network),
 			keep_alive: Box::new((watcher, updater, ws_server, http_server, ipc_server, ui_server, secretstore_key_server, ipfs_server, event_loop)),
 		}
 	})
@@ -904,6 +910,7 @@ enum RunningClientInner {
 		rpc: jsonrpc_core::MetaIoHandler<Metadata, informant::Middleware<rpc_apis::LightClientNotifier>>,
 		informant: Arc<Informant<LightNodeInformantData>>,
 		client: Arc<LightClient>,
+		net: Weak<sync::ManageNetwork>,
 		keep_alive: Box<Any>,
 	},
 	Full {
@@ -911,6 +918,7 @@ enum RunningClientInner {
 		informant: Arc<Informant<FullNodeInformantData>>,
 		client: Arc<Client>,
 		client_service: Arc<ClientService>,
+		net: Weak<sync::ManageNetwork>,
 		keep_alive: Box<Any>,
 	},
 }
@@ -934,10 +942,60 @@ impl RunningClient {
 		}
 	}
 
+	/// Returns a probe telling whether the client is still responsive.
+	pub fn liveness_probe(&self) -> LivenessProbe {
+		let inner = match self.inner {
+			RunningClientInner::Light { ref client, .. } => LivenessProbeInner::Light(Arc::downgrade(client)),
+			RunningClientInner::Full { ref client, .. } => LivenessProbeInner::Full(Arc::downgrade(client)),
+		};
+
+		LivenessProbe { inner }
+	}
+
+	/// Returns the handle changing the log levels while the client runs, which can be moved to another thread.
+	///
+	/// There is none if logging wasn't set up by the client.
+	pub fn log_reloader(&self) -> Option<LogReloader> {
+		ethcore_logger::log_reloader()
+	}
+
+	/// Returns whether the client still answers a trivial query.
+	pub fn is_responsive(&self) -> bool {
+		self.liveness_probe().is_responsive()
+	}
+
+	/// Arguments the client wants to keep when it's restarted, as `--flag=value`.
+	///
+	/// These reflect settings changed while running and override the ones given at startup.
+	pub fn sticky_args(&self) -> Vec<String> {
+		self.network().map_or_else(Vec::new, |net| {
+			let configured = net.network_config().listen_address;
+			listen_port_args(configured.as_ref().map(String::as_str), net.local_url().as_ref().map(String::as_str))
+		})
+	}
+
+	/// Duplicates of the sockets the client listens on, named after what they're for, to be handed
+	/// over to the process replacing it on a warm restart.
+	///
+	/// Only the p2p listener is taken over so far; the RPC servers still bind anew.
+	pub fn listeners(&self) -> Vec<(String, TcpListener)> {
+		self.network()
+			.and_then(|net| net.listener())
+			.map(|listener| (P2P_LISTENER.to_owned(), listener))
+			.into_iter()
+			.collect()
+	}
+
+	fn network(&self) -> Option<Arc<sync::ManageNetwork>> {
+		match self.inner {
+			RunningClientInner::Light { ref net, .. } | RunningClientInner::Full { ref net, .. } => net.upgrade(),
+		}
+	}
+
 	/// Shuts down the client.
 	pub fn shutdown(self) {
 		match self.inner {
-			RunningClientInner::Light { rpc, informant, client, keep_alive } => {
+			RunningClientInner::Light { rpc, informant, client, keep_alive, .. } => {
 				// Create a weak reference to the client so that we can wait on shutdown
 				// until it is dropped
 				let weak_client = Arc::downgrade(&client);
@@ -948,7 +1006,7 @@ impl RunningClient {
 				drop(client);
 				wait_for_drop(weak_client);
 			},
-			RunningClientInner::Full { rpc, informant, client, client_service, keep_alive } => {
+			RunningClientInner::Full { rpc, informant, client, client_service, keep_alive, .. } => {
 				info!("Finishing work, please wait...");
 				// Create a weak reference to the client so that we can wait on shutdown
 				// until it is dropped
@@ -970,6 +1028,30 @@ impl RunningClient {
 	}
 }
 
+/// Cheap liveness check of a running client, which can be moved to another thread.
+///
+/// Only holds a weak reference, so it doesn't delay the client shutdown.
+#[derive(Clone)]
+pub struct LivenessProbe {
+	inner: LivenessProbeInner,
+}
+
+#[derive(Clone)]
+enum LivenessProbeInner {
+	Light(Weak<LightClient>),
+	Full(Weak<Client>),
+}
+
+impl LivenessProbe {
+	/// Returns whether the client answers a trivial query. Always `false` after shutdown.
+	pub fn is_responsive(&self) -> bool {
+		match self.inner {
+			LivenessProbeInner::Light(ref client) => client.upgrade().map(|client| client.chain_info()).is_some(),
+			LivenessProbeInner::Full(ref client) => client.upgrade().map(|client| client.chain_info()).is_some(),
+		}
+	}
+}
+
 /// Executes the given run command.
 ///
 /// `on_client_rq` is the action to perform when the client receives an RPC request to be restarted
@@ -1088,6 +1170,33 @@ fn build_create_account_hint(spec: &SpecType, keys: &str) -> String {
 	format!("You can create an account via RPC, UI or `parity account new --chain {} --keys-path {}`.", spec, keys)
 }
 
+/// Has the client started next take over the `listeners` handed down from the process it replaces.
+pub fn inherit_listeners(listeners: Vec<(String, TcpListener)>) {
+	for (name, listener) in listeners {
+		match name.as_str() {
+			P2P_LISTENER => sync::inherit_listener(listener),
+			_ => warn!("Not taking over the unknown {} listener", name),
+		}
+	}
+}
+
+/// `--port` keeping the port the network listens on across a restart, if it isn't the configured one.
+///
+/// Asked to listen on port 0, the network takes any free port, which would change with every restart.
+fn listen_port_args(configured: Option<&str>, local_url: Option<&str>) -> Vec<String> {
+	let configured = configured.and_then(|addr| addr.parse::<SocketAddr>().ok()).map(|addr| addr.port());
+	// `enode://<id>@<address>`, followed by `+<udp port>` if it's a different one
+	let listening = local_url
+		.and_then(|url| url.rsplit('@').next())
+		.and_then(|endpoint| endpoint.split('+').next())
+		.and_then(|addr| addr.parse::<SocketAddr>().ok())
+		.map(|addr| addr.port());
+	match listening {
+		Some(port) if port != 0 && Some(port) != configured => vec![format!("--port={}", port)],
+		_ => Vec::new(),
+	}
+}
+
 fn wait_for_drop<T>(w: Weak<T>) {
 	let sleep_duration = Duration::from_secs(1);
 	let warn_timeout = Duration::from_secs(60);
@@ -1111,3 +1220,26 @@ fn wait_for_drop<T>(w: Weak<T>) {
 
 	warn!("Shutdown timeout reached, exiting uncleanly.");
 }
+
+#[cfg(test)]
+mod tests {
+	use super::listen_port_args;
+
+	const NODE_ID: &str = "a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c";
+
+	#[test]
+	fn should_keep_port_taken_instead_of_any_free_one() {
+		let url = format!("enode://{}@0.0.0.0:30310", NODE_ID);
+		assert_eq!(listen_port_args(Some("0.0.0.0:0"), Some(&url)), vec!["--port=30310".to_owned()]);
+		let url = format!("enode://{}@[::1]:30311+30399", NODE_ID);
+		assert_eq!(listen_port_args(Some("[::]:0"), Some(&url)), vec!["--port=30311".to_owned()]);
+	}
+
+	#[test]
+	fn should_not_keep_configured_or_unknown_port() {
+		let url = format!("enode://{}@0.0.0.0:30303", NODE_ID);
+		assert!(listen_port_args(Some("0.0.0.0:30303"), Some(&url)).is_empty());
+		assert!(listen_port_args(Some("0.0.0.0:0"), None).is_empty());
+		assert!(listen_port_args(Some("0.0.0.0:0"), Some("not a node url")).is_empty());
+	}
+}
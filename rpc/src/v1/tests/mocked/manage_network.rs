@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::net::TcpListener;
 use sync::{ManageNetwork, NetworkConfiguration};
 use self::ethcore_network::{ProtocolId, NetworkContext};
 
@@ -30,5 +31,7 @@ impl ManageNetwork for TestManageNetwork {
 	fn start_network(&self) {}
 	fn stop_network(&self) {}
 	fn network_config(&self) -> NetworkConfiguration { NetworkConfiguration::new_local() }
+	fn local_url(&self) -> Option<String> { None }
+	fn listener(&self) -> Option<TcpListener> { None }
 	fn with_proto_context(&self, _: ProtocolId, _: &mut FnMut(&NetworkContext)) { }
 }
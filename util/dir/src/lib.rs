@@ -233,8 +233,13 @@ pub fn default_local_path() -> String {
 
 /// Default hypervisor path
 pub fn default_hypervisor_path() -> PathBuf {
+	try_default_hypervisor_path().unwrap_or_else(|_| "$HOME/.parity-hypervisor".into())
+}
+
+/// Default hypervisor path, or why it couldn't be determined
+pub fn try_default_hypervisor_path() -> Result<PathBuf, String> {
 	let app_info = AppInfo { name: PRODUCT_HYPERVISOR, author: AUTHOR };
-	get_app_root(AppDataType::UserData, &app_info).unwrap_or_else(|_| "$HOME/.parity-hypervisor".into())
+	get_app_root(AppDataType::UserData, &app_info).map_err(|e| e.to_string())
 }
 
 /// Get home directory.
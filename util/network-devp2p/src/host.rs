@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr};
+use std::net::{self, SocketAddr, SocketAddrV4, Ipv4Addr};
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -54,6 +54,10 @@ const MAX_HANDSHAKES: usize = 1024;
 
 const DEFAULT_PORT: u16 = 30303;
 
+// Descriptor of the listener handed down from the process this one replaces, -1 once taken.
+#[cfg(unix)]
+static INHERITED_LISTENER: ::std::sync::atomic::AtomicIsize = ::std::sync::atomic::AtomicIsize::new(-1);
+
 // StreamToken/TimerToken
 const TCP_ACCEPT: StreamToken = SYS_TIMER + 1;
 const IDLE: TimerToken = SYS_TIMER + 2;
@@ -254,6 +258,53 @@ pub struct Host {
 	filter: Option<Arc<ConnectionFilter>>,
 }
 
+/// Has the next host created listen with `listener` instead of binding anew, if it's bound to the
+/// configured address.
+///
+/// A restarted node takes over the listener of the process it replaces that way, so incoming
+/// connections aren't refused in between.
+#[cfg(unix)]
+pub fn inherit_listener(listener: net::TcpListener) {
+	use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+	let previous = INHERITED_LISTENER.swap(listener.into_raw_fd() as isize, AtomicOrdering::SeqCst);
+	if previous >= 0 {
+		drop(unsafe { net::TcpListener::from_raw_fd(previous as i32) });
+	}
+}
+
+#[cfg(not(unix))]
+pub fn inherit_listener(_listener: net::TcpListener) {}
+
+/// Takes the inherited listener, unless it's bound elsewhere than `address`.
+#[cfg(unix)]
+fn take_inherited_listener(address: &SocketAddr) -> Option<net::TcpListener> {
+	use std::os::unix::io::FromRawFd;
+
+	let fd = INHERITED_LISTENER.swap(-1, AtomicOrdering::SeqCst);
+	if fd < 0 {
+		return None;
+	}
+	let listener = unsafe { net::TcpListener::from_raw_fd(fd as i32) };
+	match listener.local_addr() {
+		Ok(bound) => if bound.ip() == address.ip() && (address.port() == 0 || bound.port() == address.port()) {
+			Some(listener)
+		} else {
+			debug!(target: "network", "Not taking over the listener inherited at {:?}, listening at {:?} instead", bound, address);
+			None
+		},
+		Err(e) => {
+			debug!(target: "network", "Not taking over the inherited listener: {:?}", e);
+			None
+		},
+	}
+}
+
+#[cfg(not(unix))]
+fn take_inherited_listener(_address: &SocketAddr) -> Option<net::TcpListener> {
+	None
+}
+
 impl Host {
 	/// Create a new instance
 	pub fn new(mut config: NetworkConfiguration, filter: Option<Arc<ConnectionFilter>>) -> Result<Host, Error> {
@@ -277,7 +328,13 @@ impl Host {
 		};
 		let path = config.net_config_path.clone();
 		// Setup the server socket
-		let tcp_listener = TcpListener::bind(&listen_address)?;
+		let tcp_listener = match take_inherited_listener(&listen_address) {
+			Some(listener) => {
+				debug!(target: "network", "Taking over the inherited listener");
+				TcpListener::from_std(listener)?
+			},
+			None => TcpListener::bind(&listen_address)?,
+		};
 		listen_address = SocketAddr::new(listen_address.ip(), tcp_listener.local_addr()?.port());
 		debug!(target: "network", "Listening at {:?}", listen_address);
 		let udp_port = config.udp_port.unwrap_or(listen_address.port());
@@ -321,6 +378,20 @@ impl Host {
 		Ok(host)
 	}
 
+	/// Duplicate of the listener accepting incoming connections, which outlives the host.
+	#[cfg(unix)]
+	pub fn listener(&self) -> io::Result<net::TcpListener> {
+		use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+		let listener = self.tcp_listener.lock().try_clone()?;
+		Ok(unsafe { net::TcpListener::from_raw_fd(listener.into_raw_fd()) })
+	}
+
+	#[cfg(not(unix))]
+	pub fn listener(&self) -> io::Result<net::TcpListener> {
+		Err(io::Error::new(io::ErrorKind::Other, "listeners can only be duplicated on Unix"))
+	}
+
 	pub fn add_node(&mut self, id: &str) {
 		match Node::from_str(id) {
 			Err(e) => { debug!(target: "network", "Could not add node {}: {:?}", id, e); },
@@ -108,7 +108,7 @@ mod node_table;
 mod ip_utils;
 
 pub use service::NetworkService;
-pub use host::NetworkContext;
+pub use host::{inherit_listener, NetworkContext};
 
 pub use io::TimerToken;
 pub use node_table::{validate_node_url, NodeId};
@@ -19,6 +19,7 @@ use network::{NetworkContext, PeerId, ProtocolId, NetworkIoMessage};
 use host::Host;
 use io::*;
 use parking_lot::RwLock;
+use std::net;
 use std::sync::Arc;
 use ansi_term::Colour;
 use network::ConnectionFilter;
@@ -109,6 +110,12 @@ impl NetworkService {
 		host.as_ref().map(|h| h.local_url())
 	}
 
+	/// Returns a duplicate of the listener accepting incoming connections, if the network is running.
+	pub fn listener(&self) -> Option<net::TcpListener> {
+		let host = self.host.read();
+		host.as_ref().and_then(|h| h.listener().ok())
+	}
+
 	/// Start network IO
 	pub fn start(&self) -> Result<(), Error> {
 		let mut host = self.host.write();